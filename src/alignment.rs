@@ -0,0 +1,119 @@
+//! Python view of an alignment
+
+use pyo3::class::basic::PyObjectProtocol;
+use pyo3::prelude::*;
+
+use std::string::FromUtf8Error;
+
+use crate::needle;
+
+/// Result of aligning two sequences.
+///
+/// Coordinates of the common region are 0-based and the ends are exclusive.
+#[pyclass(name = "Alignment")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PyAlignment {
+    /// Score of the optimal alignment.
+    #[pyo3(get)]
+    score: i32,
+    /// Aligned target.
+    #[pyo3(get)]
+    target: String,
+    /// Aligned query, reverse complemented if `reversed`.
+    #[pyo3(get)]
+    query: String,
+    /// Whether the reverse complement of the query was aligned.
+    #[pyo3(get)]
+    reversed: bool,
+    /// Fraction of identical pairs in the common region.
+    #[pyo3(get)]
+    identity: f64,
+    /// Fraction of the shorter sequence inside the common region.
+    #[pyo3(get)]
+    coverage: f64,
+    /// Number of opened gaps, including end gaps.
+    #[pyo3(get)]
+    gap_opens: usize,
+    /// Number of gap extensions, including end gaps.
+    #[pyo3(get)]
+    gap_extensions: usize,
+    /// Start of the common region in the target.
+    #[pyo3(get)]
+    target_start: Option<usize>,
+    /// End of the common region in the target.
+    #[pyo3(get)]
+    target_end: Option<usize>,
+    /// Start of the common region in the query.
+    #[pyo3(get)]
+    query_start: Option<usize>,
+    /// End of the common region in the query.
+    #[pyo3(get)]
+    query_end: Option<usize>,
+    /// CIGAR string with the operations `=`, `X`, `I` and `D`, with the target as the reference,
+    /// starting at `target_start` if the mode leaves the ends of the target unaligned,
+    /// and with the unaligned ends of the query soft clipped by `S`.
+    #[pyo3(get)]
+    cigar: String,
+    /// Runs of edit operations as tuples (operation, target start, query start, length),
+    /// with the operations of the CIGAR string but `S` and 0-based positions.
+    #[pyo3(get)]
+    edits: Vec<(char, usize, usize, usize)>,
+    shown: String,
+}
+
+impl PyAlignment {
+    /// Collects the properties of `alignment`, made with the query in the `orientation`.
+    /// # Errors
+    /// Returns [Err] if invalid UTF-8 has been constructed
+    pub fn new(
+        alignment: &needle::Alignment,
+        orientation: needle::Orientation,
+    ) -> Result<Self, FromUtf8Error> {
+        let (target, query) = alignment.as_strings()?;
+        let (gap_opens, gap_extensions) = alignment.gap_counts();
+        let region = alignment.common_region();
+        Ok(PyAlignment {
+            score: alignment.score(),
+            target,
+            query,
+            reversed: orientation == needle::Orientation::Reverse,
+            identity: alignment.identity(),
+            coverage: alignment.coverage(),
+            gap_opens,
+            gap_extensions,
+            target_start: region.map(|([target_start, _], _)| target_start),
+            target_end: region.map(|(_, [target_end, _])| target_end),
+            query_start: region.map(|([_, query_start], _)| query_start),
+            query_end: region.map(|(_, [_, query_end])| query_end),
+            cigar: alignment.cigar(),
+            edits: alignment
+                .edits()
+                .iter()
+                .map(|edit| {
+                    (
+                        edit.kind.cigar_op(),
+                        edit.target_start,
+                        edit.query_start,
+                        edit.len,
+                    )
+                })
+                .collect(),
+            shown: alignment.show_alignment()?,
+        })
+    }
+}
+
+#[pyproto]
+impl PyObjectProtocol for PyAlignment {
+    /// Shows the alignment in the same way as Biopython.
+    fn __str__(&self) -> String {
+        self.shown.clone()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Alignment(score={}, target={:?}, query={:?})",
+            self.score, self.target, self.query
+        )
+    }
+}
@@ -0,0 +1,307 @@
+//! Bootstrapping distances between aligned sequences over the alignment columns
+
+use rayon::prelude::*;
+use std::fmt;
+
+use crate::distance::{make_distance_array_resampled, with_deletion, Distance, DistanceOptions};
+
+/// Error returned when the aligned sequences have different numbers of columns.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct UnequalLengths {
+    /// Number of columns of the first sequence.
+    pub expected: usize,
+    /// Number of columns of the first sequence that differs.
+    pub found: usize,
+}
+
+impl fmt::Display for UnequalLengths {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "aligned sequences should have {} columns, found {}",
+            self.expected, self.found
+        )
+    }
+}
+
+impl std::error::Error for UnequalLengths {}
+
+/// SplitMix64 pseudo-random generator.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    /// Generator of the `replicate`, independent of the order the replicates are computed in.
+    fn new(seed: u64, replicate: usize) -> Self {
+        let mut generator = SplitMix64(seed);
+        generator.0 ^= generator.next_u64() ^ replicate as u64;
+        generator
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in `0..n`.
+    fn below(&mut self, n: usize) -> usize {
+        ((self.next_u64() as u128 * n as u128) >> 64) as usize
+    }
+}
+
+/// Per-cell summary of the bootstrap replicates of a distance array.
+pub struct BootstrapSummary {
+    /// Means of the replicates.
+    pub mean: Vec<Vec<f64>>,
+    /// Lower bounds of the percentile confidence intervals.
+    pub lower: Vec<Vec<f64>>,
+    /// Upper bounds of the percentile confidence intervals.
+    pub upper: Vec<Vec<f64>>,
+}
+
+/// Returns the number of columns of the aligned `sequences`.
+///
+/// # Errors
+/// Returns [UnequalLengths] if they have different numbers.
+fn columns(sequences: &[&str]) -> Result<usize, UnequalLengths> {
+    let mut lengths = sequences.iter().map(|sequence| sequence.chars().count());
+    let expected = lengths.next().unwrap_or(0);
+    match lengths.find(|&length| length != expected) {
+        Some(found) => Err(UnequalLengths { expected, found }),
+        None => Ok(expected),
+    }
+}
+
+/// Creates `replicates` (n, k) vectors of the k `distances` between aligned `targets` and `queries`,
+/// with the alignment columns resampled with replacement,
+/// counting the sites by the `options`.
+///
+/// The columns are resampled after the [crate::distance::Deletion] of the `options`,
+/// by whole codons if the `options` have a reading frame, leaving out the columns outside them.
+/// Each pair only counts the resampled columns within its common content,
+/// so that end gaps stay left out.
+///
+/// The replicates only depend on the `seed`. Outer iteration over `targets`,
+/// inner iteration over `queries`, like in [crate::distance::make_distance_array_aligned].
+/// # Errors
+/// Returns [UnequalLengths] if the sequences have different numbers of columns.
+pub fn bootstrap_distance_arrays_aligned(
+    targets: &[&str],
+    queries: &[&str],
+    distances: &[Distance],
+    options: &DistanceOptions,
+    replicates: usize,
+    seed: u64,
+) -> Result<Vec<Vec<Vec<f64>>>, UnequalLengths> {
+    let width = columns(targets)?;
+    let query_width = columns(queries)?;
+    if !targets.is_empty() && !queries.is_empty() && query_width != width {
+        return Err(UnequalLengths {
+            expected: width,
+            found: query_width,
+        });
+    }
+    let chars = |sequences: &[&str]| -> Vec<Vec<char>> {
+        sequences.iter().map(|s| s.chars().collect()).collect()
+    };
+    let (targets, queries, options) =
+        with_deletion(targets, queries, options, |targets, queries, options| {
+            (chars(targets), chars(queries), *options)
+        });
+    let width = targets
+        .first()
+        .or_else(|| queries.first())
+        .map_or(0, Vec::len);
+    // Resampled units of `unit` columns from `start`: codons or single columns
+    let (start, unit) = match options.reading_frame {
+        Some(frame) => (usize::min(frame.offset, width), 3),
+        None => (0, 1),
+    };
+    let units = (width - start) / unit;
+    Ok((0..replicates)
+        .into_par_iter()
+        .map(|replicate| {
+            let mut generator = SplitMix64::new(seed, replicate);
+            let sample: Vec<usize> = (0..units)
+                .flat_map(|_| {
+                    let first = start + unit * generator.below(units);
+                    first..first + unit
+                })
+                .collect();
+            make_distance_array_resampled(&targets, &queries, &sample, distances, &options)
+        })
+        .collect())
+}
+
+/// Summarises the bootstrap `replicates` of a distance array by the mean of each cell
+/// and the percentile interval containing the `confidence` fraction of its replicates.
+///
+/// NaN replicates are left out of the summary of their cell.
+/// # Panics
+/// Panics if `confidence` is not in `0.0..=1.0`.
+pub fn summarize(replicates: &[Vec<Vec<f64>>], confidence: f64) -> BootstrapSummary {
+    assert!(
+        (0.0..=1.0).contains(&confidence),
+        "confidence {} is not in 0..=1",
+        confidence
+    );
+    let shape: Vec<usize> = replicates
+        .first()
+        .map_or(Vec::new(), |array| array.iter().map(Vec::len).collect());
+    let mut summary = BootstrapSummary {
+        mean: Vec::with_capacity(shape.len()),
+        lower: Vec::with_capacity(shape.len()),
+        upper: Vec::with_capacity(shape.len()),
+    };
+    let mut values = Vec::with_capacity(replicates.len());
+    for (row, &width) in shape.iter().enumerate() {
+        let (mut mean, mut lower, mut upper) = (vec![], vec![], vec![]);
+        for column in 0..width {
+            values.clear();
+            values.extend(
+                replicates
+                    .iter()
+                    .map(|array| array[row][column])
+                    .filter(|value| !value.is_nan()),
+            );
+            values.sort_by(f64::total_cmp);
+            mean.push(values.iter().sum::<f64>() / values.len() as f64);
+            lower.push(quantile(&values, (1.0 - confidence) / 2.0));
+            upper.push(quantile(&values, (1.0 + confidence) / 2.0));
+        }
+        summary.mean.push(mean);
+        summary.lower.push(lower);
+        summary.upper.push(upper);
+    }
+    summary
+}
+
+/// Returns the `fraction` quantile of the `sorted` values, interpolating between them.
+///
+/// Returns NaN if there are no values.
+fn quantile(sorted: &[f64], fraction: f64) -> f64 {
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+    let position = fraction * (sorted.len() - 1) as f64;
+    let (below, above) = (position.floor() as usize, position.ceil() as usize);
+    if below == above || sorted[below] == sorted[above] {
+        sorted[below]
+    } else {
+        sorted[below] + (position - below as f64) * (sorted[above] - sorted[below])
+    }
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+    use crate::distance::{make_distance_array_aligned, DEFAULT_DISTANCES};
+
+    #[test]
+    fn test_bootstrap() {
+        let targets = ["ACGTACGTAC", "ACGTTCGAAC"];
+        let queries = ["ACGAACGTAC", "A-GTACGTAC", "ACGTACGTAC"];
+        let replicates = bootstrap_distance_arrays_aligned(
+            &targets,
+            &queries,
+            &DEFAULT_DISTANCES,
+            &DistanceOptions::default(),
+            50,
+            7,
+        )
+        .unwrap();
+        assert_eq!(replicates.len(), 50);
+        assert!(replicates.iter().all(|array| array.len() == 6));
+        assert_eq!(
+            replicates,
+            bootstrap_distance_arrays_aligned(
+                &targets,
+                &queries,
+                &DEFAULT_DISTANCES,
+                &DistanceOptions::default(),
+                50,
+                7
+            )
+            .unwrap()
+        );
+        assert_ne!(
+            replicates,
+            bootstrap_distance_arrays_aligned(
+                &targets,
+                &queries,
+                &DEFAULT_DISTANCES,
+                &DistanceOptions::default(),
+                50,
+                8
+            )
+            .unwrap()
+        );
+        // Identical sequences stay identical
+        assert!(replicates.iter().all(|array| array[2] == vec![0.0; 4]));
+
+        let summary = summarize(&replicates, 0.9);
+        let original = make_distance_array_aligned(
+            &targets,
+            &queries,
+            &DEFAULT_DISTANCES,
+            &DistanceOptions::default(),
+        );
+        for (row, distances) in original.iter().enumerate() {
+            for (column, &distance) in distances.iter().enumerate() {
+                // A saturated replicate makes the mean infinite
+                assert!(summary.lower[row][column] <= summary.mean[row][column]);
+                assert!(summary.lower[row][column] <= distance);
+                assert!(distance <= summary.upper[row][column]);
+            }
+        }
+
+        assert_eq!(
+            bootstrap_distance_arrays_aligned(
+                &targets,
+                &["ACGT"],
+                &DEFAULT_DISTANCES,
+                &DistanceOptions::default(),
+                1,
+                0
+            ),
+            Err(UnequalLengths {
+                expected: 10,
+                found: 4
+            })
+        );
+    }
+
+    #[test]
+    fn test_bootstrap_end_gaps() {
+        let targets = ["AAAAAAAAAAACGTACGTAC----------"];
+        let queries = [
+            "----------ACGTACGTACGGGGGGGGGG",
+            "----------ACGTTCGTACGGGGGGGGGG",
+        ];
+        let distances = [Distance::P, Distance::PGaps];
+        let options = DistanceOptions::default();
+        let replicates =
+            bootstrap_distance_arrays_aligned(&targets, &queries, &distances, &options, 200, 3)
+                .unwrap();
+        let summary = summarize(&replicates, 0.95);
+        let original = make_distance_array_aligned(&targets, &queries, &distances, &options);
+        assert_eq!(original, [[0.0, 0.0], [0.1, 0.1]]);
+        // The end gaps are left out of the replicates like of the point estimates
+        assert_eq!(summary.mean[0], [0.0, 0.0]);
+        for column in 0..2 {
+            assert!(summary.lower[1][column] <= summary.mean[1][column]);
+            assert!(summary.mean[1][column] <= summary.upper[1][column]);
+            assert!((summary.mean[1][column] - 0.1).abs() < 0.03);
+        }
+    }
+
+    #[test]
+    fn test_quantile() {
+        assert_eq!(quantile(&[1.0, 2.0, 3.0, 4.0, 5.0], 0.5), 3.0);
+        assert_eq!(quantile(&[1.0, 2.0], 0.25), 1.25);
+        assert_eq!(quantile(&[1.0, f64::INFINITY], 1.0), f64::INFINITY);
+        assert!(quantile(&[], 0.5).is_nan());
+    }
+}
@@ -29,6 +29,7 @@ impl<'s> Column<'s> {
     }
 
     /// Iterator over the [Column]'s strings.
+    #[cfg(test)]
     pub fn iter(&self) -> impl Iterator<Item = &str> {
         self.strings.iter().copied()
     }
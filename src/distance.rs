@@ -1,16 +1,170 @@
 //! Calculating distances between sequences
 
+mod codon;
+mod likelihood;
+mod protein;
+
+pub use codon::{stop_codons, translate, CodonStats, GeneticCode, ReadingFrame};
+pub use likelihood::{MaximumLikelihood, SubstitutionModel, DEFAULT_MAX_DISTANCE};
+pub use protein::{
+    make_distance_array_protein, make_distance_array_protein_aligned, seq_distances_protein,
+    seq_distances_protein_aligned, ProteinDistance, ProteinLikelihood, ProteinModel,
+    DEFAULT_PROTEIN_DISTANCES,
+};
+use pyo3::prelude::pyclass;
 use rayon::prelude::*;
+use std::cell::RefCell;
+
+use crate::needle::{Aligner, AlignerWorkspace, Orientation, ScoreOverflow};
 
-use crate::needle::Aligner;
+thread_local! {
+    /// Buffers for the alignments made by the current thread,
+    /// so that the rayon workers reuse them across pairs.
+    static WORKSPACE: RefCell<AlignerWorkspace> = RefCell::new(AlignerWorkspace::default());
+    /// Reverse complement of the query being aligned by the current thread.
+    static REVERSE_QUERY: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
 
 /// State for the distance calculation
 pub struct AlignmentStats {
     total_length: usize,
     common_length: usize,
     total_gap_length: usize,
-    transitions: usize,
-    transversions: usize,
+    /// Sites pairing a gap with a base counted as a fifth state, only by the p-distances.
+    gap_differences: usize,
+    /// A <-> G substitutions, fractional for ambiguous sites.
+    purine_transitions: f64,
+    /// C <-> T substitutions.
+    pyrimidine_transitions: f64,
+    transversions: f64,
+    /// Pairs of bases at the common sites.
+    divergence: DivergenceMatrix,
+    /// How the sites are counted.
+    options: DistanceOptions,
+    /// Whether the gap of the last counted site, if any, was in the target.
+    gap_in_target: Option<bool>,
+    /// Codons of aligned coding sequences, for the codon distances.
+    codons: Option<CodonStats>,
+}
+
+/// How the sites of the sequences are counted for the distances.
+#[pyclass]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct DistanceOptions {
+    /// Which sites with gaps or missing data are left out.
+    pub deletion: Deletion,
+    /// How the sites pairing a gap with a base are counted.
+    pub gaps: GapTreatment,
+    /// Whether contiguous gaps in the same sequence count as a single site.
+    pub indel_events: bool,
+    /// How the sites with ambiguous bases are counted.
+    pub ambiguity: AmbiguityPolicy,
+    /// Where the base frequencies of the models come from.
+    pub frequencies: BaseFrequencies,
+    /// Reading frame of coding sequences, for the codon distances.
+    pub reading_frame: Option<ReadingFrame>,
+    /// Whether the first, second and third codon positions of the reading frame are counted,
+    /// in aligned sequences. All the columns are counted if [None] or without a reading frame.
+    pub codon_positions: Option<[bool; 3]>,
+}
+
+impl DistanceOptions {
+    /// Returns true if the column `i` of aligned sequences is counted
+    /// by the [DistanceOptions::codon_positions].
+    fn counts_column(&self, i: usize) -> bool {
+        match (self.reading_frame, self.codon_positions) {
+            (Some(frame), Some(positions)) => positions[(i + 3 - frame.offset % 3) % 3],
+            _ => true,
+        }
+    }
+
+    /// Returns the options with [BaseFrequencies::WholeSet] fixed to the frequencies
+    /// of the bases in the `targets` and `queries`.
+    fn with_frequencies_of(&self, targets: &[&str], queries: &[&str]) -> DistanceOptions {
+        if self.frequencies != BaseFrequencies::WholeSet {
+            return *self;
+        }
+        let mut counts = [0.0; 4];
+        for x in targets
+            .iter()
+            .chain(queries)
+            .flat_map(|sequence| sequence.bytes())
+        {
+            let set = match classify(x, self.ambiguity) {
+                SymbolType::Nucleotide(set) => set,
+                _ => continue,
+            };
+            let ambiguous = set.count_ones() > 1;
+            if ambiguous && matches!(self.ambiguity, AmbiguityPolicy::Ignore) {
+                continue;
+            }
+            let weight = 1.0 / set.count_ones() as f64;
+            for base in bases(set) {
+                counts[base.trailing_zeros() as usize] += weight;
+            }
+        }
+        let total: f64 = counts.iter().sum();
+        let frequencies = if total == 0.0 {
+            [0.0; 4]
+        } else {
+            counts.map(|count| count / total)
+        };
+        DistanceOptions {
+            frequencies: BaseFrequencies::Fixed(frequencies),
+            ..*self
+        }
+    }
+}
+
+/// Where the base frequencies of the models come from.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum BaseFrequencies {
+    /// The common sites of each pair of sequences.
+    #[default]
+    Pairwise,
+    /// All the sequences compared, fixed by the functions comparing them.
+    WholeSet,
+    /// Given frequencies of A, C, G and T.
+    Fixed([f64; 4]),
+}
+
+/// Which sites with gaps or missing data are left out.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Deletion {
+    /// Only the sites with a gap or missing data in either sequence of a pair.
+    #[default]
+    Pairwise,
+    /// The columns with a gap or missing data in any of the aligned sequences.
+    ///
+    /// The sequences aligned pair by pair are deleted pairwise, without the gaps.
+    Complete,
+}
+
+/// How the sites pairing a gap with a base are counted.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum GapTreatment {
+    /// Left out of the common sites, counted only by [AlignmentStats::pdistance_counting_gaps].
+    #[default]
+    Separate,
+    /// Counted as common sites with a difference by the p-distances.
+    ///
+    /// A gap is not a substitution, so the distances corrected by substitution models
+    /// leave these sites out.
+    FifthState,
+}
+
+/// How sites with IUPAC ambiguity codes such as R or Y are counted.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum AmbiguityPolicy {
+    /// Leave out the sites pairing an ambiguous base with a base (pairwise deletion).
+    #[default]
+    Ignore,
+    /// Treat ambiguous bases like N.
+    Missing,
+    /// Count a match if the bases the codes stand for overlap, and average otherwise.
+    Overlap,
+    /// Count the average of the differences between the bases the codes stand for.
+    Average,
 }
 
 impl AlignmentStats {
@@ -20,31 +174,98 @@ impl AlignmentStats {
             total_length: 0,
             common_length: 0,
             total_gap_length: 0,
-            transitions: 0,
-            transversions: 0,
+            gap_differences: 0,
+            purine_transitions: 0.0,
+            pyrimidine_transitions: 0.0,
+            transversions: 0.0,
+            divergence: DivergenceMatrix::default(),
+            options: DistanceOptions::default(),
+            gap_in_target: None,
+            codons: None,
         }
     }
 
+    /// Zeroed state, counting the sites by the `options`.
+    pub fn with_options(options: &DistanceOptions) -> Self {
+        AlignmentStats {
+            options: *options,
+            ..AlignmentStats::new()
+        }
+    }
+
+    // Number of transitions calculated so far
+    fn transitions(&self) -> f64 {
+        self.purine_transitions + self.pyrimidine_transitions
+    }
+
     // Number of substitions calculated so far
-    fn substitions(&self) -> usize {
-        self.transversions + self.transitions
+    fn substitions(&self) -> f64 {
+        self.transversions + self.transitions()
+    }
+
+    // Number of sites compared by the p-distance, with the gaps counted as a fifth state
+    fn compared_length(&self) -> usize {
+        self.common_length + self.gap_differences
+    }
+
+    /// Numbers of sites, named by [SITE_COUNTS].
+    ///
+    /// The sites with gaps counted as a fifth state are common sites, but not substitutions.
+    pub fn site_counts(&self) -> [f64; 5] {
+        [
+            self.total_length as f64,
+            self.compared_length() as f64,
+            self.total_gap_length as f64,
+            self.transitions(),
+            self.transversions,
+        ]
+    }
+
+    /// Frequencies of A, C, G and T at the common sites, zero if there are none.
+    pub fn base_frequencies(&self) -> [f64; 4] {
+        let (target_counts, query_counts) = self.divergence.base_counts();
+        let total: f64 = target_counts.iter().chain(query_counts.iter()).sum();
+        if total == 0.0 {
+            return [0.0; 4];
+        }
+        [0, 1, 2, 3].map(|i| (target_counts[i] + query_counts[i]) / total)
+    }
+
+    /// Base frequencies of the models: those fixed by the [DistanceOptions],
+    /// otherwise the [AlignmentStats::base_frequencies].
+    fn model_frequencies(&self) -> [f64; 4] {
+        match self.options.frequencies {
+            BaseFrequencies::Fixed(frequencies) => frequencies,
+            _ => self.base_frequencies(),
+        }
+    }
+
+    /// Returns the `estimate` from the [CodonStats], NaN if the codons weren't counted.
+    fn codon_estimate(&self, estimate: impl Fn(&CodonStats) -> f64) -> f64 {
+        self.codons.as_ref().map_or(f64::NAN, estimate)
+    }
+
+    /// Base frequencies fixed by the [DistanceOptions], if any.
+    fn fixed_frequencies(&self) -> Option<[f64; 4]> {
+        match self.options.frequencies {
+            BaseFrequencies::Fixed(frequencies) => Some(frequencies),
+            _ => None,
+        }
     }
 
     /// pairwise uncorrelated distance
     pub fn pdistance(&self) -> f64 {
-        (self.substitions() as f64 / self.common_length as f64).abs()
+        ((self.substitions() + self.gap_differences as f64) / self.compared_length() as f64).abs()
     }
 
     /// pairwise uncorrelated distance with gaps
     pub fn pdistance_counting_gaps(&self) -> f64 {
-        f64::abs(
-            (self.substitions() as f64 + self.total_gap_length as f64) / self.total_length as f64,
-        )
+        f64::abs((self.substitions() + self.total_gap_length as f64) / self.total_length as f64)
     }
 
     /// Jukes-Cantor distance
     pub fn jukes_cantor_distance(&self) -> f64 {
-        let p = self.substitions() as f64 / self.common_length as f64;
+        let p = self.substitions() / self.common_length as f64;
         if p > 3.0 / 4.0 {
             f64::INFINITY
         } else {
@@ -54,8 +275,8 @@ impl AlignmentStats {
 
     /// Kimura's two parameter distance
     pub fn kimura2p_distance(&self) -> f64 {
-        let p = self.transitions as f64 / self.common_length as f64;
-        let q = self.transversions as f64 / self.common_length as f64;
+        let p = self.transitions() / self.common_length as f64;
+        let q = self.transversions / self.common_length as f64;
         let distance =
             f64::abs(-(1.0 / 2.0) * f64::ln((1.0 - 2.0 * p - q) * f64::sqrt(1.0 - 2.0 * q)));
         if distance.is_nan() {
@@ -65,224 +286,1416 @@ impl AlignmentStats {
         }
     }
 
-    fn count_gap(&mut self) {
-        self.total_length += 1;
-        self.total_gap_length += 1;
+    /// Jukes-Cantor distance with gamma distributed rates of the shape `alpha`.
+    ///
+    /// Returns NaN if there are no common sites and infinity if the sequences are saturated.
+    pub fn jukes_cantor_gamma_distance(&self, alpha: f64) -> f64 {
+        if self.common_length == 0 {
+            return f64::NAN;
+        }
+        let p = self.substitions() / self.common_length as f64;
+        saturated_to_infinity(rate_term(0.75, 1.0 - 4.0 / 3.0 * p, Some(alpha)))
     }
 
-    fn count_match(&mut self) {
-        self.total_length += 1;
-        self.common_length += 1;
+    /// Kimura's two parameter distance with gamma distributed rates of the shape `alpha`.
+    ///
+    /// Returns NaN if there are no common sites and infinity if the sequences are saturated.
+    pub fn kimura2p_gamma_distance(&self, alpha: f64) -> f64 {
+        if self.common_length == 0 {
+            return f64::NAN;
+        }
+        let p = self.transitions() / self.common_length as f64;
+        let q = self.transversions / self.common_length as f64;
+        saturated_to_infinity(
+            rate_term(0.5, 1.0 - 2.0 * p - q, Some(alpha))
+                + rate_term(0.25, 1.0 - 2.0 * q, Some(alpha)),
+        )
+    }
+
+    /// Felsenstein's distance (F81), correcting the p-distance for unequal base frequencies.
+    ///
+    /// Returns NaN if there are no common sites and infinity if the sequences are saturated.
+    pub fn f81_distance(&self) -> f64 {
+        if self.common_length == 0 {
+            return f64::NAN;
+        }
+        let p = self.substitions() / self.common_length as f64;
+        let b = f81_weight(self.model_frequencies());
+        saturated_to_infinity(rate_term(b, 1.0 - ratio(p, b), None))
+    }
+
+    /// Hasegawa-Kishino-Yano distance (HKY85), with a single transition/transversion ratio
+    /// and unequal base frequencies.
+    ///
+    /// The ratio is the one giving the expected proportion of transitions.
+    /// Returns NaN if there are no common sites and infinity if the sequences are saturated.
+    pub fn hky85_distance(&self) -> f64 {
+        if self.common_length == 0 {
+            return f64::NAN;
+        }
+        let p = self.transitions() / self.common_length as f64;
+        let q = self.transversions / self.common_length as f64;
+        hky85(p, q, self.model_frequencies())
+    }
+
+    /// General time reversible distance of the [DivergenceMatrix], see [DivergenceMatrix::gtr_distance].
+    pub fn gtr_distance(&self) -> f64 {
+        self.divergence.gtr_distance(self.fixed_frequencies())
+    }
+
+    /// Tamura's three parameter distance (T92), correcting for the GC content.
+    ///
+    /// Returns NaN if there are no common sites and infinity if the sequences are saturated.
+    pub fn tamura3p_distance(&self) -> f64 {
+        if self.common_length == 0 {
+            return f64::NAN;
+        }
+        let p = self.transitions() / self.common_length as f64;
+        let q = self.transversions / self.common_length as f64;
+        let [_, c, g, _] = self.model_frequencies();
+        let gc = c + g;
+        let h = 2.0 * gc * (1.0 - gc);
+        saturated_to_infinity(
+            rate_term(h, 1.0 - ratio(p, h) - q, None)
+                + rate_term(0.5 * (1.0 - h), 1.0 - 2.0 * q, None),
+        )
+    }
+
+    /// Tamura-Nei distance (TN93), with different rates of purine and pyrimidine transitions
+    /// and unequal base frequencies.
+    ///
+    /// Returns NaN if there are no common sites and infinity if the sequences are saturated.
+    pub fn tamura_nei_distance(&self) -> f64 {
+        self.tamura_nei(None)
+    }
+
+    /// Tamura-Nei distance with gamma distributed rates of the shape `alpha`.
+    ///
+    /// Returns NaN if there are no common sites and infinity if the sequences are saturated.
+    pub fn tamura_nei_gamma_distance(&self, alpha: f64) -> f64 {
+        self.tamura_nei(Some(alpha))
+    }
+
+    // Tamura-Nei distance, with gamma distributed rates if `gamma_shape` is given
+    fn tamura_nei(&self, gamma_shape: Option<f64>) -> f64 {
+        if self.common_length == 0 {
+            return f64::NAN;
+        }
+        let length = self.common_length as f64;
+        let p1 = self.purine_transitions / length;
+        let p2 = self.pyrimidine_transitions / length;
+        let q = self.transversions / length;
+        let [a, c, g, t] = self.model_frequencies();
+        let (purines, pyrimidines) = (a + g, c + t);
+        let purine_weight = ratio(2.0 * a * g, purines);
+        let pyrimidine_weight = ratio(2.0 * c * t, pyrimidines);
+        let transversion_weight = 2.0
+            * (purines * pyrimidines
+                - ratio(a * g * pyrimidines, purines)
+                - ratio(c * t * purines, pyrimidines));
+        saturated_to_infinity(
+            rate_term(
+                purine_weight,
+                1.0 - ratio(p1, purine_weight) - ratio(q, 2.0 * purines),
+                gamma_shape,
+            ) + rate_term(
+                pyrimidine_weight,
+                1.0 - ratio(p2, pyrimidine_weight) - ratio(q, 2.0 * pyrimidines),
+                gamma_shape,
+            ) + rate_term(
+                transversion_weight,
+                1.0 - ratio(q, 2.0 * purines * pyrimidines),
+                gamma_shape,
+            ),
+        )
+    }
+
+    // Proportions of purine transitions, pyrimidine transitions and transversions
+    // at the common sites
+    fn substitution_proportions(&self) -> [f64; 3] {
+        let length = self.common_length as f64;
+        [
+            self.purine_transitions / length,
+            self.pyrimidine_transitions / length,
+            self.transversions / length,
+        ]
+    }
+
+    // Variance by the delta method of a distance with the `gradient`
+    // by the substitution proportions
+    fn delta_method_variance(&self, gradient: [f64; 3]) -> f64 {
+        let proportions = self.substitution_proportions();
+        let mean: f64 = (0..3).map(|i| proportions[i] * gradient[i]).sum();
+        let square: f64 = (0..3).map(|i| proportions[i] * gradient[i].powi(2)).sum();
+        ((square - mean * mean) / self.common_length as f64).max(0.0)
+    }
+
+    // Gradient of the Jukes-Cantor distance by the substitution proportions
+    fn jukes_cantor_gradient(&self, gamma_shape: Option<f64>) -> [f64; 3] {
+        let p = self.substitions() / self.common_length as f64;
+        let coefficient = -4.0 / 3.0;
+        rate_term_gradient(0.75, 1.0 - 4.0 / 3.0 * p, [coefficient; 3], gamma_shape)
+    }
+
+    // Gradient of Kimura's two parameter distance by the substitution proportions
+    fn kimura2p_gradient(&self, gamma_shape: Option<f64>) -> [f64; 3] {
+        let p = self.transitions() / self.common_length as f64;
+        let q = self.transversions / self.common_length as f64;
+        add_gradients(
+            rate_term_gradient(0.5, 1.0 - 2.0 * p - q, [-2.0, -2.0, -1.0], gamma_shape),
+            rate_term_gradient(0.25, 1.0 - 2.0 * q, [0.0, 0.0, -2.0], gamma_shape),
+        )
+    }
+
+    // Gradient of the F81 distance by the substitution proportions
+    fn f81_gradient(&self) -> [f64; 3] {
+        let p = self.substitions() / self.common_length as f64;
+        let b = f81_weight(self.model_frequencies());
+        rate_term_gradient(b, 1.0 - ratio(p, b), [-1.0 / b; 3], None)
+    }
+
+    // Gradient of the HKY85 distance by the substitution proportions, by central differences
+    fn hky85_gradient(&self) -> [f64; 3] {
+        let p = self.transitions() / self.common_length as f64;
+        let q = self.transversions / self.common_length as f64;
+        let frequencies = self.model_frequencies();
+        let by_transitions = derivative(|p| hky85(p, q, frequencies), p);
+        let by_transversions = derivative(|q| hky85(p, q, frequencies), q);
+        [by_transitions, by_transitions, by_transversions]
+    }
+
+    // Gradient of Tamura's three parameter distance by the substitution proportions
+    fn tamura3p_gradient(&self) -> [f64; 3] {
+        let p = self.transitions() / self.common_length as f64;
+        let q = self.transversions / self.common_length as f64;
+        let [_, c, g, _] = self.model_frequencies();
+        let gc = c + g;
+        let h = 2.0 * gc * (1.0 - gc);
+        add_gradients(
+            rate_term_gradient(h, 1.0 - ratio(p, h) - q, [-1.0 / h, -1.0 / h, -1.0], None),
+            rate_term_gradient(0.5 * (1.0 - h), 1.0 - 2.0 * q, [0.0, 0.0, -2.0], None),
+        )
     }
 
-    fn count_transition(&mut self) {
+    // Gradient of the Tamura-Nei distance by the substitution proportions
+    fn tamura_nei_gradient(&self, gamma_shape: Option<f64>) -> [f64; 3] {
+        let [p1, p2, q] = self.substitution_proportions();
+        let [a, c, g, t] = self.model_frequencies();
+        let (purines, pyrimidines) = (a + g, c + t);
+        let purine_weight = ratio(2.0 * a * g, purines);
+        let pyrimidine_weight = ratio(2.0 * c * t, pyrimidines);
+        let transversion_weight = 2.0
+            * (purines * pyrimidines
+                - ratio(a * g * pyrimidines, purines)
+                - ratio(c * t * purines, pyrimidines));
+        let gradient = add_gradients(
+            rate_term_gradient(
+                purine_weight,
+                1.0 - ratio(p1, purine_weight) - ratio(q, 2.0 * purines),
+                [-1.0 / purine_weight, 0.0, -0.5 / purines],
+                gamma_shape,
+            ),
+            rate_term_gradient(
+                pyrimidine_weight,
+                1.0 - ratio(p2, pyrimidine_weight) - ratio(q, 2.0 * pyrimidines),
+                [0.0, -1.0 / pyrimidine_weight, -0.5 / pyrimidines],
+                gamma_shape,
+            ),
+        );
+        add_gradients(
+            gradient,
+            rate_term_gradient(
+                transversion_weight,
+                1.0 - ratio(q, 2.0 * purines * pyrimidines),
+                [0.0, 0.0, -0.5 / (purines * pyrimidines)],
+                gamma_shape,
+            ),
+        )
+    }
+
+    // Counts a site with a gap in the target if `in_target` and in the query otherwise,
+    // following a gap in the target if `previous` is `Some(true)`
+    fn count_gap(&mut self, in_target: bool, previous: Option<bool>) {
+        self.gap_in_target = Some(in_target);
+        let extension = self.options.indel_events && previous == Some(in_target);
+        if extension || self.options.deletion == Deletion::Complete {
+            return;
+        }
         self.total_length += 1;
-        self.common_length += 1;
-        self.transitions += 1;
+        self.total_gap_length += 1;
+        if self.options.gaps == GapTreatment::FifthState {
+            self.gap_differences += 1;
+        }
     }
-    fn count_transversion(&mut self) {
+
+    // Counts a common site with the sets of bases `x` and `y`
+    fn count_common(&mut self, x: u8, y: u8) {
+        let ambiguous = x.count_ones() > 1 || y.count_ones() > 1;
+        match self.options.ambiguity {
+            _ if !ambiguous => self.count_bases(x, y, 1.0),
+            AmbiguityPolicy::Ignore | AmbiguityPolicy::Missing => return,
+            AmbiguityPolicy::Overlap if x & y != 0 => {
+                let common = x & y;
+                let weight = 1.0 / common.count_ones() as f64;
+                for base in bases(common) {
+                    self.count_bases(base, base, weight);
+                }
+            }
+            AmbiguityPolicy::Overlap | AmbiguityPolicy::Average => {
+                let weight = 1.0 / (x.count_ones() * y.count_ones()) as f64;
+                for x in bases(x) {
+                    for y in bases(y) {
+                        self.count_bases(x, y, weight);
+                    }
+                }
+            }
+        }
         self.total_length += 1;
         self.common_length += 1;
-        self.transversions += 1;
+    }
+
+    // Counts the `weight` of a site with the bases `x` and `y`
+    fn count_bases(&mut self, x: u8, y: u8, weight: f64) {
+        const PURINES: u8 = A | G;
+        const PYRIMIDINES: u8 = C | T;
+        self.divergence.add(x, y, weight);
+        if x == y {
+            return;
+        }
+        match x | y {
+            PURINES => self.purine_transitions += weight,
+            PYRIMIDINES => self.pyrimidine_transitions += weight,
+            _ => self.transversions += weight,
+        }
     }
 
     /// Count `(x, y)` pair.
     pub fn update(&mut self, (x, y): (u8, u8)) {
-        use NucleotideType::*;
         use SymbolType::*;
-        match (classify(x), classify(y)) {
-            (Gap, Nucleotide(_)) => self.count_gap(),
-            (Nucleotide(_), Gap) => self.count_gap(),
-            (Nucleotide(_), Nucleotide(_)) if x == y => self.count_match(),
-            (Nucleotide(Purine), Nucleotide(Purine))
-            | (Nucleotide(Pyrimidine), Nucleotide(Pyrimidine)) => self.count_transition(),
-            (Nucleotide(Pyrimidine), Nucleotide(Purine))
-            | (Nucleotide(Purine), Nucleotide(Pyrimidine)) => self.count_transversion(),
-            (Nucleotide(_), Nucleotide(_)) => {},
+        let ambiguity = self.options.ambiguity;
+        // Columns of gaps in both sequences don't interrupt an indel
+        let gap_in_target = self.gap_in_target.take();
+        match (classify(x, ambiguity), classify(y, ambiguity)) {
+            (Gap, Gap) => self.gap_in_target = gap_in_target,
+            (Gap, Nucleotide(_)) => self.count_gap(true, gap_in_target),
+            (Nucleotide(_), Gap) => self.count_gap(false, gap_in_target),
+            (Nucleotide(x), Nucleotide(y)) => self.count_common(x, y),
             _ => {}
         }
     }
 }
 
+/// Divergence matrix of a pair of sequences: numbers of the sites with each pair of bases.
+///
+/// Stays consistent when the base composition differs between the sequences,
+/// unlike the counts of substitutions in [AlignmentStats].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DivergenceMatrix {
+    /// Sites with `target` base `i` and `query` base `j` at `[i][j]`, among A, C, G and T,
+    /// fractional for ambiguous sites.
+    counts: [[f64; 4]; 4],
+}
+
+/// Frequencies of the pairs of bases, of the bases in the target and of the bases in the query.
+type Frequencies = (Vec<Vec<f64>>, Vec<f64>, Vec<f64>);
+
+impl DivergenceMatrix {
+    // Counts the `weight` of a site with the single bases `x` and `y`
+    fn add(&mut self, x: u8, y: u8, weight: f64) {
+        self.counts[x.trailing_zeros() as usize][y.trailing_zeros() as usize] += weight;
+    }
+
+    /// Numbers of A, C, G and T in the target and in the query.
+    fn base_counts(&self) -> ([f64; 4], [f64; 4]) {
+        let target = self.counts.map(|row| row.iter().sum());
+        let query = [0, 1, 2, 3].map(|j| self.counts.iter().map(|row| row[j]).sum());
+        (target, query)
+    }
+
+    /// Returns the matrix of the frequencies of the pairs of the bases occurring
+    /// in either sequence, and the frequencies of the bases in the target and in the query.
+    ///
+    /// Returns [None] if there are no sites.
+    fn frequencies(&self) -> Option<Frequencies> {
+        let (target_counts, query_counts) = self.base_counts();
+        let total: f64 = target_counts.iter().sum();
+        if total == 0.0 {
+            return None;
+        }
+        let bases: Vec<usize> = (0..4)
+            .filter(|&i| target_counts[i] + query_counts[i] > 0.0)
+            .collect();
+        let matrix = bases
+            .iter()
+            .map(|&i| bases.iter().map(|&j| self.counts[i][j] / total).collect())
+            .collect();
+        let target = bases.iter().map(|&i| target_counts[i] / total).collect();
+        let query = bases.iter().map(|&i| query_counts[i] / total).collect();
+        Some((matrix, target, query))
+    }
+
+    /// LogDet distance: `-ln(det F) / k - ln(k)`, where `F` is the divergence matrix
+    /// of the `k` bases occurring in the sequences, normalised to frequencies.
+    ///
+    /// Is zero for identical sequences only if their bases are equally frequent.
+    /// Returns NaN if there are no sites and infinity if the sequences are saturated.
+    pub fn logdet_distance(&self) -> f64 {
+        let (matrix, _, _) = match self.frequencies() {
+            Some(frequencies) => frequencies,
+            None => return f64::NAN,
+        };
+        let k = matrix.len() as f64;
+        saturated_to_infinity(-determinant(matrix).ln() / k - k.ln())
+    }
+
+    /// Paralinear distance (Lake, 1994): LogDet corrected by the base frequencies
+    /// of both sequences, zero for identical sequences.
+    ///
+    /// Returns NaN if there are no sites and infinity if the sequences are saturated.
+    pub fn paralinear_distance(&self) -> f64 {
+        let (matrix, target, query) = match self.frequencies() {
+            Some(frequencies) => frequencies,
+            None => return f64::NAN,
+        };
+        let k = matrix.len() as f64;
+        let log_frequencies: f64 = target.iter().chain(query.iter()).map(|f| f.ln()).sum();
+        saturated_to_infinity(-(determinant(matrix).ln() - 0.5 * log_frequencies) / k)
+    }
+
+    /// General time reversible distance (Rodríguez et al., 1990): `-tr(Π ln(Π⁻¹ F))`,
+    /// where `F` is the symmetrised divergence matrix of the bases occurring in the sequences,
+    /// normalised to frequencies, and `Π` is the diagonal matrix of the `frequencies` of the bases,
+    /// by default those of `F`.
+    ///
+    /// Returns NaN if there are no sites and infinity if the sequences are saturated.
+    pub fn gtr_distance(&self, frequencies: Option<[f64; 4]>) -> f64 {
+        gtr(&self.counts, frequencies)
+    }
+
+    /// Variance of the [DivergenceMatrix::gtr_distance] by the delta method,
+    /// with the derivatives by central differences.
+    pub fn gtr_variance(&self, frequencies: Option<[f64; 4]>) -> f64 {
+        let total: f64 = self.counts.iter().flatten().sum();
+        if total == 0.0 {
+            return f64::NAN;
+        }
+        let pairs = self.counts.map(|row| row.map(|count| count / total));
+        let (mut mean, mut square) = (0.0, 0.0);
+        for (i, row) in pairs.iter().enumerate() {
+            for (j, &pair) in row.iter().enumerate() {
+                if pair == 0.0 {
+                    continue;
+                }
+                let gradient = derivative(
+                    |value| {
+                        let mut pairs = pairs;
+                        pairs[i][j] = value;
+                        gtr(&pairs, frequencies)
+                    },
+                    pair,
+                );
+                mean += pair * gradient;
+                square += pair * gradient * gradient;
+            }
+        }
+        ((square - mean * mean) / total).max(0.0)
+    }
+
+    /// Variance of the [DivergenceMatrix::logdet_distance] by the delta method.
+    pub fn logdet_variance(&self) -> f64 {
+        self.delta_method_variance(false)
+    }
+
+    /// Variance of the [DivergenceMatrix::paralinear_distance] by the delta method.
+    pub fn paralinear_variance(&self) -> f64 {
+        self.delta_method_variance(true)
+    }
+
+    // Variance by the delta method of the LogDet distance, or of the paralinear one if `paralinear`
+    fn delta_method_variance(&self, paralinear: bool) -> f64 {
+        let (matrix, target, query) = match self.frequencies() {
+            Some(frequencies) => frequencies,
+            None => return f64::NAN,
+        };
+        let inverse = match inverse(matrix.clone()) {
+            Some(inverse) => inverse,
+            None => return f64::INFINITY,
+        };
+        let k = matrix.len();
+        let (mut mean, mut square) = (0.0, 0.0);
+        for i in 0..k {
+            for j in 0..k {
+                // Derivative of the distance by the frequency of the pair
+                let mut gradient = inverse[j][i];
+                if paralinear {
+                    gradient -= 0.5 / target[i] + 0.5 / query[j];
+                }
+                gradient /= -(k as f64);
+                mean += matrix[i][j] * gradient;
+                square += matrix[i][j] * gradient * gradient;
+            }
+        }
+        let total: f64 = self.counts.iter().flatten().sum();
+        ((square - mean * mean) / total).max(0.0)
+    }
+}
+
+/// Returns the inverse of the square `matrix` by Gauss-Jordan elimination,
+/// or [None] if it is singular.
+fn inverse(mut matrix: Vec<Vec<f64>>) -> Option<Vec<Vec<f64>>> {
+    let n = matrix.len();
+    let mut inverse: Vec<Vec<f64>> = (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+        .collect();
+    for column in 0..n {
+        let pivot = (column..n)
+            .max_by(|&a, &b| matrix[a][column].abs().total_cmp(&matrix[b][column].abs()))
+            .expect("the column has rows");
+        if matrix[pivot][column] == 0.0 {
+            return None;
+        }
+        matrix.swap(pivot, column);
+        inverse.swap(pivot, column);
+        let scale = matrix[column][column];
+        matrix[column].iter_mut().for_each(|value| *value /= scale);
+        inverse[column].iter_mut().for_each(|value| *value /= scale);
+        let (pivot_row, pivot_inverse) = (matrix[column].clone(), inverse[column].clone());
+        for (row, (matrix_row, inverse_row)) in
+            matrix.iter_mut().zip(inverse.iter_mut()).enumerate()
+        {
+            if row == column {
+                continue;
+            }
+            let factor = matrix_row[column];
+            for (value, pivot_value) in matrix_row.iter_mut().zip(&pivot_row) {
+                *value -= factor * pivot_value;
+            }
+            for (value, pivot_value) in inverse_row.iter_mut().zip(&pivot_inverse) {
+                *value -= factor * pivot_value;
+            }
+        }
+    }
+    Some(inverse)
+}
+
+/// Computes the [DivergenceMatrix::gtr_distance] of the pair `counts`.
+fn gtr(counts: &[[f64; 4]; 4], frequencies: Option<[f64; 4]>) -> f64 {
+    let bases: Vec<usize> = (0..4)
+        .filter(|&i| (0..4).any(|j| counts[i][j] + counts[j][i] > 0.0))
+        .collect();
+    let total: f64 = counts.iter().flatten().sum();
+    if total == 0.0 {
+        return f64::NAN;
+    }
+    let symmetric = |i: usize, j: usize| (counts[i][j] + counts[j][i]) / (2.0 * total);
+    let frequencies: Vec<f64> = match frequencies {
+        Some(frequencies) => {
+            let sum: f64 = bases.iter().map(|&i| frequencies[i]).sum();
+            bases.iter().map(|&i| frequencies[i] / sum).collect()
+        }
+        None => bases
+            .iter()
+            .map(|&i| bases.iter().map(|&j| symmetric(i, j)).sum())
+            .collect(),
+    };
+    // Π^(-1/2) F Π^(-1/2) is symmetric, with the eigenvalues of Π⁻¹ F
+    let matrix = bases
+        .iter()
+        .zip(&frequencies)
+        .map(|(&i, x)| {
+            bases
+                .iter()
+                .zip(&frequencies)
+                .map(|(&j, y)| symmetric(i, j) / (x * y).sqrt())
+                .collect()
+        })
+        .collect();
+    let (values, vectors) = symmetric_eigen(matrix);
+    let distance = values
+        .iter()
+        .enumerate()
+        .map(|(k, value)| {
+            let weight: f64 = vectors
+                .iter()
+                .zip(&frequencies)
+                .map(|(row, frequency)| frequency * row[k] * row[k])
+                .sum();
+            -weight * value.ln()
+        })
+        .sum();
+    saturated_to_infinity(distance)
+}
+
+/// Returns the eigenvalues of the symmetric `matrix` and the matrix of its eigenvectors,
+/// as columns, by the Jacobi eigenvalue algorithm.
+fn symmetric_eigen(mut matrix: Vec<Vec<f64>>) -> (Vec<f64>, Vec<Vec<f64>>) {
+    let n = matrix.len();
+    let mut vectors: Vec<Vec<f64>> = (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+        .collect();
+    for _ in 0..100 {
+        let off_diagonal: f64 = (0..n)
+            .flat_map(|i| (0..n).filter(move |&j| j != i).map(move |j| (i, j)))
+            .map(|(i, j)| matrix[i][j] * matrix[i][j])
+            .sum();
+        if off_diagonal < 1e-30 {
+            break;
+        }
+        for p in 0..n {
+            for q in p + 1..n {
+                if matrix[p][q] == 0.0 {
+                    continue;
+                }
+                // Rotation zeroing the element (p, q)
+                let theta = (matrix[q][q] - matrix[p][p]) / (2.0 * matrix[p][q]);
+                let t = theta.signum() / (theta.abs() + theta.hypot(1.0));
+                let c = 1.0 / t.hypot(1.0);
+                let s = t * c;
+                for row in matrix.iter_mut().chain(vectors.iter_mut()) {
+                    let (x, y) = (row[p], row[q]);
+                    row[p] = c * x - s * y;
+                    row[q] = s * x + c * y;
+                }
+                let (row_p, row_q) = (matrix[p].clone(), matrix[q].clone());
+                matrix[p] = row_p
+                    .iter()
+                    .zip(&row_q)
+                    .map(|(x, y)| c * x - s * y)
+                    .collect();
+                matrix[q] = row_p
+                    .iter()
+                    .zip(&row_q)
+                    .map(|(x, y)| s * x + c * y)
+                    .collect();
+            }
+        }
+    }
+    ((0..n).map(|i| matrix[i][i]).collect(), vectors)
+}
+
+/// Returns the determinant of the square `matrix`, by Gaussian elimination.
+fn determinant(mut matrix: Vec<Vec<f64>>) -> f64 {
+    let n = matrix.len();
+    let mut determinant = 1.0;
+    for column in 0..n {
+        let pivot = (column..n)
+            .max_by(|&a, &b| matrix[a][column].abs().total_cmp(&matrix[b][column].abs()))
+            .expect("the column has rows");
+        if matrix[pivot][column] == 0.0 {
+            return 0.0;
+        }
+        if pivot != column {
+            matrix.swap(pivot, column);
+            determinant = -determinant;
+        }
+        determinant *= matrix[column][column];
+        let (above, below) = matrix.split_at_mut(column + 1);
+        let pivot_row = &above[column];
+        for row in below {
+            let factor = row[column] / pivot_row[column];
+            for (value, pivot_value) in row[column..].iter_mut().zip(&pivot_row[column..]) {
+                *value -= factor * pivot_value;
+            }
+        }
+    }
+    determinant
+}
+
+/// Names of the [AlignmentStats::site_counts]: sites with a symbol in either sequence,
+/// sites with a symbol in both, sites with a gap in one, transitions and transversions.
+pub const SITE_COUNTS: [&str; 5] = [
+    "total_length",
+    "common_length",
+    "gap_length",
+    "transitions",
+    "transversions",
+];
+
+/// Distance computed from the [AlignmentStats] of a pair of sequences.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Distance {
+    /// Uncorrected p-distance.
+    P,
+    /// Uncorrected p-distance, counting the gaps as differences.
+    PGaps,
+    JukesCantor,
+    Kimura2P,
+    Tamura3P,
+    TamuraNei,
+    /// Jukes-Cantor distance with the gamma shape parameter.
+    JukesCantorGamma(f64),
+    /// Kimura's two parameter distance with the gamma shape parameter.
+    Kimura2PGamma(f64),
+    /// Tamura-Nei distance with the gamma shape parameter.
+    TamuraNeiGamma(f64),
+    /// LogDet distance of the [DivergenceMatrix].
+    LogDet,
+    /// Paralinear distance of the [DivergenceMatrix].
+    Paralinear,
+    F81,
+    Hky85,
+    /// General time reversible distance of the [DivergenceMatrix].
+    Gtr,
+    /// Maximum likelihood distance of the [DivergenceMatrix].
+    MaximumLikelihood(MaximumLikelihood),
+    /// Synonymous distance of the [CodonStats].
+    Synonymous,
+    /// Nonsynonymous distance of the [CodonStats].
+    NonSynonymous,
+    /// Ratio of the nonsynonymous and synonymous distances.
+    DnDs,
+}
+
+/// Distances returned when no others are selected.
+pub const DEFAULT_DISTANCES: [Distance; 4] = [
+    Distance::P,
+    Distance::JukesCantor,
+    Distance::Kimura2P,
+    Distance::PGaps,
+];
+
+impl Distance {
+    /// Computes the distance from `alignment_stats`.
+    pub fn compute(self, alignment_stats: &AlignmentStats) -> f64 {
+        match self {
+            Distance::P => alignment_stats.pdistance(),
+            Distance::PGaps => alignment_stats.pdistance_counting_gaps(),
+            Distance::JukesCantor => alignment_stats.jukes_cantor_distance(),
+            Distance::Kimura2P => alignment_stats.kimura2p_distance(),
+            Distance::Tamura3P => alignment_stats.tamura3p_distance(),
+            Distance::TamuraNei => alignment_stats.tamura_nei_distance(),
+            Distance::JukesCantorGamma(alpha) => alignment_stats.jukes_cantor_gamma_distance(alpha),
+            Distance::Kimura2PGamma(alpha) => alignment_stats.kimura2p_gamma_distance(alpha),
+            Distance::TamuraNeiGamma(alpha) => alignment_stats.tamura_nei_gamma_distance(alpha),
+            Distance::LogDet => alignment_stats.divergence.logdet_distance(),
+            Distance::Paralinear => alignment_stats.divergence.paralinear_distance(),
+            Distance::F81 => alignment_stats.f81_distance(),
+            Distance::Hky85 => alignment_stats.hky85_distance(),
+            Distance::Gtr => alignment_stats.gtr_distance(),
+            Distance::MaximumLikelihood(estimator) => estimator.distance(
+                &alignment_stats.divergence.counts,
+                alignment_stats.model_frequencies(),
+            ),
+            Distance::Synonymous => alignment_stats.codon_estimate(CodonStats::synonymous_distance),
+            Distance::NonSynonymous => {
+                alignment_stats.codon_estimate(CodonStats::nonsynonymous_distance)
+            }
+            Distance::DnDs => alignment_stats.codon_estimate(CodonStats::dn_ds),
+        }
+    }
+
+    /// Computes the variance of the distance from `alignment_stats`: binomial for the p-distances,
+    /// by the curvature of the likelihood for the maximum likelihood ones,
+    /// by the delta method for the others.
+    ///
+    /// Is NaN or infinite where the distance is.
+    pub fn variance(self, alignment_stats: &AlignmentStats) -> f64 {
+        let distance = self.compute(alignment_stats);
+        if !distance.is_finite() {
+            return distance;
+        }
+        let stats = alignment_stats;
+        match self {
+            Distance::P => binomial_variance(stats.pdistance(), stats.compared_length()),
+            Distance::PGaps => {
+                binomial_variance(stats.pdistance_counting_gaps(), stats.total_length)
+            }
+            Distance::JukesCantor => stats.delta_method_variance(stats.jukes_cantor_gradient(None)),
+            Distance::Kimura2P => stats.delta_method_variance(stats.kimura2p_gradient(None)),
+            Distance::Tamura3P => stats.delta_method_variance(stats.tamura3p_gradient()),
+            Distance::TamuraNei => stats.delta_method_variance(stats.tamura_nei_gradient(None)),
+            Distance::JukesCantorGamma(alpha) => {
+                stats.delta_method_variance(stats.jukes_cantor_gradient(Some(alpha)))
+            }
+            Distance::Kimura2PGamma(alpha) => {
+                stats.delta_method_variance(stats.kimura2p_gradient(Some(alpha)))
+            }
+            Distance::TamuraNeiGamma(alpha) => {
+                stats.delta_method_variance(stats.tamura_nei_gradient(Some(alpha)))
+            }
+            Distance::LogDet => stats.divergence.logdet_variance(),
+            Distance::Paralinear => stats.divergence.paralinear_variance(),
+            Distance::F81 => stats.delta_method_variance(stats.f81_gradient()),
+            Distance::Hky85 => stats.delta_method_variance(stats.hky85_gradient()),
+            Distance::Gtr => stats.divergence.gtr_variance(stats.fixed_frequencies()),
+            Distance::MaximumLikelihood(estimator) => {
+                estimator.variance(&stats.divergence.counts, stats.model_frequencies())
+            }
+            Distance::Synonymous => stats.codon_estimate(CodonStats::synonymous_variance),
+            Distance::NonSynonymous => stats.codon_estimate(CodonStats::nonsynonymous_variance),
+            Distance::DnDs => stats.codon_estimate(CodonStats::dn_ds_variance),
+        }
+    }
+
+    /// Computes the standard error of the distance from `alignment_stats`.
+    pub fn standard_error(self, alignment_stats: &AlignmentStats) -> f64 {
+        self.variance(alignment_stats).sqrt()
+    }
+}
+
+/// Variance of the proportion `p` of `n` sites.
+fn binomial_variance(p: f64, n: usize) -> f64 {
+    p * (1.0 - p) / n as f64
+}
+
+/// Returns `numerator / denominator`, or 0 if `numerator` is 0.
+///
+/// The terms of the distance formulas vanish with the frequencies they are divided by.
+fn ratio(numerator: f64, denominator: f64) -> f64 {
+    if numerator == 0.0 {
+        0.0
+    } else {
+        numerator / denominator
+    }
+}
+
+/// Returns `-weight * ln(argument)`, or 0 if `weight` is 0.
+///
+/// With gamma distributed rates of the shape `alpha` the logarithm becomes
+/// `alpha * (argument^(-1 / alpha) - 1)`.
+fn rate_term(weight: f64, argument: f64, gamma_shape: Option<f64>) -> f64 {
+    match gamma_shape {
+        _ if weight == 0.0 => 0.0,
+        Some(_) if argument <= 0.0 => f64::INFINITY,
+        Some(alpha) => weight * alpha * (argument.powf(-1.0 / alpha) - 1.0),
+        None => -weight * argument.ln(),
+    }
+}
+
+/// Returns the gradient of [rate_term] by proportions, where the derivatives
+/// of `argument` by them are `coefficients`.
+fn rate_term_gradient(
+    weight: f64,
+    argument: f64,
+    coefficients: [f64; 3],
+    gamma_shape: Option<f64>,
+) -> [f64; 3] {
+    if weight == 0.0 {
+        return [0.0; 3];
+    }
+    let slope = match gamma_shape {
+        Some(alpha) => -weight * argument.powf(-1.0 / alpha - 1.0),
+        None => -weight / argument,
+    };
+    coefficients.map(|coefficient| slope * coefficient)
+}
+
+/// Returns the derivative of `f` at `x` by central differences.
+fn derivative(f: impl Fn(f64) -> f64, x: f64) -> f64 {
+    const STEP: f64 = 1e-6;
+    (f(x + STEP) - f(x - STEP)) / (2.0 * STEP)
+}
+
+/// Returns the weight `1 - Σπ²` of the F81 distance for the base `frequencies` π.
+fn f81_weight(frequencies: [f64; 4]) -> f64 {
+    1.0 - frequencies.iter().map(|f| f * f).sum::<f64>()
+}
+
+/// Computes the HKY85 distance for the proportions `p` of transitions and `q` of transversions
+/// and the base `frequencies`.
+///
+/// Under HKY85 with the transversion rate `β` and the transition/transversion ratio `κ`,
+/// `q` gives `βt` like in TN93 and `p` then gives `κβt`, found by bisection.
+fn hky85(p: f64, q: f64, [a, c, g, t]: [f64; 4]) -> f64 {
+    let (purines, pyrimidines) = (a + g, c + t);
+    let decay = 1.0 - ratio(q, 2.0 * purines * pyrimidines);
+    if decay <= 0.0 {
+        return f64::INFINITY;
+    }
+    let transversion_time = -decay.ln();
+    // Expected proportion of transitions within a class of bases, as a function of κβt
+    let class_transitions = |weight: f64, class: f64, other: f64, transition_time: f64| {
+        if weight == 0.0 {
+            return 0.0;
+        }
+        let transition_decay = (-(class * transition_time + other * transversion_time)).exp();
+        weight * (1.0 + (1.0 / class - 1.0) * decay - transition_decay / class)
+    };
+    let transitions = |transition_time| {
+        class_transitions(2.0 * a * g, purines, pyrimidines, transition_time)
+            + class_transitions(2.0 * c * t, pyrimidines, purines, transition_time)
+    };
+    let (mut low, mut high) = (0.0, 1.0);
+    if p <= transitions(low) {
+        high = low;
+    } else if p >= transitions(f64::INFINITY) {
+        return f64::INFINITY;
+    }
+    while transitions(high) < p {
+        low = high;
+        high *= 2.0;
+    }
+    for _ in 0..100 {
+        let middle = 0.5 * (low + high);
+        if transitions(middle) < p {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    let transition_time = 0.5 * (low + high);
+    2.0 * (purines * pyrimidines * transversion_time + (a * g + c * t) * transition_time)
+}
+
+/// Returns the sum of two gradients.
+fn add_gradients(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+/// Maps the NaN of a logarithm of a negative number to infinity.
+///
+/// So is the difference of two infinite logarithms of zero.
+fn saturated_to_infinity(distance: f64) -> f64 {
+    if distance.is_nan() {
+        f64::INFINITY
+    } else {
+        distance
+    }
+}
+
+// Sets of bases, with a bit for each of A, C, G and T
+const A: u8 = 0b0001;
+const C: u8 = 0b0010;
+const G: u8 = 0b0100;
+const T: u8 = 0b1000;
+
+/// Returns the set of the bases the IUPAC code `x` stands for, empty if it is not a base.
+fn base_set(x: u8) -> u8 {
+    match x.to_ascii_uppercase() {
+        b'A' => A,
+        b'C' => C,
+        b'G' => G,
+        b'T' | b'U' => T,
+        b'R' => A | G,
+        b'Y' => C | T,
+        b'S' => C | G,
+        b'W' => A | T,
+        b'K' => G | T,
+        b'M' => A | C,
+        b'B' => C | G | T,
+        b'D' => A | G | T,
+        b'H' => A | C | T,
+        b'V' => A | C | G,
+        _ => 0,
+    }
+}
+
+/// Iterator over the single bases of the set `bases`.
+fn bases(bases: u8) -> impl Iterator<Item = u8> {
+    IntoIterator::into_iter([A, C, G, T]).filter(move |&base| bases & base != 0)
+}
+
 enum SymbolType {
     Gap,
+    /// N, ? and the symbols that are not IUPAC codes.
     Missing,
-    Nucleotide(NucleotideType),
-}
-
-enum NucleotideType {
-    Purine,
-    Pyrimidine,
-    Unknown,
+    /// Set of the bases the symbol stands for.
+    Nucleotide(u8),
 }
 
-fn classify(x: u8) -> SymbolType {
-    use NucleotideType::*;
+fn classify(x: u8, ambiguity: AmbiguityPolicy) -> SymbolType {
     use SymbolType::*;
     match x {
         b'-' => Gap,
         b'n' | b'N' | b'?' => Missing,
-        b'a' | b'A' | b'g' | b'G' => Nucleotide(Purine),
-        b'c' | b'C' | b't' | b'T' => Nucleotide(Pyrimidine),
-        _ => Nucleotide(Unknown),
+        _ => match base_set(x) {
+            0 => Missing,
+            bases if ambiguity == AmbiguityPolicy::Missing && bases.count_ones() > 1 => Missing,
+            bases => Nucleotide(bases),
+        },
     }
 }
 
-/// Returns 4 distances between `target` and `query`.
+/// Returns the `distances` between `target` and `query`,
+/// counting the sites by the `options`.
 ///
-/// Performs alignment.
-pub fn seq_distances(aligner: &Aligner, target: &str, query: &str) -> [f64; 4] {
-    let alignment = aligner.align(target.as_bytes(), query.as_bytes());
-    let mut alignment_stats = AlignmentStats::new();
-    alignment
-        .common_path_iter()
-        .for_each(|pair| alignment_stats.update(pair));
-    [
-        alignment_stats.pdistance(),
-        alignment_stats.jukes_cantor_distance(),
-        alignment_stats.kimura2p_distance(),
-        alignment_stats.pdistance_counting_gaps(),
-    ]
+/// Performs alignment, in the workspace of the current thread.
+/// # Errors
+/// Returns [ScoreOverflow] if the alignment score could overflow.
+pub fn seq_distances(
+    aligner: &Aligner,
+    target: &str,
+    query: &str,
+    distances: &[Distance],
+    options: &DistanceOptions,
+) -> Result<Vec<f64>, ScoreOverflow> {
+    seq_distances_oriented(aligner, target, query, distances, options)
+        .map(|(distances, _)| distances)
+}
+
+/// Returns the `distances` between `target` and `query`, and the orientation of `query`
+/// used for them.
+///
+/// Performs alignment like [seq_distances], with the reverse complement of `query`
+/// if the `aligner` detects the orientation.
+/// # Errors
+/// Returns [ScoreOverflow] if the alignment score could overflow.
+pub fn seq_distances_oriented(
+    aligner: &Aligner,
+    target: &str,
+    query: &str,
+    distances: &[Distance],
+    options: &DistanceOptions,
+) -> Result<(Vec<f64>, Orientation), ScoreOverflow> {
+    let (alignment_stats, orientation) = aligned_pair_stats(aligner, target, query, options)?;
+    let distances = distances
+        .iter()
+        .map(|distance| distance.compute(&alignment_stats))
+        .collect();
+    Ok((distances, orientation))
+}
+
+/// Returns the `distances` between `target` and `query` and their standard errors.
+///
+/// Performs alignment like [seq_distances].
+/// # Errors
+/// Returns [ScoreOverflow] if the alignment score could overflow.
+pub fn seq_distances_with_errors(
+    aligner: &Aligner,
+    target: &str,
+    query: &str,
+    distances: &[Distance],
+    options: &DistanceOptions,
+) -> Result<(Vec<f64>, Vec<f64>), ScoreOverflow> {
+    let (alignment_stats, _) = aligned_pair_stats(aligner, target, query, options)?;
+    Ok(estimates(&alignment_stats, distances))
+}
+
+/// Computes the `distances` and their standard errors from `alignment_stats`.
+fn estimates(alignment_stats: &AlignmentStats, distances: &[Distance]) -> (Vec<f64>, Vec<f64>) {
+    distances
+        .iter()
+        .map(|distance| {
+            (
+                distance.compute(alignment_stats),
+                distance.standard_error(alignment_stats),
+            )
+        })
+        .unzip()
+}
+
+/// Aligns `target` and `query` in the workspace of the current thread and counts
+/// the common part of the alignment by the `options`.
+///
+/// Returns the counts and the orientation of `query` in the alignment.
+/// # Errors
+/// Returns [ScoreOverflow] if the alignment score could overflow.
+fn aligned_pair_stats(
+    aligner: &Aligner,
+    target: &str,
+    query: &str,
+    options: &DistanceOptions,
+) -> Result<(AlignmentStats, Orientation), ScoreOverflow> {
+    let options = options.with_frequencies_of(&[target], &[query]);
+    let mut alignment_stats = AlignmentStats::with_options(&options);
+    let orientation = WORKSPACE.with(|workspace| {
+        REVERSE_QUERY.with(|reverse| {
+            aligner
+                .align_oriented(
+                    &mut workspace.borrow_mut(),
+                    target.as_bytes(),
+                    query.as_bytes(),
+                    &mut reverse.borrow_mut(),
+                )
+                .map(|(alignment, orientation)| {
+                    alignment
+                        .common_path_iter()
+                        .for_each(|pair| alignment_stats.update(pair));
+                    orientation
+                })
+        })
+    })?;
+    Ok((alignment_stats, orientation))
 }
 
 // Returns true if the character is part of a meaningful part of a sequences
-fn is_nucleotide(c: char) -> bool {
-    !matches!(c, '-' | 'n' | 'N' | '?')
-}
-
-// Returns the inclusive boundaries of the common non-gap part of given sequences
-fn common_content(target: &str, query: &str) -> Option<(usize, usize)> {
-    let target_start = target.find(is_nucleotide)?;
-    let query_start = query.find(is_nucleotide)?;
-    let target_end = target.rfind(is_nucleotide)?;
-    let query_end = query.rfind(is_nucleotide)?;
-    let start = usize::max(target_start, query_start);
-    let end = usize::min(target_end, query_end);
-    if (end >= start) {
+fn is_nucleotide(c: char, ambiguity: AmbiguityPolicy) -> bool {
+    c.is_ascii() && matches!(classify(c as u8, ambiguity), SymbolType::Nucleotide(_))
+}
+
+// Returns the inclusive boundaries of the common part of given sequences
+// between their first and last sites by `is_site`
+// from their positioned symbols
+fn common_content<I>(
+    target: I,
+    query: I,
+    is_site: impl Fn(char) -> bool + Copy,
+) -> Option<(usize, usize)>
+where
+    I: DoubleEndedIterator<Item = (usize, char)> + Clone,
+{
+    let first = |symbols: I| symbols.clone().find(|&(_, c)| is_site(c)).map(|(i, _)| i);
+    let last = |symbols: I| symbols.rev().find(|&(_, c)| is_site(c)).map(|(i, _)| i);
+    let start = usize::max(first(target.clone())?, first(query.clone())?);
+    let end = usize::min(last(target)?, last(query)?);
+    if end >= start {
         Some((start, end))
     } else {
         None
     }
 }
 
-/// Returns 4 distances between `target` and `query`.
+/// Counts the common content of the aligned `target` and `query` by the `options`,
+/// at the selected codon positions, and their codons if the `options` have a reading frame.
 ///
-/// Expects aligned sequences.
-pub fn seq_distances_aligned(target: &str, query: &str) -> [f64; 4] {
-    let (start, end) = match common_content(target, query) {
-        None => return [f64::NAN; 4],
-        Some(x) => x,
-    };
-    let target = &target[start..=end];
-    let query = &query[start..=end];
-    let mut alignment_stats = AlignmentStats::new();
-    target
+/// Returns [None] if there is none.
+fn common_stats(target: &str, query: &str, options: &DistanceOptions) -> Option<AlignmentStats> {
+    let options = options.with_frequencies_of(&[target], &[query]);
+    let (start, end) = common_content(target.char_indices(), query.char_indices(), |c| {
+        is_nucleotide(c, options.ambiguity)
+    })?;
+    let mut alignment_stats = AlignmentStats::with_options(&options);
+    target[start..=end]
         .bytes()
-        .zip(query.bytes())
-        .for_each(|pair| alignment_stats.update(pair));
-    [
-        alignment_stats.pdistance(),
-        alignment_stats.jukes_cantor_distance(),
-        alignment_stats.kimura2p_distance(),
-        alignment_stats.pdistance_counting_gaps(),
-    ]
-}
-
-pub fn seq_distances_p(target: &str, query: &str) -> f64 {
-    let (start, end) = match common_content(target, query) {
-        None => return f64::NAN,
-        Some(x) => x,
-    };
-    let target = &target[start..=end];
-    let query = &query[start..=end];
-    let mut alignment_stats = AlignmentStats::new();
-    target
-        .bytes()
-        .zip(query.bytes())
-        .for_each(|pair| alignment_stats.update(pair));
-
-    alignment_stats.pdistance()
-
+        .zip(query[start..=end].bytes())
+        .enumerate()
+        .filter(|&(i, _)| options.counts_column(start + i))
+        .for_each(|(_, pair)| alignment_stats.update(pair));
+    alignment_stats.codons = options
+        .reading_frame
+        .map(|frame| CodonStats::new(target, query, &frame));
+    Some(alignment_stats)
 }
 
-pub fn seq_distances_p_gaps(target: &str, query: &str) -> f64 {
-    let (start, end) = match common_content(target, query) {
-        None => return f64::NAN,
-        Some(x) => x,
-    };
-    let target = &target[start..=end];
-    let query = &query[start..=end];
-    let mut alignment_stats = AlignmentStats::new();
-    target
-        .bytes()
-        .zip(query.bytes())
-        .for_each(|pair| alignment_stats.update(pair));
+/// Returns the `distances` between `target` and `query`,
+/// counting the sites by the `options`.
+///
+/// Expects aligned sequences.
+pub fn seq_distances_aligned(
+    target: &str,
+    query: &str,
+    distances: &[Distance],
+    options: &DistanceOptions,
+) -> Vec<f64> {
+    match common_stats(target, query, options) {
+        None => vec![f64::NAN; distances.len()],
+        Some(alignment_stats) => distances
+            .iter()
+            .map(|distance| distance.compute(&alignment_stats))
+            .collect(),
+    }
+}
 
-    alignment_stats.pdistance_counting_gaps()
+/// Returns the `distances` between `target` and `query` and their standard errors.
+///
+/// Expects aligned sequences.
+pub fn seq_distances_aligned_with_errors(
+    target: &str,
+    query: &str,
+    distances: &[Distance],
+    options: &DistanceOptions,
+) -> (Vec<f64>, Vec<f64>) {
+    match common_stats(target, query, options) {
+        None => (
+            vec![f64::NAN; distances.len()],
+            vec![f64::NAN; distances.len()],
+        ),
+        Some(alignment_stats) => estimates(&alignment_stats, distances),
+    }
+}
 
+/// Returns the [AlignmentStats::site_counts] of the common content of `target` and `query`,
+/// counted by the `options`.
+///
+/// Expects aligned sequences.
+pub fn seq_site_counts_aligned(target: &str, query: &str, options: &DistanceOptions) -> [f64; 5] {
+    common_stats(target, query, options).map_or([0.0; 5], |stats| stats.site_counts())
 }
 
-pub fn seq_distances_jukes_cantor(target: &str, query: &str) -> f64 {
-    let (start, end) = match common_content(target, query) {
-        None => return f64::NAN,
-        Some(x) => x,
-    };
-    let target = &target[start..=end];
-    let query = &query[start..=end];
-    let mut alignment_stats = AlignmentStats::new();
-    target
-        .bytes()
-        .zip(query.bytes())
-        .for_each(|pair| alignment_stats.update(pair));
+pub fn seq_distances_p(target: &str, query: &str, options: &DistanceOptions) -> f64 {
+    common_stats(target, query, options).map_or(f64::NAN, |stats| stats.pdistance())
+}
 
-    alignment_stats.jukes_cantor_distance()
+pub fn seq_distances_p_gaps(target: &str, query: &str, options: &DistanceOptions) -> f64 {
+    common_stats(target, query, options).map_or(f64::NAN, |stats| stats.pdistance_counting_gaps())
+}
 
+pub fn seq_distances_jukes_cantor(target: &str, query: &str, options: &DistanceOptions) -> f64 {
+    common_stats(target, query, options).map_or(f64::NAN, |stats| stats.jukes_cantor_distance())
 }
 
-pub fn seq_distances_kimura2p(target: &str, query: &str) -> f64 {
-    let (start, end) = match common_content(target, query) {
-        None => return f64::NAN,
-        Some(x) => x,
-    };
-    let target = &target[start..=end];
-    let query = &query[start..=end];
-    let mut alignment_stats = AlignmentStats::new();
-    target
-        .bytes()
-        .zip(query.bytes())
-        .for_each(|pair| alignment_stats.update(pair));
+pub fn seq_distances_kimura2p(target: &str, query: &str, options: &DistanceOptions) -> f64 {
+    common_stats(target, query, options).map_or(f64::NAN, |stats| stats.kimura2p_distance())
+}
 
-    alignment_stats.kimura2p_distance()
+pub fn seq_distances_tamura3p(target: &str, query: &str, options: &DistanceOptions) -> f64 {
+    common_stats(target, query, options).map_or(f64::NAN, |stats| stats.tamura3p_distance())
+}
 
+pub fn seq_distances_tamura_nei(target: &str, query: &str, options: &DistanceOptions) -> f64 {
+    common_stats(target, query, options).map_or(f64::NAN, |stats| stats.tamura_nei_distance())
 }
 
-/// Creates (n, 4) vector of distances between `targets` and `queries`.
+/// Creates (n, k) vector of the k `distances` between `targets` and `queries`.
 ///
 /// Outer iteration over `targets`.
 /// Inner iteration over `queries`.
-/// Performs sequence-to-sequence alignment
-pub fn make_distance_array(aligner: &Aligner, targets: &[&str], queries: &[&str]) -> Vec<Vec<f64>> {
+/// Performs sequence-to-sequence alignment,
+/// in the orientation of each query giving the better alignment if the `aligner` detects it.
+/// # Errors
+/// Returns [ScoreOverflow] if the score of any alignment could overflow.
+pub fn make_distance_array(
+    aligner: &Aligner,
+    targets: &[&str],
+    queries: &[&str],
+    distances: &[Distance],
+    options: &DistanceOptions,
+) -> Result<Vec<Vec<f64>>, ScoreOverflow> {
+    let options = &options.with_frequencies_of(targets, queries);
     targets
         .par_iter()
         .flat_map_iter(|target| {
             queries
                 .iter()
-                .map(move |query| Vec::from(seq_distances(aligner, target, query)))
+                .map(move |query| seq_distances(aligner, target, query, distances, options))
         })
         .collect()
 }
 
-pub fn make_distance_array_aligned(targets: &[&str], queries: &[&str]) -> Vec<Vec<f64>> {
+/// Creates (n, k) vector of the k `distances` between aligned `targets` and `queries`.
+pub fn make_distance_array_aligned(
+    targets: &[&str],
+    queries: &[&str],
+    distances: &[Distance],
+    options: &DistanceOptions,
+) -> Vec<Vec<f64>> {
+    with_deletion(targets, queries, options, |targets, queries, options| {
+        let options = &options.with_frequencies_of(targets, queries);
+        targets
+            .par_iter()
+            .flat_map_iter(|target| {
+                queries
+                    .iter()
+                    .map(move |query| seq_distances_aligned(target, query, distances, options))
+            })
+            .collect()
+    })
+}
+
+/// Creates (n, k) vector of the k `distances` between the aligned `targets` and `queries`,
+/// counting their resampled `columns`, which may repeat.
+///
+/// Each pair only counts the columns within its common content, like in
+/// [make_distance_array_aligned]. The [Deletion] of the `options` should be already applied,
+/// and with a reading frame the `columns` should be whole codons of it.
+pub fn make_distance_array_resampled(
+    targets: &[Vec<char>],
+    queries: &[Vec<char>],
+    columns: &[usize],
+    distances: &[Distance],
+    options: &DistanceOptions,
+) -> Vec<Vec<f64>> {
+    let options = &if options.frequencies == BaseFrequencies::WholeSet {
+        let sequences: Vec<String> = targets
+            .iter()
+            .chain(queries)
+            .map(|sequence| resample(sequence, columns))
+            .collect();
+        let sequences: Vec<&str> = sequences.iter().map(String::as_str).collect();
+        let (targets, queries) = sequences.split_at(targets.len());
+        options.with_frequencies_of(targets, queries)
+    } else {
+        *options
+    };
     targets
         .par_iter()
         .flat_map_iter(|target| {
-            queries
-                .iter()
-                .map(move |query| Vec::from(seq_distances_aligned(target, query)))
+            queries.iter().map(move |query| {
+                match resampled_stats(target, query, columns, options) {
+                    None => vec![f64::NAN; distances.len()],
+                    Some(alignment_stats) => distances
+                        .iter()
+                        .map(|distance| distance.compute(&alignment_stats))
+                        .collect(),
+                }
+            })
         })
         .collect()
 }
 
+/// Returns the `columns` of the `sequence`.
+fn resample(sequence: &[char], columns: &[usize]) -> String {
+    columns.iter().map(|&i| sequence[i]).collect()
+}
+
+/// Counts the resampled `columns` of the aligned `target` and `query`
+/// within their common content by the `options`.
+///
+/// Returns [None] if there is none.
+fn resampled_stats(
+    target: &[char],
+    query: &[char],
+    columns: &[usize],
+    options: &DistanceOptions,
+) -> Option<AlignmentStats> {
+    let (start, end) = common_content(
+        target.iter().copied().enumerate(),
+        query.iter().copied().enumerate(),
+        |c| is_nucleotide(c, options.ambiguity),
+    )?;
+    let symbol = |c: char| if c.is_ascii() { c as u8 } else { u8::MAX };
+    let mut alignment_stats = AlignmentStats::with_options(options);
+    columns
+        .iter()
+        .filter(|&&i| (start..=end).contains(&i) && options.counts_column(i))
+        .for_each(|&i| alignment_stats.update((symbol(target[i]), symbol(query[i]))));
+    alignment_stats.codons = options.reading_frame.map(|frame| {
+        CodonStats::new(
+            &resample(target, columns),
+            &resample(query, columns),
+            &ReadingFrame { offset: 0, ..frame },
+        )
+    });
+    Some(alignment_stats)
+}
+
+/// (n, k) vectors of distances and of their standard errors.
+pub type DistanceArrays = (Vec<Vec<f64>>, Vec<Vec<f64>>);
+
+/// Creates (n, k) vectors of the k `distances` between `targets` and `queries`
+/// and of their standard errors.
+///
+/// Performs alignment like [make_distance_array].
+/// # Errors
+/// Returns [ScoreOverflow] if the score of any alignment could overflow.
+pub fn make_distance_array_with_errors(
+    aligner: &Aligner,
+    targets: &[&str],
+    queries: &[&str],
+    distances: &[Distance],
+    options: &DistanceOptions,
+) -> Result<DistanceArrays, ScoreOverflow> {
+    let options = &options.with_frequencies_of(targets, queries);
+    let rows: Vec<_> = targets
+        .par_iter()
+        .flat_map_iter(|target| {
+            queries.iter().map(move |query| {
+                seq_distances_with_errors(aligner, target, query, distances, options)
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(rows.into_iter().unzip())
+}
+
+/// Creates (n, k) vectors of the k `distances` between aligned `targets` and `queries`
+/// and of their standard errors.
+pub fn make_distance_array_aligned_with_errors(
+    targets: &[&str],
+    queries: &[&str],
+    distances: &[Distance],
+    options: &DistanceOptions,
+) -> DistanceArrays {
+    with_deletion(targets, queries, options, |targets, queries, options| {
+        let options = &options.with_frequencies_of(targets, queries);
+        targets
+            .par_iter()
+            .flat_map_iter(|target| {
+                queries.iter().map(move |query| {
+                    seq_distances_aligned_with_errors(target, query, distances, options)
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .unzip()
+    })
+}
+
+/// Creates (n, 5) vector of the [AlignmentStats::site_counts] of aligned `targets` and `queries`.
+pub fn make_site_count_array_aligned(
+    targets: &[&str],
+    queries: &[&str],
+    options: &DistanceOptions,
+) -> Vec<Vec<f64>> {
+    with_deletion(targets, queries, options, |targets, queries, options| {
+        targets
+            .par_iter()
+            .flat_map_iter(|target| {
+                queries
+                    .iter()
+                    .map(move |query| seq_site_counts_aligned(target, query, options).to_vec())
+            })
+            .collect()
+    })
+}
+
+/// Calls `f` with the aligned `targets` and `queries` left after the [Deletion] of the `options`,
+/// and the `options` with the reading frame of the columns left.
+///
+/// [Deletion::Complete] drops every column where any of the sequences is not a nucleotide
+/// or has ended, with the rest of its codon if the `options` have a reading frame.
+pub fn with_deletion<R>(
+    targets: &[&str],
+    queries: &[&str],
+    options: &DistanceOptions,
+    f: impl FnOnce(&[&str], &[&str], &DistanceOptions) -> R,
+) -> R {
+    let offset = options.reading_frame.map(|frame| frame.offset);
+    with_deletion_by(
+        targets,
+        queries,
+        options.deletion,
+        offset,
+        |c| is_nucleotide(c, options.ambiguity),
+        |targets, queries, offset| {
+            let reading_frame = options
+                .reading_frame
+                .zip(offset)
+                .map(|(frame, offset)| ReadingFrame { offset, ..frame });
+            let options = DistanceOptions {
+                reading_frame,
+                ..*options
+            };
+            f(targets, queries, &options)
+        },
+    )
+}
+
+/// Calls `f` with the aligned `targets` and `queries` left after the `deletion`,
+/// which tells the sites by `is_site` and keeps or drops whole codons from the `offset`, if any,
+/// and with the offset of the first codon among the columns left.
+fn with_deletion_by<R>(
+    targets: &[&str],
+    queries: &[&str],
+    deletion: Deletion,
+    offset: Option<usize>,
+    is_site: impl Fn(char) -> bool,
+    f: impl FnOnce(&[&str], &[&str], Option<usize>) -> R,
+) -> R {
+    if deletion == Deletion::Pairwise {
+        return f(targets, queries, offset);
+    }
+    let sequences: Vec<Vec<char>> = targets
+        .iter()
+        .chain(queries)
+        .map(|sequence| sequence.chars().collect())
+        .collect();
+    let width = sequences.iter().map(Vec::len).max().unwrap_or(0);
+    let mut kept: Vec<bool> = (0..width)
+        .map(|i| {
+            sequences
+                .iter()
+                .all(|sequence| sequence.get(i).is_some_and(|&c| is_site(c)))
+        })
+        .collect();
+    let offset = offset.map(|offset| {
+        let offset = usize::min(offset, width);
+        for codon in kept[offset..].chunks_mut(3) {
+            if codon.contains(&false) {
+                codon.fill(false);
+            }
+        }
+        kept[..offset].iter().filter(|&&kept| kept).count()
+    });
+    let kept: Vec<usize> = (0..width).filter(|&i| kept[i]).collect();
+    let sequences: Vec<String> = sequences
+        .iter()
+        .map(|sequence| kept.iter().map(|&i| sequence[i]).collect())
+        .collect();
+    let sequences: Vec<&str> = sequences.iter().map(String::as_str).collect();
+    let (targets, queries) = sequences.split_at(targets.len());
+    f(targets, queries, offset)
+}
+
 #[cfg(test)]
 mod test_super {
 
@@ -301,13 +1714,509 @@ mod test_super {
         assert_eq!(alignment_stats.pdistance_counting_gaps(), 2.0 / 9.0);
     }
 
+    fn stats(target: &str, query: &str) -> AlignmentStats {
+        let mut alignment_stats = AlignmentStats::new();
+        target
+            .bytes()
+            .zip(query.bytes())
+            .for_each(|pair| alignment_stats.update(pair));
+        alignment_stats
+    }
+
+    #[test]
+    fn test_tamura_distances() {
+        // Equal base frequencies and transition rates reduce both to Kimura's distance
+        let alignment_stats = stats("ACGTACGTACGTACGTACGT", "GTACCAGTACGTACGTACGT");
+        assert_eq!(alignment_stats.base_frequencies(), [0.25; 4]);
+        let kimura2p = alignment_stats.kimura2p_distance();
+        assert!((alignment_stats.tamura3p_distance() - kimura2p).abs() < 1e-12);
+        assert!((alignment_stats.tamura_nei_distance() - kimura2p).abs() < 1e-12);
+
+        let alignment_stats = stats("AAAAGGGCCTAAGAAGCATT", "AAGAGGGCTTAAGCAGCATA");
+        assert_eq!(
+            (
+                alignment_stats.purine_transitions,
+                alignment_stats.pyrimidine_transitions,
+                alignment_stats.transversions
+            ),
+            (1.0, 1.0, 2.0)
+        );
+        assert!((alignment_stats.tamura3p_distance() - 0.2345908508842621).abs() < 1e-12);
+        assert!((alignment_stats.tamura_nei_distance() - 0.2458551054145633).abs() < 1e-12);
+
+        // Only A and T, without transitions
+        let alignment_stats = stats("AATTA", "AATTT");
+        assert!(alignment_stats.tamura_nei_distance().is_finite());
+        assert!(alignment_stats.tamura3p_distance().is_finite());
+        assert_eq!(stats("ACGT", "TGCA").tamura_nei_distance(), f64::INFINITY);
+        assert!(stats("--", "AC").tamura3p_distance().is_nan());
+    }
+
+    #[test]
+    fn test_nucleotide_models() {
+        // Equal base frequencies reduce F81 to Jukes-Cantor and HKY85 to Kimura's distance
+        let alignment_stats = stats("ACGTACGTACGTACGTACGT", "GTACCAGTACGTACGTACGT");
+        assert!(
+            (alignment_stats.f81_distance() - alignment_stats.jukes_cantor_distance()).abs()
+                < 1e-12
+        );
+        assert!(
+            (alignment_stats.hky85_distance() - alignment_stats.kimura2p_distance()).abs() < 1e-9
+        );
+
+        // GTR reduces to Jukes-Cantor for equally frequent differences
+        let mut alignment_stats = AlignmentStats::new();
+        for x in *b"ACGT" {
+            for y in *b"ACGT" {
+                let sites = if x == y { 10 } else { 1 };
+                (0..sites).for_each(|_| alignment_stats.update((x, y)));
+            }
+        }
+        let jukes_cantor = -0.75 * (36.0_f64 / 52.0).ln();
+        assert!((alignment_stats.gtr_distance() - jukes_cantor).abs() < 1e-12);
+        assert!((alignment_stats.jukes_cantor_distance() - jukes_cantor).abs() < 1e-12);
+
+        // Expected proportions under HKY85 give back its distance, like TN93
+        let frequencies = [0.1, 0.2, 0.3, 0.4];
+        let [a, c, g, t] = frequencies;
+        let (purines, pyrimidines) = (a + g, c + t);
+        let (transversion_time, transition_time) = (0.2_f64, 0.6);
+        let decay = (-transversion_time).exp();
+        let class_transitions = |weight: f64, class: f64, other: f64| {
+            let transition_decay = (-(class * transition_time + other * transversion_time)).exp();
+            weight * (1.0 + (1.0 / class - 1.0) * decay - transition_decay / class)
+        };
+        let alignment_stats = AlignmentStats {
+            total_length: 1000,
+            common_length: 1000,
+            purine_transitions: 1000.0 * class_transitions(2.0 * a * g, purines, pyrimidines),
+            pyrimidine_transitions: 1000.0 * class_transitions(2.0 * c * t, pyrimidines, purines),
+            transversions: 1000.0 * 2.0 * purines * pyrimidines * (1.0 - decay),
+            options: DistanceOptions {
+                frequencies: BaseFrequencies::Fixed(frequencies),
+                ..DistanceOptions::default()
+            },
+            ..AlignmentStats::new()
+        };
+        let expected =
+            2.0 * (purines * pyrimidines * transversion_time + (a * g + c * t) * transition_time);
+        assert!((alignment_stats.hky85_distance() - expected).abs() < 1e-9);
+        assert!((alignment_stats.tamura_nei_distance() - expected).abs() < 1e-9);
+
+        let alignment_stats = stats("AAAAGGGCCTAAGAAGCATT", "AAGAGGGCTTAAGCAGCATA");
+        for distance in [Distance::F81, Distance::Hky85, Distance::Gtr] {
+            assert!(distance.compute(&alignment_stats) > alignment_stats.pdistance());
+            let variance = distance.variance(&alignment_stats);
+            assert!(variance.is_finite() && variance > 0.0);
+        }
+        assert_eq!(stats("ACGT", "TGCA").gtr_distance(), f64::INFINITY);
+        assert_eq!(stats("ACGT", "TGCA").hky85_distance(), f64::INFINITY);
+        assert!(stats("--", "AC").f81_distance().is_nan());
+        assert_eq!(stats("AACT", "AACT").gtr_distance(), 0.0);
+    }
+
+    #[test]
+    fn test_base_frequencies() {
+        let whole_set = DistanceOptions {
+            frequencies: BaseFrequencies::WholeSet,
+            ..DistanceOptions::default()
+        };
+        let targets = ["AACG", "AC-G"];
+        let queries = ["AATT"];
+        let frequencies = [5.0 / 11.0, 2.0 / 11.0, 2.0 / 11.0, 2.0 / 11.0];
+        assert_eq!(
+            whole_set
+                .with_frequencies_of(&targets, &queries)
+                .frequencies,
+            BaseFrequencies::Fixed(frequencies)
+        );
+        let fixed = DistanceOptions {
+            frequencies: BaseFrequencies::Fixed(frequencies),
+            ..DistanceOptions::default()
+        };
+        let distances = [Distance::F81, Distance::TamuraNei];
+        assert_eq!(
+            make_distance_array_aligned(&targets, &queries, &distances, &whole_set),
+            make_distance_array_aligned(&targets, &queries, &distances, &fixed)
+        );
+        assert_ne!(
+            make_distance_array_aligned(&targets, &queries, &distances, &whole_set),
+            make_distance_array_aligned(
+                &targets,
+                &queries,
+                &distances,
+                &DistanceOptions::default()
+            )
+        );
+    }
+
+    #[test]
+    fn test_gamma_distances() {
+        let alignment_stats = stats("AAAAGGGCCTAAGAAGCATT", "AAGAGGGCTTAAGCAGCATA");
+        // p = 0.2
+        let expected = 0.75 * 0.5 * ((1.0 - 4.0 / 3.0 * 0.2_f64).powf(-2.0) - 1.0);
+        assert!((alignment_stats.jukes_cantor_gamma_distance(0.5) - expected).abs() < 1e-12);
+        // Rates vary less with larger shapes
+        let pairs = [
+            (
+                Distance::JukesCantor,
+                Distance::JukesCantorGamma as fn(f64) -> Distance,
+            ),
+            (Distance::Kimura2P, Distance::Kimura2PGamma),
+            (Distance::TamuraNei, Distance::TamuraNeiGamma),
+        ];
+        for &(distance, gamma) in pairs.iter() {
+            let uniform = distance.compute(&alignment_stats);
+            assert!((gamma(1e9).compute(&alignment_stats) - uniform).abs() < 1e-6);
+            assert!(gamma(0.5).compute(&alignment_stats) > gamma(2.0).compute(&alignment_stats));
+            assert!(gamma(2.0).compute(&alignment_stats) > uniform);
+            assert_eq!(gamma(0.5).compute(&stats("ACGT", "ACGT")), 0.0);
+            assert_eq!(
+                gamma(0.5).compute(&stats("ACGT", "TGCA")),
+                f64::INFINITY,
+                "{:?}",
+                distance
+            );
+        }
+    }
+
+    #[test]
+    fn test_divergence_matrix() {
+        // Equal base frequencies and substitution rates reduce both to Jukes-Cantor's distance
+        let mut divergence = DivergenceMatrix::default();
+        for x in bases(A | C | G | T) {
+            for y in bases(A | C | G | T) {
+                divergence.add(x, y, if x == y { 9.0 } else { 1.0 });
+            }
+        }
+        let expected = -0.75 * (1.0 - 4.0 / 3.0 * 0.25_f64).ln();
+        assert!((divergence.paralinear_distance() - expected).abs() < 1e-12);
+        assert!((divergence.logdet_distance() - expected).abs() < 1e-12);
+
+        let identical = stats("AACCCGGGGT", "AACCCGGGGT");
+        assert!(identical.divergence.paralinear_distance().abs() < 1e-12);
+        assert!(identical.divergence.logdet_distance() > 0.0);
+        // Absent bases are left out
+        assert!(stats("ACAC", "ACAC").divergence.paralinear_distance().abs() < 1e-12);
+        assert_eq!(stats("ACGT", "CATG").divergence.paralinear_distance(), 0.0);
+        assert_eq!(
+            stats("AACC", "ACAC").divergence.paralinear_distance(),
+            f64::INFINITY
+        );
+        assert!(stats("NN-", "AC-").divergence.logdet_distance().is_nan());
+        assert_eq!(
+            stats("AAGGTC", "AAGCTC").base_frequencies(),
+            [4.0 / 12.0, 3.0 / 12.0, 3.0 / 12.0, 2.0 / 12.0]
+        );
+    }
+
+    #[test]
+    fn test_variances() {
+        let alignment_stats = stats("AAAAGGGCCTAAGAAGCATT", "AAGAGGGCTTAAGCAGCATA");
+        let (p, q, length) = (0.1, 0.1, 20.0);
+        let close = |distance: Distance, expected: f64| {
+            let variance = distance.variance(&alignment_stats);
+            assert!((variance - expected).abs() < 1e-12, "{:?}", distance);
+        };
+        close(Distance::P, 0.2 * 0.8 / length);
+        close(Distance::PGaps, 0.2 * 0.8 / length);
+        close(
+            Distance::JukesCantor,
+            0.2 * 0.8 / length / (1.0 - 4.0 / 3.0 * 0.2_f64).powi(2),
+        );
+        let c1 = 1.0 / (1.0 - 2.0 * p - q);
+        let c2 = 1.0 / (1.0 - 2.0 * q);
+        let c3 = (c1 + c2) / 2.0;
+        let kimura2p = (c1 * c1 * p + c3 * c3 * q - (c1 * p + c3 * q) * (c1 * p + c3 * q)) / length;
+        close(Distance::Kimura2P, kimura2p);
+        // Rates vary less with larger shapes
+        assert!((Distance::Kimura2PGamma(1e9).variance(&alignment_stats) - kimura2p).abs() < 1e-9);
+        assert!(
+            Distance::TamuraNeiGamma(0.5).variance(&alignment_stats)
+                > Distance::TamuraNei.variance(&alignment_stats)
+        );
+        assert!(Distance::Paralinear.variance(&alignment_stats) > 0.0);
+        assert!(Distance::LogDet.variance(&alignment_stats) > 0.0);
+
+        // Tamura's distance is Kimura's one for equal GC and AT content
+        let alignment_stats = stats("ACGTACGTACGTACGTACGT", "GTACCAGTACGTACGTACGT");
+        assert!(
+            (Distance::Tamura3P.variance(&alignment_stats)
+                - Distance::Kimura2P.variance(&alignment_stats))
+            .abs()
+                < 1e-12
+        );
+
+        let identical = stats("AACCCGGGGT", "AACCCGGGGT");
+        for &distance in [
+            Distance::JukesCantor,
+            Distance::TamuraNei,
+            Distance::Paralinear,
+        ]
+        .iter()
+        {
+            assert_eq!(distance.standard_error(&identical), 0.0, "{:?}", distance);
+        }
+        let saturated = stats("ACGT", "TGCA");
+        assert_eq!(Distance::Kimura2P.standard_error(&saturated), f64::INFINITY);
+        assert!(Distance::TamuraNei
+            .standard_error(&stats("--", "AC"))
+            .is_nan());
+    }
+
+    #[test]
+    fn test_ambiguity() {
+        use AmbiguityPolicy::*;
+        let options = |ambiguity| DistanceOptions {
+            ambiguity,
+            ..DistanceOptions::default()
+        };
+        let counts = |target, query, ambiguity| {
+            let [_, common, _, transitions, transversions] =
+                seq_site_counts_aligned(target, query, &options(ambiguity));
+            (common, transitions, transversions)
+        };
+        // R is A or G, Y is C or T
+        let (target, query) = ("ACGTRRR", "ACGTAYC");
+        assert_eq!(counts(target, query, Ignore), (4.0, 0.0, 0.0));
+        assert_eq!(counts(target, query, Missing), (4.0, 0.0, 0.0));
+        assert_eq!(counts(target, query, Overlap), (7.0, 0.0, 2.0));
+        assert_eq!(counts(target, query, Average), (7.0, 0.5, 2.0));
+        assert_eq!(counts("ACGTRR", "ACGTRR", Overlap), (6.0, 0.0, 0.0));
+        assert_eq!(counts("ACGTRR", "ACGTRR", Average), (6.0, 1.0, 0.0));
+        // Symbols that are not IUPAC codes are missing data, even where they match
+        assert_eq!(counts("AXGTA", "AXGTC", Ignore), (4.0, 0.0, 1.0));
+        assert_eq!(
+            seq_distances_aligned("AXGTA", "AXGTC", &[Distance::P], &options(Ignore)),
+            vec![0.25]
+        );
+        assert_eq!(counts("AC*TX", "AC*TX", Average), (3.0, 0.0, 0.0));
+
+        // Missing symbols aren't counted as gaps
+        let p_gaps = |ambiguity| {
+            seq_distances_aligned("ACGTAC", "ACGT-W", &[Distance::PGaps], &options(ambiguity))[0]
+        };
+        assert_eq!(p_gaps(Ignore), 0.2);
+        assert_eq!(p_gaps(Missing), 0.0);
+
+        let alignment_stats = {
+            let mut alignment_stats = AlignmentStats::with_options(&options(Average));
+            b"ACGTN"
+                .iter()
+                .copied()
+                .zip(b"ACGTN".iter().copied())
+                .for_each(|pair| alignment_stats.update(pair));
+            alignment_stats
+        };
+        assert_eq!(alignment_stats.base_frequencies(), [0.25; 4]);
+        // Every distance counts the fractional differences
+        let (target, query) = ("ACGTACGTAGCTACGTAGCR", "ACGTACGTAGCTACGTAGCA");
+        for &distance in DEFAULT_DISTANCES
+            .iter()
+            .chain([Distance::TamuraNei, Distance::Paralinear].iter())
+        {
+            assert_eq!(
+                seq_distances_aligned(target, query, &[distance], &options(Ignore))[0],
+                0.0
+            );
+            assert!(seq_distances_aligned(target, query, &[distance], &options(Average))[0] > 0.0);
+        }
+    }
+
+    #[test]
+    fn test_distance_options() {
+        let fifth_state = DistanceOptions {
+            gaps: GapTreatment::FifthState,
+            ..DistanceOptions::default()
+        };
+        assert_eq!(
+            seq_site_counts_aligned("ACGT-A", "ACGTCA", &fifth_state),
+            [6.0, 6.0, 1.0, 0.0, 0.0]
+        );
+        // Gaps are neither transversions nor common sites of the models
+        assert_eq!(
+            seq_site_counts_aligned("ACGT--ACGT", "ACGTACACGT", &fifth_state),
+            [10.0, 10.0, 2.0, 0.0, 0.0]
+        );
+        assert_eq!(
+            seq_distances_aligned(
+                "ACGT--ACGT",
+                "ACGTACACGT",
+                &[Distance::P, Distance::Kimura2P],
+                &fifth_state
+            ),
+            vec![0.2, 0.0]
+        );
+        assert_eq!(
+            seq_distances_aligned(
+                "ACGT-A",
+                "ACGTCA",
+                &[Distance::P, Distance::PGaps],
+                &fifth_state
+            ),
+            vec![1.0 / 6.0; 2]
+        );
+
+        let indel_events = DistanceOptions {
+            indel_events: true,
+            ..DistanceOptions::default()
+        };
+        // Gaps in the other sequence start a new event, gaps in both don't end one
+        assert_eq!(
+            seq_site_counts_aligned("AC--G-TA", "ACTT--CA", &indel_events),
+            [6.0, 4.0, 2.0, 1.0, 0.0]
+        );
+        assert_eq!(
+            seq_site_counts_aligned("AC-T-GTA", "ACT-TGCA", &indel_events),
+            [8.0, 5.0, 3.0, 1.0, 0.0]
+        );
+
+        let complete = DistanceOptions {
+            deletion: Deletion::Complete,
+            ..DistanceOptions::default()
+        };
+        assert_eq!(
+            seq_site_counts_aligned("AC-TA", "ACGTN", &complete),
+            [3.0, 3.0, 0.0, 0.0, 0.0]
+        );
+        assert_eq!(
+            make_site_count_array_aligned(&["ACGTA", "AC-TA"], &["ACGTN", "TCGTA"], &complete),
+            vec![
+                vec![3.0, 3.0, 0.0, 0.0, 0.0],
+                vec![3.0, 3.0, 0.0, 0.0, 1.0],
+                vec![3.0, 3.0, 0.0, 0.0, 0.0],
+                vec![3.0, 3.0, 0.0, 0.0, 1.0],
+            ]
+        );
+        assert_eq!(
+            make_site_count_array_aligned(&["ACGTA", "AC-TA"], &["ACGTN"], &indel_events),
+            vec![vec![4.0, 4.0, 0.0, 0.0, 0.0], vec![4.0, 3.0, 1.0, 0.0, 0.0]]
+        );
+    }
+
+    #[test]
+    fn test_codon_distances() {
+        let codons = [Distance::Synonymous, Distance::NonSynonymous, Distance::P];
+        let frame = |offset| ReadingFrame {
+            offset,
+            code: GeneticCode::ncbi(1).unwrap(),
+        };
+        let options = DistanceOptions {
+            reading_frame: Some(frame(0)),
+            ..DistanceOptions::default()
+        };
+        let expected = seq_distances_aligned("CTTCTT", "CTCATT", &codons, &options);
+        assert!(expected[0] > 0.0 && expected[1] > 0.0);
+        assert_eq!(expected[2], 1.0 / 3.0);
+        let without_frame = seq_distances_aligned("CTTCTT", "CTCATT", &codons, &Default::default());
+        assert!(without_frame[0].is_nan() && without_frame[1].is_nan());
+        // The codon with a gap is left out whole, and the column before the frame
+        let complete = DistanceOptions {
+            deletion: Deletion::Complete,
+            reading_frame: Some(frame(1)),
+            ..DistanceOptions::default()
+        };
+        let array =
+            make_distance_array_aligned(&["-CTTCTTAAA"], &["ACTCATTA-A"], &codons, &complete);
+        assert_eq!(array, [expected]);
+    }
+
+    #[test]
+    fn test_codon_positions() {
+        let options = |offset, positions| DistanceOptions {
+            reading_frame: Some(ReadingFrame {
+                offset,
+                code: GeneticCode::ncbi(1).unwrap(),
+            }),
+            codon_positions: Some(positions),
+            ..DistanceOptions::default()
+        };
+        let p = |options| seq_distances_p("ACGACGACG", "ACTACGTCG", &options);
+        // The differences are at a third and a first position
+        assert_eq!(p(options(0, [false, false, true])), 1.0 / 3.0);
+        assert_eq!(p(options(0, [true, true, false])), 1.0 / 6.0);
+        assert_eq!(p(options(0, [false, true, false])), 0.0);
+        assert_eq!(p(options(2, [true, false, false])), 1.0 / 3.0);
+        // Without a reading frame all the columns are counted
+        let unframed = DistanceOptions {
+            reading_frame: None,
+            ..options(0, [false, true, false])
+        };
+        assert_eq!(p(unframed), 2.0 / 9.0);
+        let array = make_distance_array_aligned(
+            &["ACGACGACG"],
+            &["ACTACGTCG", "ACGACGACG"],
+            &[Distance::P],
+            &options(0, [false, false, true]),
+        );
+        assert_eq!(array, [[1.0 / 3.0], [0.0]]);
+    }
+
+    #[test]
+    fn test_site_counts() {
+        assert_eq!(
+            seq_site_counts_aligned("--ACGTTA-CN", "AAACATCAAGA", &DistanceOptions::default()),
+            [8.0, 7.0, 1.0, 2.0, 1.0]
+        );
+        assert_eq!(
+            seq_site_counts_aligned("ACGT----", "----ACGT", &DistanceOptions::default()),
+            [0.0; 5]
+        );
+        assert_eq!(
+            make_site_count_array_aligned(
+                &["ACGT", "AC-T"],
+                &["ACGA"],
+                &DistanceOptions::default()
+            ),
+            vec![vec![4.0, 4.0, 0.0, 0.0, 1.0], vec![4.0, 3.0, 1.0, 0.0, 1.0]]
+        );
+    }
+
+    #[test]
+    fn test_seq_distances_oriented() {
+        let target = "ggatccttagcaacgttagccat";
+        let query = "atggctaacgttgctaagg";
+        let aligner = Aligner::default();
+        let (distances, orientation) = seq_distances_oriented(
+            &aligner,
+            target,
+            query,
+            &DEFAULT_DISTANCES,
+            &DistanceOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(orientation, Orientation::Forward);
+        assert!(distances[0] > 0.5);
+        let aligner = Aligner {
+            detect_orientation: true,
+            ..Aligner::default()
+        };
+        let (distances, orientation) = seq_distances_oriented(
+            &aligner,
+            target,
+            query,
+            &DEFAULT_DISTANCES,
+            &DistanceOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(orientation, Orientation::Reverse);
+        assert_eq!(distances[0], 0.0);
+    }
+
     #[test]
     fn test_distance_table() {
         let targets = ["foo", "fao", "f-o"];
         let queries = ["foo", "bar"];
 
         let aligner = Aligner::default();
-        let distance_table = make_distance_array(&aligner, &targets, &queries);
+        let distance_table = make_distance_array(
+            &aligner,
+            &targets,
+            &queries,
+            &DEFAULT_DISTANCES,
+            &DistanceOptions::default(),
+        )
+        .unwrap();
         let pdistances = vec![0.0, 1.0, 1.0 / 3.0, 2.0 / 3.0, 0.0, 1.0];
         assert_eq!(
             distance_table.iter().map(|v| v[0]).collect::<Vec<_>>(),
@@ -0,0 +1,412 @@
+//! Translation and codon-based distances
+
+use super::{base_set, bases, A, C, G, T};
+
+/// Genetic code of an NCBI translation table.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct GeneticCode {
+    /// Number of the NCBI translation table.
+    pub id: u8,
+    /// Amino acids of the codons, '*' for the stop codons.
+    amino_acids: &'static [u8; 64],
+}
+
+/// Amino acids of the NCBI translation tables, by number,
+/// with the bases of the codons in the order T, C, A, G.
+const TRANSLATION_TABLES: [(u8, &[u8; 64]); 22] = [
+    (
+        1,
+        b"FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    ),
+    (
+        2,
+        b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNKKSS**VVVVAAAADDEEGGGG",
+    ),
+    (
+        3,
+        b"FFLLSSSSYY**CCWWTTTTPPPPHHQQRRRRIIMMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    ),
+    (
+        4,
+        b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    ),
+    (
+        5,
+        b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNKKSSSSVVVVAAAADDEEGGGG",
+    ),
+    (
+        6,
+        b"FFLLSSSSYYQQCC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    ),
+    (
+        9,
+        b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNNKSSSSVVVVAAAADDEEGGGG",
+    ),
+    (
+        10,
+        b"FFLLSSSSYY**CCCWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    ),
+    (
+        11,
+        b"FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    ),
+    (
+        12,
+        b"FFLLSSSSYY**CC*WLLLSPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    ),
+    (
+        13,
+        b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNKKSSGGVVVVAAAADDEEGGGG",
+    ),
+    (
+        14,
+        b"FFLLSSSSYYY*CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNNKSSSSVVVVAAAADDEEGGGG",
+    ),
+    (
+        16,
+        b"FFLLSSSSYY*LCC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    ),
+    (
+        21,
+        b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNNKSSSSVVVVAAAADDEEGGGG",
+    ),
+    (
+        22,
+        b"FFLLSS*SYY*LCC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    ),
+    (
+        23,
+        b"FF*LSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    ),
+    (
+        24,
+        b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSSKVVVVAAAADDEEGGGG",
+    ),
+    (
+        25,
+        b"FFLLSSSSYY**CCGWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    ),
+    (
+        26,
+        b"FFLLSSSSYY**CC*WLLLAPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    ),
+    (
+        29,
+        b"FFLLSSSSYYYYCC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    ),
+    (
+        30,
+        b"FFLLSSSSYYEECC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG",
+    ),
+    (
+        33,
+        b"FFLLSSSSYYY*CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSSKVVVVAAAADDEEGGGG",
+    ),
+];
+
+/// Codon of single bases.
+type Codon = [u8; 3];
+
+impl GeneticCode {
+    /// Returns the genetic code of the NCBI translation table `id`,
+    /// [None] if there is none or its stop codons depend on the context.
+    pub fn ncbi(id: u8) -> Option<Self> {
+        TRANSLATION_TABLES
+            .iter()
+            .find(|&&(table, _)| table == id)
+            .map(|&(id, amino_acids)| GeneticCode { id, amino_acids })
+    }
+
+    /// Amino acid of the `codon`, '*' for a stop codon.
+    fn amino_acid(&self, codon: Codon) -> u8 {
+        let index = |base: u8| match base {
+            T => 0,
+            C => 1,
+            A => 2,
+            _ => 3,
+        };
+        self.amino_acids[16 * index(codon[0]) + 4 * index(codon[1]) + index(codon[2])]
+    }
+
+    fn is_stop(&self, codon: Codon) -> bool {
+        self.amino_acid(codon) == b'*'
+    }
+
+    /// Translates a codon of nucleotide symbols: '-' if it is a gap,
+    /// 'X' if it is partly a gap or its ambiguity codes stand for different amino acids.
+    pub fn translate_codon(&self, symbols: &[u8]) -> u8 {
+        if symbols.iter().all(|&x| x == b'-') {
+            return b'-';
+        }
+        let set = |x: u8| match x {
+            b'n' | b'N' => A | C | G | T,
+            _ => base_set(x),
+        };
+        let mut amino_acids = bases(set(symbols[0])).flat_map(|x| {
+            bases(set(symbols[1]))
+                .flat_map(move |y| bases(set(symbols[2])).map(move |z| self.amino_acid([x, y, z])))
+        });
+        match amino_acids.next() {
+            Some(first) if amino_acids.all(|other| other == first) => first,
+            _ => b'X',
+        }
+    }
+
+    /// Synonymous sites of the sense `codon`: the proportions of the changes of each position
+    /// keeping the amino acid.
+    fn synonymous_sites(&self, codon: Codon) -> f64 {
+        let amino_acid = self.amino_acid(codon);
+        let synonymous = (0..3)
+            .flat_map(|i| {
+                bases(A | C | G | T)
+                    .filter(move |&base| base != codon[i])
+                    .map(move |base| {
+                        let mut other = codon;
+                        other[i] = base;
+                        other
+                    })
+            })
+            .filter(|&other| self.amino_acid(other) == amino_acid)
+            .count();
+        synonymous as f64 / 3.0
+    }
+
+    /// Synonymous and nonsynonymous differences between the sense codons `x` and `y`,
+    /// averaged over the pathways of single changes between them avoiding stop codons.
+    fn differences(&self, x: Codon, y: Codon) -> (f64, f64) {
+        let positions: Vec<usize> = (0..3).filter(|&i| x[i] != y[i]).collect();
+        let pathways: Vec<(f64, f64, bool)> = permutations(&positions)
+            .iter()
+            .map(|order| {
+                let (mut synonymous, mut nonsynonymous, mut through_stop) = (0.0, 0.0, false);
+                let mut codon = x;
+                for &i in order {
+                    let mut next = codon;
+                    next[i] = y[i];
+                    through_stop |= self.is_stop(next);
+                    if self.amino_acid(next) == self.amino_acid(codon) {
+                        synonymous += 1.0;
+                    } else {
+                        nonsynonymous += 1.0;
+                    }
+                    codon = next;
+                }
+                (synonymous, nonsynonymous, through_stop)
+            })
+            .collect();
+        // All the pathways are counted if each passes through a stop codon
+        let avoiding_stops = pathways.iter().any(|&(_, _, through_stop)| !through_stop);
+        let counted: Vec<(f64, f64)> = pathways
+            .iter()
+            .filter(|&&(_, _, through_stop)| !(avoiding_stops && through_stop))
+            .map(|&(synonymous, nonsynonymous, _)| (synonymous, nonsynonymous))
+            .collect();
+        let n = counted.len() as f64;
+        let synonymous: f64 = counted.iter().map(|&(synonymous, _)| synonymous).sum();
+        let nonsynonymous: f64 = counted
+            .iter()
+            .map(|&(_, nonsynonymous)| nonsynonymous)
+            .sum();
+        (synonymous / n, nonsynonymous / n)
+    }
+}
+
+/// Returns every order of the `positions`.
+fn permutations(positions: &[usize]) -> Vec<Vec<usize>> {
+    if positions.is_empty() {
+        return vec![Vec::new()];
+    }
+    (0..positions.len())
+        .flat_map(|i| {
+            let mut rest = positions.to_vec();
+            let first = rest.remove(i);
+            permutations(&rest).into_iter().map(move |mut order| {
+                order.insert(0, first);
+                order
+            })
+        })
+        .collect()
+}
+
+/// Reading frame of coding sequences.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ReadingFrame {
+    /// Position of the first base of the first codon.
+    pub offset: usize,
+    pub code: GeneticCode,
+}
+
+impl ReadingFrame {
+    /// Iterator over the complete codons of the `sequence`.
+    fn codons<'a>(&self, sequence: &'a str) -> std::slice::ChunksExact<'a, u8> {
+        let sequence = sequence.as_bytes();
+        sequence[usize::min(self.offset, sequence.len())..].chunks_exact(3)
+    }
+}
+
+/// Translates the `sequence` in the reading `frame`, leaving out an incomplete last codon.
+///
+/// Gaps of whole codons are kept as '-', stop codons are '*'.
+pub fn translate(sequence: &str, frame: &ReadingFrame) -> String {
+    frame
+        .codons(sequence)
+        .map(|codon| frame.code.translate_codon(codon) as char)
+        .collect()
+}
+
+/// Returns the positions in the `sequence` of the stop codons in the reading `frame`.
+pub fn stop_codons(sequence: &str, frame: &ReadingFrame) -> Vec<usize> {
+    frame
+        .codons(sequence)
+        .enumerate()
+        .filter(|(_, codon)| frame.code.translate_codon(codon) == b'*')
+        .map(|(i, _)| frame.offset + 3 * i)
+        .collect()
+}
+
+/// Synonymous and nonsynonymous sites and differences of a pair of aligned coding sequences,
+/// counted by the method of Nei and Gojobori.
+///
+/// Only the pairs of sense codons of single bases are counted.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CodonStats {
+    synonymous_sites: f64,
+    nonsynonymous_sites: f64,
+    synonymous_differences: f64,
+    nonsynonymous_differences: f64,
+}
+
+impl CodonStats {
+    /// Counts the codons of the aligned `target` and `query` in the reading `frame`.
+    pub fn new(target: &str, query: &str, frame: &ReadingFrame) -> Self {
+        let code = frame.code;
+        let codon = |symbols: &[u8]| -> Option<Codon> {
+            let codon = [0, 1, 2].map(|i| base_set(symbols[i]));
+            let single = codon.iter().all(|base| base.count_ones() == 1);
+            Some(codon).filter(|&codon| single && !code.is_stop(codon))
+        };
+        let mut stats = CodonStats::default();
+        for (x, y) in frame.codons(target).zip(frame.codons(query)) {
+            let (x, y) = match (codon(x), codon(y)) {
+                (Some(x), Some(y)) => (x, y),
+                _ => continue,
+            };
+            let synonymous = (code.synonymous_sites(x) + code.synonymous_sites(y)) / 2.0;
+            stats.synonymous_sites += synonymous;
+            stats.nonsynonymous_sites += 3.0 - synonymous;
+            let (synonymous, nonsynonymous) = code.differences(x, y);
+            stats.synonymous_differences += synonymous;
+            stats.nonsynonymous_differences += nonsynonymous;
+        }
+        stats
+    }
+
+    /// Proportion of the synonymous sites that differ.
+    fn synonymous_proportion(&self) -> f64 {
+        self.synonymous_differences / self.synonymous_sites
+    }
+
+    /// Proportion of the nonsynonymous sites that differ.
+    fn nonsynonymous_proportion(&self) -> f64 {
+        self.nonsynonymous_differences / self.nonsynonymous_sites
+    }
+
+    /// Jukes-Cantor corrected synonymous distance, dS
+    pub fn synonymous_distance(&self) -> f64 {
+        jukes_cantor(self.synonymous_proportion())
+    }
+
+    /// Jukes-Cantor corrected nonsynonymous distance, dN
+    pub fn nonsynonymous_distance(&self) -> f64 {
+        jukes_cantor(self.nonsynonymous_proportion())
+    }
+
+    /// Ratio of the nonsynonymous and synonymous distances, dN/dS
+    pub fn dn_ds(&self) -> f64 {
+        self.nonsynonymous_distance() / self.synonymous_distance()
+    }
+
+    /// Variance of the [CodonStats::synonymous_distance], by the delta method.
+    pub fn synonymous_variance(&self) -> f64 {
+        jukes_cantor_variance(self.synonymous_proportion(), self.synonymous_sites)
+    }
+
+    /// Variance of the [CodonStats::nonsynonymous_distance], by the delta method.
+    pub fn nonsynonymous_variance(&self) -> f64 {
+        jukes_cantor_variance(self.nonsynonymous_proportion(), self.nonsynonymous_sites)
+    }
+
+    /// Variance of the [CodonStats::dn_ds], by the delta method,
+    /// taking the distances as independent.
+    pub fn dn_ds_variance(&self) -> f64 {
+        let dn = self.nonsynonymous_distance();
+        let ds = self.synonymous_distance();
+        (dn / ds).powi(2)
+            * (self.nonsynonymous_variance() / (dn * dn) + self.synonymous_variance() / (ds * ds))
+    }
+}
+
+/// Jukes-Cantor correction of the proportion `p` of differing sites.
+fn jukes_cantor(p: f64) -> f64 {
+    if p >= 3.0 / 4.0 {
+        f64::INFINITY
+    } else {
+        f64::abs(-(3.0 / 4.0) * f64::ln_1p(-(4.0 / 3.0) * p))
+    }
+}
+
+/// Variance of the [jukes_cantor] correction of the proportion `p` of `n` sites.
+fn jukes_cantor_variance(p: f64, n: f64) -> f64 {
+    p * (1.0 - p) / (n * (1.0 - 4.0 / 3.0 * p).powi(2))
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    fn frame(offset: usize, id: u8) -> ReadingFrame {
+        ReadingFrame {
+            offset,
+            code: GeneticCode::ncbi(id).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_translation() {
+        assert_eq!(translate("ATGGCCTGAAGA", &frame(0, 1)), "MA*R");
+        // Invertebrate mitochondrial code
+        assert_eq!(translate("ATGGCCTGAAGA", &frame(0, 5)), "MAWS");
+        assert_eq!(translate("catggccT", &frame(1, 1)), "MA");
+        assert_eq!(translate("CTNATN---A-GTAR", &frame(0, 1)), "LX-X*");
+        assert_eq!(stop_codons("ATGTAAGGCTAGC", &frame(0, 1)), [3, 9]);
+        assert_eq!(stop_codons("CATGAGA", &frame(1, 2)), [4]);
+        assert_eq!(GeneticCode::ncbi(7), None);
+    }
+
+    #[test]
+    fn test_nei_gojobori() {
+        let code = GeneticCode::ncbi(1).unwrap();
+        assert_eq!(code.synonymous_sites([C, T, T]), 1.0);
+        assert_eq!(code.synonymous_sites([A, T, T]), 2.0 / 3.0);
+        // Both pathways are synonymous
+        assert_eq!(code.differences([T, T, A], [C, T, G]), (2.0, 0.0));
+        // Through CTG or ATA
+        assert_eq!(code.differences([A, T, G], [C, T, A]), (0.5, 1.5));
+        // TAG is a stop codon
+        assert_eq!(code.differences([T, G, G], [C, A, G]), (0.0, 2.0));
+
+        // Leu Leu against Leu Ile, leaving out the stop codon and the gaps
+        let stats = CodonStats::new("CTTCTTTAA---", "CTCATTTTAAAA", &frame(0, 1));
+        let synonymous_sites = 11.0 / 6.0;
+        let nonsynonymous_sites = 25.0 / 6.0;
+        assert!((stats.synonymous_sites - synonymous_sites).abs() < 1e-12);
+        assert!((stats.nonsynonymous_sites - nonsynonymous_sites).abs() < 1e-12);
+        let ds = jukes_cantor(1.0 / synonymous_sites);
+        let dn = jukes_cantor(1.0 / nonsynonymous_sites);
+        assert!((stats.synonymous_distance() - ds).abs() < 1e-12);
+        assert!((stats.nonsynonymous_distance() - dn).abs() < 1e-12);
+        assert!((stats.dn_ds() - dn / ds).abs() < 1e-12);
+        assert!(stats.dn_ds_variance() > 0.0);
+        assert!(CodonStats::new("CTT", "CTT", &frame(0, 1)).dn_ds().is_nan());
+    }
+}
@@ -0,0 +1,402 @@
+//! Maximum likelihood distances between pairs of sequences
+
+use super::symmetric_eigen;
+
+/// Substitution model of the [MaximumLikelihood] distances.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SubstitutionModel {
+    /// Equal rates and base frequencies.
+    JukesCantor,
+    /// Transition/transversion ratio with equal base frequencies.
+    Kimura2P,
+    /// Transition/transversion ratio with unequal base frequencies.
+    Hky85,
+    /// Six exchangeabilities with unequal base frequencies.
+    Gtr,
+}
+
+/// Pairs of bases of the exchangeabilities, among A, C, G and T.
+const PAIRS: [(usize, usize); 6] = [(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)];
+
+impl SubstitutionModel {
+    /// Number of free exchangeabilities, estimated with the distance.
+    fn parameters(self) -> usize {
+        match self {
+            SubstitutionModel::JukesCantor => 0,
+            SubstitutionModel::Kimura2P | SubstitutionModel::Hky85 => 1,
+            SubstitutionModel::Gtr => 5,
+        }
+    }
+
+    /// Exchangeabilities of the [PAIRS] for the free `parameters`.
+    fn exchangeabilities(self, parameters: &[f64]) -> [f64; 6] {
+        match self {
+            SubstitutionModel::JukesCantor => [1.0; 6],
+            SubstitutionModel::Kimura2P | SubstitutionModel::Hky85 => {
+                let kappa = parameters[0];
+                [1.0, kappa, 1.0, 1.0, kappa, 1.0]
+            }
+            SubstitutionModel::Gtr => [
+                parameters[0],
+                parameters[1],
+                parameters[2],
+                parameters[3],
+                parameters[4],
+                1.0,
+            ],
+        }
+    }
+}
+
+/// Returns the exchangeability of the bases `i` and `j` among the `exchangeabilities` of the [PAIRS].
+fn pair_exchangeability(exchangeabilities: &[f64; 6], i: usize, j: usize) -> f64 {
+    let pair = (usize::min(i, j), usize::max(i, j));
+    PAIRS
+        .iter()
+        .position(|&other| other == pair)
+        .map_or(0.0, |k| exchangeabilities[k])
+}
+
+/// Maximum likelihood distance of a pair of sequences under a [SubstitutionModel].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MaximumLikelihood {
+    pub model: SubstitutionModel,
+    /// Shape of the gamma distributed rates, equal rates if [None].
+    pub gamma_shape: Option<f64>,
+    /// Largest distance estimated, that of saturated sequences.
+    pub max_distance: f64,
+}
+
+/// Default [MaximumLikelihood::max_distance].
+pub const DEFAULT_MAX_DISTANCE: f64 = 10.0;
+
+// Bounds of the logarithms of the free exchangeabilities
+const MIN_LN_EXCHANGEABILITY: f64 = -9.0;
+const MAX_LN_EXCHANGEABILITY: f64 = 9.0;
+
+// Most rounds of estimating the distance and the exchangeabilities in turn
+const MAX_ROUNDS: usize = 50;
+
+impl MaximumLikelihood {
+    /// Estimates the distance from the divergence matrix `counts` of a pair of sequences,
+    /// with the base `frequencies` for the models with unequal ones.
+    ///
+    /// The free exchangeabilities of the model are estimated with the distance.
+    /// Returns NaN if there are no sites.
+    pub fn distance(&self, counts: &[[f64; 4]; 4], frequencies: [f64; 4]) -> f64 {
+        self.estimate(counts, frequencies)
+            .map_or(f64::NAN, |(distance, _)| distance)
+    }
+
+    /// Variance of the [MaximumLikelihood::distance], by the curvature of the likelihood
+    /// at the estimate with the exchangeabilities fixed.
+    ///
+    /// Returns NaN if there are no sites and infinity if the likelihood is flat.
+    pub fn variance(&self, counts: &[[f64; 4]; 4], frequencies: [f64; 4]) -> f64 {
+        let (distance, matrix) = match self.estimate(counts, frequencies) {
+            Some((distance, Some(matrix))) if distance > 0.0 => (distance, matrix),
+            Some(_) => return 0.0,
+            None => return f64::NAN,
+        };
+        curvature_variance(
+            |distance| matrix.log_likelihood(|i, j| counts[i][j], distance, self.gamma_shape),
+            distance,
+        )
+    }
+
+    /// Returns the estimated distance and the rate matrix at the estimate,
+    /// [None] if the sequences have a single base.
+    ///
+    /// Returns [None] if there are no sites.
+    fn estimate(
+        &self,
+        counts: &[[f64; 4]; 4],
+        frequencies: [f64; 4],
+    ) -> Option<(f64, Option<RateMatrix>)> {
+        let total: f64 = counts.iter().flatten().sum();
+        if total == 0.0 {
+            return None;
+        }
+        let frequencies = match self.model {
+            SubstitutionModel::JukesCantor | SubstitutionModel::Kimura2P => [0.25; 4],
+            SubstitutionModel::Hky85 | SubstitutionModel::Gtr => frequencies,
+        };
+        let model = self.model;
+        let rate_matrix = |parameters: &[f64]| {
+            let exchangeabilities = model.exchangeabilities(parameters);
+            RateMatrix::new(
+                |i, j| pair_exchangeability(&exchangeabilities, i, j),
+                &frequencies,
+            )
+        };
+        let log_likelihood = |parameters: &[f64], distance: f64| {
+            rate_matrix(parameters).map_or(0.0, |matrix| {
+                matrix.log_likelihood(|i, j| counts[i][j], distance, self.gamma_shape)
+            })
+        };
+        let mut parameters = vec![1.0; model.parameters()];
+        let mut distance = 0.0;
+        let mut best = f64::NEG_INFINITY;
+        for _ in 0..MAX_ROUNDS {
+            distance = golden_section(
+                |distance| log_likelihood(&parameters, distance),
+                0.0,
+                self.max_distance,
+            );
+            for i in 0..parameters.len() {
+                let ln_parameter = golden_section(
+                    |ln_parameter| {
+                        let mut parameters = parameters.clone();
+                        parameters[i] = ln_parameter.exp();
+                        log_likelihood(&parameters, distance)
+                    },
+                    MIN_LN_EXCHANGEABILITY,
+                    MAX_LN_EXCHANGEABILITY,
+                );
+                parameters[i] = ln_parameter.exp();
+            }
+            let current = log_likelihood(&parameters, distance);
+            if current - best <= 1e-10 * current.abs() {
+                break;
+            }
+            best = current;
+        }
+        Some((distance, rate_matrix(&parameters)))
+    }
+}
+
+/// Returns the variance of the estimate `distance` maximising `log_likelihood`,
+/// by the curvature of `log_likelihood` there.
+///
+/// Returns infinity if the likelihood is flat.
+pub fn curvature_variance(log_likelihood: impl Fn(f64) -> f64, distance: f64) -> f64 {
+    let step = 1e-3 * distance;
+    let curvature = (log_likelihood(distance + step) - 2.0 * log_likelihood(distance)
+        + log_likelihood(distance - step))
+        / (step * step);
+    if curvature < 0.0 {
+        -1.0 / curvature
+    } else {
+        f64::INFINITY
+    }
+}
+
+/// Returns the point in `low..=high` maximising the unimodal `f`, by golden-section search.
+pub fn golden_section(f: impl Fn(f64) -> f64, mut low: f64, mut high: f64) -> f64 {
+    let ratio = (5.0_f64.sqrt() - 1.0) / 2.0;
+    let mut left = high - ratio * (high - low);
+    let mut right = low + ratio * (high - low);
+    let (mut f_left, mut f_right) = (f(left), f(right));
+    while high - low > 1e-10 * (1.0 + low.abs()) {
+        if f_left < f_right {
+            low = left;
+            left = right;
+            f_left = f_right;
+            right = low + ratio * (high - low);
+            f_right = f(right);
+        } else {
+            high = right;
+            right = left;
+            f_right = f_left;
+            left = high - ratio * (high - low);
+            f_left = f(left);
+        }
+    }
+    0.5 * (low + high)
+}
+
+/// Time reversible rate matrix, scaled to one substitution per site,
+/// by the eigendecomposition of its symmetrised form.
+pub struct RateMatrix {
+    /// States with nonzero frequencies.
+    states: Vec<usize>,
+    /// Square roots of their frequencies.
+    roots: Vec<f64>,
+    /// Eigenvalues of the rate matrix.
+    values: Vec<f64>,
+    /// Eigenvectors of the symmetrised rate matrix, as columns.
+    vectors: Vec<Vec<f64>>,
+}
+
+impl RateMatrix {
+    /// Returns the rate matrix with the symmetric `exchangeability` of each pair of states
+    /// and the state `frequencies`, or [None] if there are no substitutions between its states.
+    pub fn new(exchangeability: impl Fn(usize, usize) -> f64, frequencies: &[f64]) -> Option<Self> {
+        let states: Vec<usize> = (0..frequencies.len())
+            .filter(|&i| frequencies[i] > 0.0)
+            .collect();
+        let sum: f64 = states.iter().map(|&i| frequencies[i]).sum();
+        let frequencies: Vec<f64> = frequencies
+            .iter()
+            .map(|frequency| frequency / sum)
+            .collect();
+        let roots: Vec<f64> = states.iter().map(|&i| frequencies[i].sqrt()).collect();
+        let exchangeability = |i: usize, j: usize| if i == j { 0.0 } else { exchangeability(i, j) };
+        // Π^(1/2) Q Π^(-1/2) is symmetric, with the eigenvalues of Q
+        let mut matrix: Vec<Vec<f64>> = states
+            .iter()
+            .zip(&roots)
+            .map(|(&i, x)| {
+                states
+                    .iter()
+                    .zip(&roots)
+                    .map(|(&j, y)| exchangeability(i, j) * x * y)
+                    .collect()
+            })
+            .collect();
+        let mut rate = 0.0;
+        for (a, &i) in states.iter().enumerate() {
+            let outflow: f64 = states
+                .iter()
+                .map(|&j| exchangeability(i, j) * frequencies[j])
+                .sum();
+            matrix[a][a] = -outflow;
+            rate += frequencies[i] * outflow;
+        }
+        if rate == 0.0 {
+            return None;
+        }
+        matrix.iter_mut().flatten().for_each(|value| *value /= rate);
+        let (values, vectors) = symmetric_eigen(matrix);
+        Some(RateMatrix {
+            states,
+            roots,
+            values,
+            vectors,
+        })
+    }
+
+    /// Log-likelihood of the sites with each pair of states, numbering `count`, at the `distance`,
+    /// up to a constant, with gamma distributed rates of the shape `gamma_shape` if given.
+    pub fn log_likelihood(
+        &self,
+        count: impl Fn(usize, usize) -> f64,
+        distance: f64,
+        gamma_shape: Option<f64>,
+    ) -> f64 {
+        let decays: Vec<f64> = self
+            .values
+            .iter()
+            .map(|&value| match gamma_shape {
+                Some(alpha) => (1.0 - value * distance / alpha).powf(-alpha),
+                None => (value * distance).exp(),
+            })
+            .collect();
+        let mut log_likelihood = 0.0;
+        for (x, (&i, root_i)) in self.vectors.iter().zip(self.states.iter().zip(&self.roots)) {
+            for (y, (&j, root_j)) in self.vectors.iter().zip(self.states.iter().zip(&self.roots)) {
+                let count = count(i, j);
+                if count == 0.0 {
+                    continue;
+                }
+                let probability: f64 = (0..decays.len())
+                    .map(|k| x[k] * y[k] * decays[k])
+                    .sum::<f64>()
+                    * root_j
+                    / root_i;
+                log_likelihood += count * probability.max(f64::MIN_POSITIVE).ln();
+            }
+        }
+        log_likelihood
+    }
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+    use crate::distance::{seq_distances_aligned, Distance, DistanceOptions};
+
+    fn distances(target: &str, query: &str, distances: &[Distance]) -> Vec<f64> {
+        seq_distances_aligned(target, query, distances, &DistanceOptions::default())
+    }
+
+    fn maximum_likelihood(model: SubstitutionModel, gamma_shape: Option<f64>) -> Distance {
+        Distance::MaximumLikelihood(MaximumLikelihood {
+            model,
+            gamma_shape,
+            max_distance: DEFAULT_MAX_DISTANCE,
+        })
+    }
+
+    #[test]
+    fn test_maximum_likelihood() {
+        use SubstitutionModel::*;
+        let (target, query) = (
+            "AAAAGGGCCTAAGAAGCATTACGTTGCA",
+            "AAGAGGGCTTAAGCAGCATAACGATGCA",
+        );
+        // The closed forms are the estimates of their models
+        let pairs = [
+            (Distance::JukesCantor, maximum_likelihood(JukesCantor, None)),
+            (Distance::Kimura2P, maximum_likelihood(Kimura2P, None)),
+            (
+                Distance::JukesCantorGamma(0.5),
+                maximum_likelihood(JukesCantor, Some(0.5)),
+            ),
+        ];
+        for &(closed_form, estimate) in &pairs {
+            let values = distances(target, query, &[closed_form, estimate]);
+            assert!((values[0] - values[1]).abs() < 1e-6, "{:?}", values);
+        }
+        // and GTR where its rate matrix is valid
+        let (mut balanced_target, mut balanced_query) = (String::new(), String::new());
+        for x in "ACGT".chars() {
+            for y in "ACGT".chars() {
+                let sites = if x == y { 10 } else { 1 };
+                for _ in 0..sites {
+                    balanced_target.push(x);
+                    balanced_query.push(y);
+                }
+            }
+        }
+        let values = distances(
+            &balanced_target,
+            &balanced_query,
+            &[Distance::Gtr, maximum_likelihood(Gtr, None)],
+        );
+        assert!((values[0] - values[1]).abs() < 1e-6, "{:?}", values);
+
+        let hky85 = distances(target, query, &[maximum_likelihood(Hky85, None)])[0];
+        assert!(hky85 > distances(target, query, &[Distance::P])[0]);
+
+        // Saturated sequences get the largest distance
+        let saturated = distances("ACGTACGT", "TGCATGCA", &[Distance::JukesCantor])[0];
+        assert_eq!(saturated, f64::INFINITY);
+        for &model in &[JukesCantor, Kimura2P, Hky85, Gtr] {
+            let estimate = distances("ACGTACGT", "TGCATGCA", &[maximum_likelihood(model, None)])[0];
+            assert!(1.0 < estimate && estimate <= DEFAULT_MAX_DISTANCE);
+            if model == JukesCantor {
+                assert!((estimate - DEFAULT_MAX_DISTANCE).abs() < 1e-6);
+            }
+            assert!(distances("ACGT", "ACGT", &[maximum_likelihood(model, None)])[0] < 1e-9);
+        }
+        assert!(distances("--", "AC", &[maximum_likelihood(Gtr, None)])[0].is_nan());
+    }
+
+    #[test]
+    fn test_maximum_likelihood_variance() {
+        let (target, query) = (
+            "AAAAGGGCCTAAGAAGCATTACGTTGCA",
+            "AAGAGGGCTTAAGCAGCATAACGATGCA",
+        );
+        let options = DistanceOptions::default();
+        let (_, errors) = crate::distance::seq_distances_aligned_with_errors(
+            target,
+            query,
+            &[
+                Distance::JukesCantor,
+                maximum_likelihood(SubstitutionModel::JukesCantor, None),
+            ],
+            &options,
+        );
+        // The curvature of the Jukes-Cantor likelihood gives the variance of the delta method
+        assert!((errors[0] - errors[1]).abs() < 1e-4, "{:?}", errors);
+    }
+
+    #[test]
+    fn test_golden_section() {
+        let maximum = golden_section(|x| -(x - 2.0) * (x - 2.0), 0.0, 10.0);
+        assert!((maximum - 2.0).abs() < 1e-8);
+        assert!((golden_section(|x| x, 0.0, 10.0) - 10.0).abs() < 1e-8);
+    }
+}
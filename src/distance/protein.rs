@@ -0,0 +1,530 @@
+//! Distances between pairs of protein sequences
+
+use rayon::prelude::*;
+use std::sync::OnceLock;
+
+use super::likelihood::{golden_section, RateMatrix};
+use super::{common_content, with_deletion_by, Deletion, DistanceOptions, GapTreatment, WORKSPACE};
+use crate::needle::{Aligner, ScoreOverflow};
+
+/// Amino acids, in the order of the exchangeabilities of the [ProteinModel]s.
+const AMINO_ACIDS: &[u8; 20] = b"ARNDCQEGHILKMFPSTWYV";
+
+/// Returns the index of the amino acid `x` in [AMINO_ACIDS], [None] if it is not one.
+fn amino_acid(x: u8) -> Option<usize> {
+    let x = x.to_ascii_uppercase();
+    AMINO_ACIDS.iter().position(|&other| other == x)
+}
+
+enum SymbolType {
+    Gap,
+    /// Ambiguity codes such as X, B or Z, stop codons and unknown symbols.
+    Missing,
+    /// Index of the amino acid in [AMINO_ACIDS].
+    AminoAcid(usize),
+}
+
+fn classify(x: u8) -> SymbolType {
+    match x {
+        b'-' => SymbolType::Gap,
+        _ => amino_acid(x).map_or(SymbolType::Missing, SymbolType::AminoAcid),
+    }
+}
+
+// Returns true if the character is part of a meaningful part of a protein sequence
+fn is_amino_acid(c: char) -> bool {
+    !c.is_ascii() || amino_acid(c as u8).is_some()
+}
+
+/// State for the distance calculation between protein sequences.
+///
+/// Sites are counted like in [super::AlignmentStats], except for the ambiguity codes,
+/// which are always missing data.
+pub struct ProteinStats {
+    total_length: usize,
+    common_length: usize,
+    total_gap_length: usize,
+    /// Common sites with different amino acids.
+    differences: usize,
+    /// Sites pairing a gap with an amino acid counted as a fifth state, only by the p-distances.
+    gap_differences: usize,
+    /// Sites with `target` amino acid `i` and `query` amino acid `j` at `[i][j]`.
+    counts: [[f64; 20]; 20],
+    /// How the sites are counted.
+    options: DistanceOptions,
+    /// Whether the gap of the last counted site, if any, was in the target.
+    gap_in_target: Option<bool>,
+}
+
+impl ProteinStats {
+    /// Zeroed state, counting the sites by the `options`.
+    pub fn with_options(options: &DistanceOptions) -> Self {
+        ProteinStats {
+            total_length: 0,
+            common_length: 0,
+            total_gap_length: 0,
+            differences: 0,
+            gap_differences: 0,
+            counts: [[0.0; 20]; 20],
+            options: *options,
+            gap_in_target: None,
+        }
+    }
+
+    /// pairwise uncorrelated distance
+    pub fn pdistance(&self) -> f64 {
+        (self.differences + self.gap_differences) as f64
+            / (self.common_length + self.gap_differences) as f64
+    }
+
+    /// pairwise uncorrelated distance with gaps
+    pub fn pdistance_counting_gaps(&self) -> f64 {
+        (self.differences + self.total_gap_length) as f64 / self.total_length as f64
+    }
+
+    // Proportion of the common sites with different amino acids, without the gaps
+    fn replacements(&self) -> f64 {
+        self.differences as f64 / self.common_length as f64
+    }
+
+    /// Poisson corrected distance, `-ln(1 - p)`
+    pub fn poisson_distance(&self) -> f64 {
+        -f64::ln_1p(-self.replacements())
+    }
+
+    /// Kimura's protein distance, `-ln(1 - p - 0.2 p^2)`
+    pub fn kimura_distance(&self) -> f64 {
+        let p = self.replacements();
+        let argument = 1.0 - p - 0.2 * p * p;
+        if argument <= 0.0 {
+            f64::INFINITY
+        } else {
+            -argument.ln()
+        }
+    }
+
+    // Counts a site with a gap in the target if `in_target` and in the query otherwise,
+    // following a gap in the target if `previous` is `Some(true)`
+    fn count_gap(&mut self, in_target: bool, previous: Option<bool>) {
+        self.gap_in_target = Some(in_target);
+        let extension = self.options.indel_events && previous == Some(in_target);
+        if extension || self.options.deletion == Deletion::Complete {
+            return;
+        }
+        self.total_length += 1;
+        self.total_gap_length += 1;
+        if self.options.gaps == GapTreatment::FifthState {
+            self.gap_differences += 1;
+        }
+    }
+
+    /// Count `(x, y)` pair.
+    pub fn update(&mut self, (x, y): (u8, u8)) {
+        use SymbolType::*;
+        // Columns of gaps in both sequences don't interrupt an indel
+        let gap_in_target = self.gap_in_target.take();
+        match (classify(x), classify(y)) {
+            (Gap, Gap) => self.gap_in_target = gap_in_target,
+            (Gap, AminoAcid(_)) => self.count_gap(true, gap_in_target),
+            (AminoAcid(_), Gap) => self.count_gap(false, gap_in_target),
+            (AminoAcid(i), AminoAcid(j)) => {
+                self.total_length += 1;
+                self.common_length += 1;
+                if i != j {
+                    self.differences += 1;
+                }
+                self.counts[i][j] += 1.0;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Empirical amino acid substitution model of the [ProteinLikelihood] distances.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ProteinModel {
+    /// Jones, Taylor and Thornton (1992).
+    Jtt,
+    /// Whelan and Goldman (2001).
+    Wag,
+}
+
+impl ProteinModel {
+    /// Rate matrix of the model, with its amino acid frequencies.
+    fn rate_matrix(self) -> &'static RateMatrix {
+        static JTT_MATRIX: OnceLock<RateMatrix> = OnceLock::new();
+        static WAG_MATRIX: OnceLock<RateMatrix> = OnceLock::new();
+        let (matrix, text) = match self {
+            ProteinModel::Jtt => (&JTT_MATRIX, JTT),
+            ProteinModel::Wag => (&WAG_MATRIX, WAG),
+        };
+        matrix.get_or_init(|| parse_model(text))
+    }
+}
+
+/// Parses a model in the PAML format: the lower triangle of the exchangeabilities
+/// followed by the amino acid frequencies, in the order of [AMINO_ACIDS].
+fn parse_model(text: &str) -> RateMatrix {
+    let values: Vec<f64> = text
+        .split_whitespace()
+        .map(|value| value.parse().expect("built-in models are valid"))
+        .collect();
+    let (triangle, frequencies) = values.split_at(190);
+    let exchangeability = |i: usize, j: usize| {
+        let (i, j) = (usize::max(i, j), usize::min(i, j));
+        triangle[i * (i - 1) / 2 + j]
+    };
+    RateMatrix::new(exchangeability, frequencies).expect("built-in models are valid")
+}
+
+/// Maximum likelihood distance of a pair of protein sequences under a [ProteinModel].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ProteinLikelihood {
+    pub model: ProteinModel,
+    /// Shape of the gamma distributed rates, equal rates if [None].
+    pub gamma_shape: Option<f64>,
+    /// Largest distance estimated, that of saturated sequences.
+    pub max_distance: f64,
+}
+
+impl ProteinLikelihood {
+    /// Estimates the distance from the numbers of sites with each pair of amino acids `counts`,
+    /// with the amino acid frequencies of the model.
+    ///
+    /// Returns NaN if there are no sites.
+    pub fn distance(&self, counts: &[[f64; 20]; 20]) -> f64 {
+        if counts.iter().flatten().all(|&count| count == 0.0) {
+            return f64::NAN;
+        }
+        let matrix = self.model.rate_matrix();
+        golden_section(
+            |distance| matrix.log_likelihood(|i, j| counts[i][j], distance, self.gamma_shape),
+            0.0,
+            self.max_distance,
+        )
+    }
+}
+
+/// Distance computed from the [ProteinStats] of a pair of sequences.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ProteinDistance {
+    /// Uncorrected p-distance.
+    P,
+    /// Uncorrected p-distance, counting the gaps as differences.
+    PGaps,
+    Poisson,
+    Kimura,
+    /// Maximum likelihood distance under an empirical model.
+    MaximumLikelihood(ProteinLikelihood),
+}
+
+/// Protein distances returned when no others are selected.
+pub const DEFAULT_PROTEIN_DISTANCES: [ProteinDistance; 4] = [
+    ProteinDistance::P,
+    ProteinDistance::Poisson,
+    ProteinDistance::Kimura,
+    ProteinDistance::PGaps,
+];
+
+impl ProteinDistance {
+    /// Computes the distance from `protein_stats`.
+    pub fn compute(self, protein_stats: &ProteinStats) -> f64 {
+        match self {
+            ProteinDistance::P => protein_stats.pdistance(),
+            ProteinDistance::PGaps => protein_stats.pdistance_counting_gaps(),
+            ProteinDistance::Poisson => protein_stats.poisson_distance(),
+            ProteinDistance::Kimura => protein_stats.kimura_distance(),
+            ProteinDistance::MaximumLikelihood(estimator) => {
+                estimator.distance(&protein_stats.counts)
+            }
+        }
+    }
+}
+
+/// Computes the `distances` from `protein_stats`.
+fn compute_all(protein_stats: &ProteinStats, distances: &[ProteinDistance]) -> Vec<f64> {
+    distances
+        .iter()
+        .map(|distance| distance.compute(protein_stats))
+        .collect()
+}
+
+/// Returns the `distances` between the protein sequences `target` and `query`,
+/// counting the sites by the `options`.
+///
+/// Performs alignment, in the workspace of the current thread, never reversing `query`.
+/// # Errors
+/// Returns [ScoreOverflow] if the alignment score could overflow.
+pub fn seq_distances_protein(
+    aligner: &Aligner,
+    target: &str,
+    query: &str,
+    distances: &[ProteinDistance],
+    options: &DistanceOptions,
+) -> Result<Vec<f64>, ScoreOverflow> {
+    let mut protein_stats = ProteinStats::with_options(options);
+    WORKSPACE.with(|workspace| {
+        aligner
+            .align_with(
+                &mut workspace.borrow_mut(),
+                target.as_bytes(),
+                query.as_bytes(),
+            )
+            .map(|alignment| {
+                alignment
+                    .common_path_iter()
+                    .for_each(|pair| protein_stats.update(pair))
+            })
+    })?;
+    Ok(compute_all(&protein_stats, distances))
+}
+
+/// Counts the common content of the aligned protein sequences `target` and `query`
+/// by the `options`.
+///
+/// Returns [None] if there is none.
+fn protein_stats(target: &str, query: &str, options: &DistanceOptions) -> Option<ProteinStats> {
+    let (start, end) = common_content(target.char_indices(), query.char_indices(), is_amino_acid)?;
+    let target = &target[start..=end];
+    let query = &query[start..=end];
+    let mut protein_stats = ProteinStats::with_options(options);
+    target
+        .bytes()
+        .zip(query.bytes())
+        .for_each(|pair| protein_stats.update(pair));
+    Some(protein_stats)
+}
+
+/// Returns the `distances` between the protein sequences `target` and `query`,
+/// counting the sites by the `options`.
+///
+/// Expects aligned sequences.
+pub fn seq_distances_protein_aligned(
+    target: &str,
+    query: &str,
+    distances: &[ProteinDistance],
+    options: &DistanceOptions,
+) -> Vec<f64> {
+    match protein_stats(target, query, options) {
+        None => vec![f64::NAN; distances.len()],
+        Some(protein_stats) => compute_all(&protein_stats, distances),
+    }
+}
+
+/// Creates (n, k) vector of the k `distances` between the protein sequences
+/// `targets` and `queries`.
+///
+/// Outer iteration over `targets`.
+/// Inner iteration over `queries`.
+/// Performs sequence-to-sequence alignment.
+/// # Errors
+/// Returns [ScoreOverflow] if the score of any alignment could overflow.
+pub fn make_distance_array_protein(
+    aligner: &Aligner,
+    targets: &[&str],
+    queries: &[&str],
+    distances: &[ProteinDistance],
+    options: &DistanceOptions,
+) -> Result<Vec<Vec<f64>>, ScoreOverflow> {
+    targets
+        .par_iter()
+        .flat_map_iter(|target| {
+            queries
+                .iter()
+                .map(move |query| seq_distances_protein(aligner, target, query, distances, options))
+        })
+        .collect()
+}
+
+/// Creates (n, k) vector of the k `distances` between the aligned protein sequences
+/// `targets` and `queries`.
+///
+/// [Deletion::Complete] drops every column where any of the sequences is not an amino acid.
+pub fn make_distance_array_protein_aligned(
+    targets: &[&str],
+    queries: &[&str],
+    distances: &[ProteinDistance],
+    options: &DistanceOptions,
+) -> Vec<Vec<f64>> {
+    with_deletion_by(
+        targets,
+        queries,
+        options.deletion,
+        None,
+        is_amino_acid,
+        |targets, queries, _| {
+            targets
+                .par_iter()
+                .flat_map_iter(|target| {
+                    queries.iter().map(move |query| {
+                        seq_distances_protein_aligned(target, query, distances, options)
+                    })
+                })
+                .collect()
+        },
+    )
+}
+
+// Exchangeabilities and amino acid frequencies of the models, in the PAML format
+
+const JTT: &str = "
+58
+54 45
+81 16 528
+56 113 34 10
+57 310 86 49 9
+105 29 58 767 5 323
+179 137 81 130 59 26 119
+27 328 391 112 69 597 26 23
+36 22 47 11 17 9 12 6 16
+30 38 12 7 23 72 9 6 56 229
+35 646 263 26 7 292 181 27 45 21 14
+54 44 30 15 31 43 18 14 33 479 388 65
+15 5 10 4 78 4 5 5 40 89 248 4 43
+194 74 15 15 14 164 18 24 115 10 102 21 16 17
+378 101 503 59 223 53 30 201 73 40 59 47 29 92 285
+475 64 232 38 42 51 32 33 46 245 25 103 226 12 118 477
+9 126 8 4 115 18 10 55 8 9 52 10 24 53 6 35 12
+11 20 70 46 209 24 7 8 573 32 24 8 18 536 10 63 21 71
+298 17 16 31 62 20 45 47 11 961 180 14 323 62 23 38 112 25 16
+
+0.076748 0.051691 0.042645 0.051544 0.019803 0.040752 0.061830 0.073152 0.022944 0.053761 0.091904 0.058676 0.023826 0.040126 0.050901 0.068765 0.058565 0.014261 0.032102 0.066005
+";
+
+const WAG: &str = "
+0.551571
+0.509848 0.635346
+0.738998 0.147304 5.429420
+1.027040 0.528191 0.265256 0.0302949
+0.908598 3.035500 1.543640 0.616783 0.0988179
+1.582850 0.439157 0.947198 6.174160 0.021352 5.469470
+1.416720 0.584665 1.125560 0.865584 0.306674 0.330052 0.567717
+0.316954 2.137150 3.956290 0.930676 0.248972 4.294110 0.570025 0.249410
+0.193335 0.186979 0.554236 0.039437 0.170135 0.113917 0.127395 0.0304501 0.138190
+0.397915 0.497671 0.131528 0.0848047 0.384287 0.869489 0.154263 0.0613037 0.499462 3.170970
+0.906265 5.351420 3.012010 0.479855 0.0740339 3.894900 2.584430 0.373558 0.890432 0.323832 0.257555
+0.893496 0.683162 0.198221 0.103754 0.390482 1.545260 0.315124 0.174100 0.404141 4.257460 4.854020 0.934276
+0.210494 0.102711 0.0961621 0.0467304 0.398020 0.0999208 0.0811339 0.049931 0.679371 1.059470 2.115170 0.088836 1.190630
+1.438550 0.679489 0.195081 0.423984 0.109404 0.933372 0.682355 0.243570 0.696198 0.0999288 0.415844 0.556896 0.171329 0.161444
+3.370790 1.224190 3.974230 1.071760 1.407660 1.028870 0.704939 1.341820 0.740169 0.319440 0.344739 0.967130 0.493905 0.545931 1.613280
+2.121110 0.554413 2.030060 0.374866 0.512984 0.857928 0.822765 0.225833 0.473307 1.458160 0.326622 1.386980 1.516120 0.171903 0.795384 4.378020
+0.113133 1.163920 0.0719167 0.129767 0.717070 0.215737 0.156557 0.336983 0.262569 0.212483 0.665309 0.137505 0.515706 1.529640 0.139405 0.523742 0.110864
+0.240735 0.381533 1.086000 0.325711 0.543833 0.227710 0.196303 0.103604 3.873440 0.420170 0.398618 0.133264 0.428437 6.454280 0.216046 0.786993 0.291148 2.485390
+2.006010 0.251849 0.196246 0.152335 1.002140 0.301281 0.588731 0.187247 0.118358 7.821300 1.800340 0.305434 2.058450 0.649892 0.314887 0.232739 1.388230 0.365369 0.314730
+
+0.0866279 0.043972 0.0390894 0.0570451 0.0193078 0.0367281 0.0580589 0.0832518 0.0244313 0.048466 0.086209 0.0620286 0.0195027 0.0384319 0.0457631 0.0695179 0.0610127 0.0143859 0.0352742 0.0708956
+";
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    const TARGET: &str = "ARNDCQEGHILKMFPSTWYVARNDCQEGHILKMFPSTWYV";
+    const QUERY: &str = "ARNDCQEGHILKMFPSTWYVARNDCQEGHLLKMFPSTWYV";
+
+    fn distances(target: &str, query: &str, options: &DistanceOptions) -> Vec<f64> {
+        seq_distances_protein_aligned(target, query, &DEFAULT_PROTEIN_DISTANCES, options)
+    }
+
+    #[test]
+    fn test_protein_distances() {
+        let options = DistanceOptions::default();
+        let [p, poisson, kimura, p_gaps] = distances(TARGET, QUERY, &options)[..] else {
+            unreachable!()
+        };
+        assert_eq!(p, 0.025);
+        assert_eq!(p_gaps, 0.025);
+        assert!((poisson + f64::ln(0.975)).abs() < 1e-12);
+        assert!((kimura + f64::ln(1.0 - 0.025 - 0.2 * 0.025 * 0.025)).abs() < 1e-12);
+        assert_eq!(distances(TARGET, &TARGET.to_lowercase(), &options)[0], 0.0);
+        let shifted = &format!("{}{}", &TARGET[1..], &TARGET[..1]);
+        let saturated = distances(TARGET, shifted, &options);
+        assert_eq!(saturated[..3], [1.0, f64::INFINITY, f64::INFINITY]);
+        assert!(distances("X-", "A-", &options)[0].is_nan());
+    }
+
+    #[test]
+    fn test_protein_gaps() {
+        let options = DistanceOptions::default();
+        // The gap and the site with X are left out, but for the gap in p_gaps
+        let counts = distances("AC-DEX", "ACWDFA", &options);
+        assert_eq!((counts[0], counts[3]), (0.25, 0.4));
+        assert_eq!(distances("--ACDE", "WWACDF", &options)[3], 0.25);
+        let fifth_state = DistanceOptions {
+            gaps: GapTreatment::FifthState,
+            ..DistanceOptions::default()
+        };
+        let counts = distances("AC-DEX", "ACWDFA", &fifth_state);
+        assert_eq!((counts[0], counts[3]), (0.4, 0.4));
+        let indel_events = DistanceOptions {
+            indel_events: true,
+            ..DistanceOptions::default()
+        };
+        assert_eq!(distances("AC--DE", "ACWWDF", &indel_events)[3], 0.4);
+        let complete = DistanceOptions {
+            deletion: Deletion::Complete,
+            ..DistanceOptions::default()
+        };
+        let array = make_distance_array_protein_aligned(
+            &["AC-DE"],
+            &["ACWDF", "ACWXE"],
+            &[ProteinDistance::P],
+            &complete,
+        );
+        assert_eq!(array, [[1.0 / 3.0], [0.0]]);
+    }
+
+    #[test]
+    fn test_protein_likelihood() {
+        let estimate = |model, target, query, gamma_shape| {
+            let estimator = ProteinLikelihood {
+                model,
+                gamma_shape,
+                max_distance: 10.0,
+            };
+            seq_distances_protein_aligned(
+                target,
+                query,
+                &[ProteinDistance::MaximumLikelihood(estimator)],
+                &DistanceOptions::default(),
+            )[0]
+        };
+        let shifted = &format!("{}{}", &TARGET[1..], &TARGET[..1]);
+        for model in [ProteinModel::Jtt, ProteinModel::Wag] {
+            assert!(estimate(model, TARGET, TARGET, None) < 1e-6);
+            let distance = estimate(model, TARGET, QUERY, None);
+            assert!(distance > 0.025 && distance < 0.05, "{}", distance);
+            let gamma = estimate(model, TARGET, QUERY, Some(1e6));
+            assert!((gamma - distance).abs() < 1e-4);
+            assert!(estimate(model, TARGET, QUERY, Some(0.5)) > distance);
+            // Saturated pairs stay finite
+            let saturated = estimate(model, TARGET, shifted, None);
+            assert!(saturated > 1.0 && saturated <= 10.0, "{}", saturated);
+            assert!(estimate(model, "X", "B", None).is_nan());
+        }
+    }
+
+    #[test]
+    fn test_seq_distances_protein() {
+        let aligner = Aligner::default();
+        let options = DistanceOptions::default();
+        let distances = seq_distances_protein(
+            &aligner,
+            TARGET,
+            QUERY,
+            &DEFAULT_PROTEIN_DISTANCES,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(distances, self::distances(TARGET, QUERY, &options));
+        let array = make_distance_array_protein(
+            &aligner,
+            &[TARGET],
+            &[TARGET, QUERY],
+            &[ProteinDistance::P],
+            &options,
+        )
+        .unwrap();
+        assert_eq!(array, [[0.0], [0.025]]);
+    }
+}
@@ -1,3 +1,5 @@
+mod alignment;
+mod bootstrap;
 mod column;
 mod distance;
 mod needle;
@@ -6,50 +8,242 @@ use pyo3::exceptions;
 use pyo3::prelude::*;
 use pyo3::wrap_pyfunction;
 
-use numpy::PyArray2;
+use numpy::{PyArray2, PyArray3};
 
+use crate::alignment::PyAlignment;
+use crate::bootstrap::UnequalLengths;
 use crate::column::Column;
-use crate::needle::Aligner;
+use crate::distance::{
+    AmbiguityPolicy, BaseFrequencies, Deletion, Distance, DistanceOptions, GapTreatment,
+    GeneticCode, MaximumLikelihood, ProteinDistance, ProteinLikelihood, ProteinModel, ReadingFrame,
+    SubstitutionModel, DEFAULT_DISTANCES, DEFAULT_MAX_DISTANCE, DEFAULT_PROTEIN_DISTANCES,
+    SITE_COUNTS,
+};
+use crate::needle::{
+    Aligner, AlignerWorkspace, AlignmentMode, MatrixError, Orientation, ScoreOverflow,
+    SubstitutionMatrix, TiePolicy,
+};
+
+impl From<ScoreOverflow> for PyErr {
+    fn from(err: ScoreOverflow) -> PyErr {
+        exceptions::PyOverflowError::new_err(err.to_string())
+    }
+}
+
+impl From<UnequalLengths> for PyErr {
+    fn from(err: UnequalLengths) -> PyErr {
+        exceptions::PyValueError::new_err(err.to_string())
+    }
+}
+
+impl From<MatrixError> for PyErr {
+    fn from(err: MatrixError) -> PyErr {
+        exceptions::PyValueError::new_err(err.to_string())
+    }
+}
 
 /// Makes an Aligner with given scores
-#[pyfunction]
-#[text_signature = "(match_score, mismatch_score, end_open_gap_score, end_extend_gap_score, internal_open_gap_score, internal_extend_gap_score, /)"]
+///
+/// If `substitution_matrix` is given, it scores the pairs of symbols in its alphabet,
+/// other pairs are scored with `match_score` and `mismatch_score`.
+///
+/// `mode` is one of:
+/// - "global": align the whole sequences,
+/// - "local": align the best matching parts of the sequences,
+/// - "glocal": align the whole query to a part of the target,
+/// - "overlap": align with free end gaps in both sequences.
+/// End gap scores are only used in the global mode.
+///
+/// Pairs of sequences whose alignment table has more than `linear_memory_threshold` cells
+/// are aligned in memory linear in their length, at the cost of some speed.
+///
+/// With a `band_width`, only the alignments staying within that many diagonals of the main
+/// diagonal are considered, which is faster for similar sequences. If `widen_band` is true,
+/// alignments touching the edge of the band are computed again with a twice wider band,
+/// until they fit inside it.
+///
+/// `tie_policy` chooses among alignments with the same score:
+/// - "leftmost": place gaps as far left as possible,
+/// - "rightmost": place gaps as far right as possible,
+/// - "substitution": prefer a mismatch to a pair of gaps, otherwise place gaps rightmost.
+///
+/// If `detect_orientation` is true, `make_alignment` and the distances also align
+/// the reverse complement of the query, IUPAC codes included, and keep the better orientation.
+///
+/// Raises OverflowError if any score is too large.
+#[pyfunction(
+    substitution_matrix = "None",
+    mode = "\"global\"",
+    linear_memory_threshold = "None",
+    band_width = "None",
+    widen_band = "true",
+    tie_policy = "\"leftmost\"",
+    detect_orientation = "false"
+)]
+#[text_signature = "(match_score, mismatch_score, end_open_gap_score, end_extend_gap_score, internal_open_gap_score, internal_extend_gap_score, /, substitution_matrix=None, mode='global', linear_memory_threshold=None, band_width=None, widen_band=True, tie_policy='leftmost', detect_orientation=False)"]
+#[allow(clippy::too_many_arguments)]
 pub fn make_aligner(
-    match_score: i16,
-    mismatch_score: i16,
-    end_open_gap_score: i16,
-    end_extend_gap_score: i16,
-    internal_open_gap_score: i16,
-    internal_extend_gap_score: i16,
-) -> Aligner {
-    Aligner {
+    match_score: i32,
+    mismatch_score: i32,
+    end_open_gap_score: i32,
+    end_extend_gap_score: i32,
+    internal_open_gap_score: i32,
+    internal_extend_gap_score: i32,
+    substitution_matrix: Option<SubstitutionMatrix>,
+    mode: &str,
+    linear_memory_threshold: Option<usize>,
+    band_width: Option<usize>,
+    widen_band: bool,
+    tie_policy: &str,
+    detect_orientation: bool,
+) -> PyResult<Aligner> {
+    let mode = match mode {
+        "global" => AlignmentMode::Global,
+        "local" => AlignmentMode::Local,
+        "glocal" => AlignmentMode::Glocal,
+        "overlap" => AlignmentMode::Overlap,
+        _ => {
+            return Err(exceptions::PyValueError::new_err(format!(
+                "unknown alignment mode: {:?}",
+                mode
+            )))
+        }
+    };
+    let tie_policy = match tie_policy {
+        "leftmost" => TiePolicy::LeftmostGaps,
+        "rightmost" => TiePolicy::RightmostGaps,
+        "substitution" => TiePolicy::PreferSubstitution,
+        _ => {
+            return Err(exceptions::PyValueError::new_err(format!(
+                "unknown tie policy: {:?}",
+                tie_policy
+            )))
+        }
+    };
+    let aligner = Aligner {
         match_score,
         mismatch_score,
         end_gap_penalty: end_open_gap_score,
         end_gap_extend_penalty: end_extend_gap_score,
         gap_penalty: internal_open_gap_score,
         gap_extend_penalty: internal_extend_gap_score,
+        substitution_matrix,
+        mode,
+        linear_memory_threshold: linear_memory_threshold
+            .unwrap_or_else(|| Aligner::default().linear_memory_threshold),
+        band_width,
+        widen_band,
+        tie_policy,
+        detect_orientation,
+    };
+    aligner.check_scores()?;
+    Ok(aligner)
+}
+
+// Converts a score given by Python to an integer
+fn integer_score(score: f64) -> PyResult<i32> {
+    if score.fract() == 0.0 && score.abs() <= i32::MAX as f64 {
+        Ok(score as i32)
+    } else {
+        Err(exceptions::PyValueError::new_err(format!(
+            "substitution scores should be integers, got {}",
+            score
+        )))
+    }
+}
+
+// Converts a symbol given by Python to a byte
+fn symbol_byte(symbol: &str) -> PyResult<u8> {
+    match symbol.as_bytes() {
+        &[byte] => Ok(byte),
+        _ => Err(exceptions::PyValueError::new_err(format!(
+            "expected a single ASCII symbol, got {:?}",
+            symbol
+        ))),
     }
 }
 
+/// Returns a built-in substitution matrix.
+///
+/// Known names are "NUC.4.4" (also "EDNAFULL"), "BLOSUM62" and "PAM250".
+#[pyfunction]
+#[text_signature = "(name, /)"]
+fn substitution_matrix(name: &str) -> PyResult<SubstitutionMatrix> {
+    SubstitutionMatrix::named(name).ok_or_else(|| {
+        exceptions::PyValueError::new_err(format!("unknown substitution matrix: {:?}", name))
+    })
+}
+
+/// Returns a DNA substitution matrix that scores transitions (A<->G, C<->T)
+/// and transversions differently.
+#[pyfunction]
+#[text_signature = "(match_score, transition_score, transversion_score, /)"]
+fn transition_transversion_matrix(
+    match_score: i32,
+    transition_score: i32,
+    transversion_score: i32,
+) -> SubstitutionMatrix {
+    SubstitutionMatrix::transition_transversion(match_score, transition_score, transversion_score)
+}
+
+/// Makes a substitution matrix from user-supplied scores.
+///
+/// `scores` is either a dict mapping pairs of symbols to scores,
+/// with keys like "AG" or ("A", "G"), or a square 2D array of scores.
+/// An array needs the `alphabet` labelling its rows and columns,
+/// unless it has the `alphabet` attribute, like Biopython's substitution matrices.
+#[pyfunction(alphabet = "None")]
+#[text_signature = "(scores, /, alphabet=None)"]
+fn make_substitution_matrix(
+    scores: &PyAny,
+    alphabet: Option<String>,
+) -> PyResult<SubstitutionMatrix> {
+    if let Ok(scores) = scores.downcast::<pyo3::types::PyDict>() {
+        let pairs = scores
+            .iter()
+            .map(|(key, score)| {
+                let (a, b) = match key.extract::<&str>() {
+                    Ok(key) if key.len() == 2 => (&key[..1], &key[1..]),
+                    _ => key.extract::<(&str, &str)>()?,
+                };
+                Ok((
+                    symbol_byte(a)?,
+                    symbol_byte(b)?,
+                    integer_score(score.extract()?)?,
+                ))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        return Ok(SubstitutionMatrix::from_pairs(&pairs)?);
+    }
+    let alphabet = match alphabet {
+        Some(alphabet) => alphabet,
+        None => scores.getattr("alphabet")?.extract()?,
+    };
+    let rows = scores
+        .extract::<Vec<Vec<f64>>>()?
+        .into_iter()
+        .map(|row| row.into_iter().map(integer_score).collect())
+        .collect::<PyResult<Vec<Vec<i32>>>>()?;
+    Ok(SubstitutionMatrix::new(alphabet.as_bytes(), &rows)?)
+}
+
 /// Returns two strings that represent aligned `target` and aligned `query` respectively.
 #[pyfunction]
 #[text_signature = "(target, query, /)"]
 fn align_to_str(target: &str, query: &str) -> PyResult<(String, String)> {
     let aligner = Aligner::default();
     aligner
-        .align(target.as_bytes(), query.as_bytes())
+        .align(target.as_bytes(), query.as_bytes())?
         .as_strings()
         .map_err(exceptions::PyUnicodeEncodeError::new_err)
 }
 
-
 /// Returns two strings that represent aligned `target` and aligned `query` respectively.
 #[pyfunction]
 #[text_signature = "(target, query, /)"]
 fn align_seq(aligner: &Aligner, target: &str, query: &str) -> PyResult<(String, String)> {
     aligner
-        .align(target.as_bytes(), query.as_bytes())
+        .align(target.as_bytes(), query.as_bytes())?
         .as_strings()
         .map_err(exceptions::PyUnicodeEncodeError::new_err)
 }
@@ -59,125 +253,1149 @@ fn align_seq(aligner: &Aligner, target: &str, query: &str) -> PyResult<(String,
 #[text_signature = "(aligner, target, query, /)"]
 fn show_alignment(aligner: &Aligner, target: &str, query: &str) -> PyResult<String> {
     aligner
-        .align(target.as_bytes(), query.as_bytes())
+        .align(target.as_bytes(), query.as_bytes())?
         .show_alignment()
         .map_err(exceptions::PyUnicodeEncodeError::new_err)
 }
 
-/// Returns 4 distances between `target` and `query`.
+/// Returns the optimal [PyAlignment] of `target` and `query`.
 ///
-/// Performs alignment.
+/// Aligns the reverse complement of `query` instead if the aligner detects
+/// the orientation and it aligns better.
 #[pyfunction]
 #[text_signature = "(aligner, target, query, /)"]
-fn seq_distances(aligner: &Aligner, target: &str, query: &str) -> [f64; 4] {
-    crate::distance::seq_distances(aligner, target, query)
+fn make_alignment(aligner: &Aligner, target: &str, query: &str) -> PyResult<PyAlignment> {
+    let mut reverse = Vec::new();
+    let (alignment, orientation) = aligner.align_oriented(
+        &mut AlignerWorkspace::default(),
+        target.as_bytes(),
+        query.as_bytes(),
+        &mut reverse,
+    )?;
+    PyAlignment::new(&alignment, orientation).map_err(exceptions::PyUnicodeEncodeError::new_err)
+}
+
+/// Returns the number of optimal alignments of `target` and `query`.
+#[pyfunction]
+#[text_signature = "(aligner, target, query, /)"]
+fn count_alignments(aligner: &Aligner, target: &str, query: &str) -> PyResult<u64> {
+    Ok(aligner.count_alignments(target.as_bytes(), query.as_bytes())?)
+}
+
+/// Returns up to `limit` optimal alignments of `target` and `query`,
+/// the first of them being the one returned by `make_alignment`.
+#[pyfunction(limit = "100")]
+#[text_signature = "(aligner, target, query, /, limit=100)"]
+fn co_optimal_alignments(
+    aligner: &Aligner,
+    target: &str,
+    query: &str,
+    limit: usize,
+) -> PyResult<Vec<PyAlignment>> {
+    aligner
+        .co_optimal_alignments(target.as_bytes(), query.as_bytes(), limit)?
+        .iter()
+        .map(|alignment| PyAlignment::new(alignment, Orientation::Forward))
+        .collect::<Result<_, _>>()
+        .map_err(exceptions::PyUnicodeEncodeError::new_err)
+}
+
+/// Returns the distances named by `distances`, with the shape parameter `gamma_shape`
+/// for the gamma corrected ones and the maximum likelihood ones if given,
+/// and the upper bound `max_distance` of the maximum likelihood ones.
+///
+/// Returns the default p-distance, Jukes-Cantor, Kimura 2P and p-distance with gaps
+/// if `distances` is None.
+///
+/// Only the p-distances and the codon distances can count gaps as a fifth state of `options`.
+/// The codon distances need `aligned` sequences and the reading frame of `options`,
+/// and so do the codon positions of `options`.
+fn selected_distances(
+    distances: Option<Vec<String>>,
+    gamma_shape: Option<f64>,
+    max_distance: Option<f64>,
+    options: &DistanceOptions,
+    aligned: bool,
+) -> PyResult<Vec<Distance>> {
+    if !aligned && options.codon_positions.is_some() {
+        return Err(exceptions::PyValueError::new_err(
+            "codon_positions need aligned sequences",
+        ));
+    }
+    let distances = match distances {
+        Some(distances) => distances,
+        None => {
+            check_model_gaps("jukes_cantor", options)?;
+            return Ok(DEFAULT_DISTANCES.to_vec());
+        }
+    };
+    let alpha = || required_gamma_shape(gamma_shape);
+    let max_distance = checked_max_distance(max_distance)?;
+    let codon = |name: &str, distance| -> PyResult<Distance> {
+        if !aligned {
+            Err(exceptions::PyValueError::new_err(format!(
+                "distance {:?} needs aligned sequences",
+                name
+            )))
+        } else if options.reading_frame.is_none() {
+            Err(exceptions::PyValueError::new_err(format!(
+                "distance {:?} needs a reading frame in the options",
+                name
+            )))
+        } else {
+            Ok(distance)
+        }
+    };
+    let maximum_likelihood = |model| -> PyResult<Distance> {
+        Ok(Distance::MaximumLikelihood(MaximumLikelihood {
+            model,
+            gamma_shape: gamma_shape.map(|_| alpha()).transpose()?,
+            max_distance,
+        }))
+    };
+    distances
+        .iter()
+        .map(|name| {
+            let distance = match name.as_str() {
+                "p" => Distance::P,
+                "p_gaps" => Distance::PGaps,
+                "jukes_cantor" => Distance::JukesCantor,
+                "kimura2p" => Distance::Kimura2P,
+                "tamura3p" => Distance::Tamura3P,
+                "tamura_nei" => Distance::TamuraNei,
+                "jukes_cantor_gamma" => Distance::JukesCantorGamma(alpha()?),
+                "kimura2p_gamma" => Distance::Kimura2PGamma(alpha()?),
+                "tamura_nei_gamma" => Distance::TamuraNeiGamma(alpha()?),
+                "logdet" => Distance::LogDet,
+                "paralinear" => Distance::Paralinear,
+                "f81" => Distance::F81,
+                "hky85" => Distance::Hky85,
+                "gtr" => Distance::Gtr,
+                "ml_jukes_cantor" => maximum_likelihood(SubstitutionModel::JukesCantor)?,
+                "ml_kimura2p" => maximum_likelihood(SubstitutionModel::Kimura2P)?,
+                "ml_hky85" => maximum_likelihood(SubstitutionModel::Hky85)?,
+                "ml_gtr" => maximum_likelihood(SubstitutionModel::Gtr)?,
+                "ds" => codon(name, Distance::Synonymous)?,
+                "dn" => codon(name, Distance::NonSynonymous)?,
+                "dn_ds" => codon(name, Distance::DnDs)?,
+                _ => {
+                    return Err(exceptions::PyValueError::new_err(format!(
+                        "unknown distance: {:?}",
+                        name
+                    )))
+                }
+            };
+            match distance {
+                Distance::P
+                | Distance::PGaps
+                | Distance::Synonymous
+                | Distance::NonSynonymous
+                | Distance::DnDs => {}
+                _ => check_model_gaps(name, options)?,
+            }
+            Ok(distance)
+        })
+        .collect()
+}
+
+/// Checks that the `options` don't count gaps as a fifth state for the distance `name`
+/// corrected by a substitution model, since a gap is not a substitution.
+fn check_model_gaps(name: &str, options: &DistanceOptions) -> PyResult<()> {
+    match options.gaps {
+        GapTreatment::Separate => Ok(()),
+        GapTreatment::FifthState => Err(exceptions::PyValueError::new_err(format!(
+            "distance {:?} is corrected by a substitution model \
+             and can't count gaps as a fifth state",
+            name
+        ))),
+    }
+}
+
+/// Returns the protein distances named by `distances`, with the shape parameter `gamma_shape`
+/// and the upper bound `max_distance` of the maximum likelihood ones.
+///
+/// Returns the default p-distance, Poisson, Kimura and p-distance with gaps
+/// if `distances` is None.
+///
+/// The codon positions of `options` don't apply to proteins,
+/// and only the p-distances can count gaps as a fifth state.
+fn selected_protein_distances(
+    distances: Option<Vec<String>>,
+    gamma_shape: Option<f64>,
+    max_distance: Option<f64>,
+    options: &DistanceOptions,
+) -> PyResult<Vec<ProteinDistance>> {
+    if options.codon_positions.is_some() {
+        return Err(exceptions::PyValueError::new_err(
+            "codon_positions don't apply to protein sequences",
+        ));
+    }
+    let distances = match distances {
+        Some(distances) => distances,
+        None => {
+            check_model_gaps("poisson", options)?;
+            return Ok(DEFAULT_PROTEIN_DISTANCES.to_vec());
+        }
+    };
+    let max_distance = checked_max_distance(max_distance)?;
+    let maximum_likelihood = |model| -> PyResult<ProteinDistance> {
+        Ok(ProteinDistance::MaximumLikelihood(ProteinLikelihood {
+            model,
+            gamma_shape: gamma_shape
+                .map(|_| required_gamma_shape(gamma_shape))
+                .transpose()?,
+            max_distance,
+        }))
+    };
+    distances
+        .iter()
+        .map(|name| {
+            let distance = match name.as_str() {
+                "p" => ProteinDistance::P,
+                "p_gaps" => ProteinDistance::PGaps,
+                "poisson" => ProteinDistance::Poisson,
+                "kimura" => ProteinDistance::Kimura,
+                "jtt" => maximum_likelihood(ProteinModel::Jtt)?,
+                "wag" => maximum_likelihood(ProteinModel::Wag)?,
+                _ => {
+                    return Err(exceptions::PyValueError::new_err(format!(
+                        "unknown protein distance: {:?}",
+                        name
+                    )))
+                }
+            };
+            match distance {
+                ProteinDistance::P | ProteinDistance::PGaps => {}
+                _ => check_model_gaps(name, options)?,
+            }
+            Ok(distance)
+        })
+        .collect()
+}
+
+/// Returns `gamma_shape`, which should be given and positive.
+fn required_gamma_shape(gamma_shape: Option<f64>) -> PyResult<f64> {
+    match gamma_shape {
+        Some(alpha) if alpha > 0.0 => Ok(alpha),
+        Some(alpha) => Err(exceptions::PyValueError::new_err(format!(
+            "gamma_shape should be positive, got {}",
+            alpha
+        ))),
+        None => Err(exceptions::PyValueError::new_err(
+            "gamma corrected distances need gamma_shape",
+        )),
+    }
+}
+
+/// Returns `max_distance`, which should be positive, or the default one.
+fn checked_max_distance(max_distance: Option<f64>) -> PyResult<f64> {
+    match max_distance {
+        None => Ok(DEFAULT_MAX_DISTANCE),
+        Some(max_distance) if max_distance > 0.0 => Ok(max_distance),
+        Some(max_distance) => Err(exceptions::PyValueError::new_err(format!(
+            "max_distance should be positive, got {}",
+            max_distance
+        ))),
+    }
+}
+
+/// Returns options for counting the sites of the sequences in the distances.
+///
+/// `deletion` chooses which sites with gaps or missing data are left out:
+/// - "pairwise": those of each pair of sequences,
+/// - "complete": the columns with any in the whole set of aligned sequences,
+///   the sequences aligned pair by pair being compared without their gaps.
+///
+/// `gaps` chooses how the sites pairing a gap with a base are counted:
+/// - "separate": only by "p_gaps",
+/// - "fifth_state": as a difference by "p" and "p_gaps", the distances corrected
+///   by substitution models raise ValueError with it.
+///
+/// If `indel_events` is true, contiguous gaps in the same sequence count as a single site.
+///
+/// `ambiguity` chooses how the sites with IUPAC ambiguity codes such as R or Y are counted:
+/// - "ignore": leave out the sites pairing them with bases,
+/// - "missing": treat them like N,
+/// - "overlap": count a match if the codes share a base, average the differences otherwise,
+/// - "average": average the differences between the bases the codes stand for.
+///
+/// `frequencies` chooses the base frequencies of the models using them:
+/// - "pairwise": those of the common sites of each pair of sequences,
+/// - "whole_set": those of all the sequences compared by a call.
+///
+/// `frame`, if given, is the position of the first codon of aligned coding sequences,
+/// translated with the NCBI translation table numbered `genetic_code`, for the codon distances.
+/// With "complete" deletion, whole codons are left out.
+///
+/// `codon_positions`, a list of the codon positions 1, 2 and 3 of the `frame`,
+/// such as [3] or [1, 2], restricts the columns of aligned sequences counted by the other distances.
+/// The functions performing alignment and the protein ones raise ValueError with it.
+#[pyfunction(
+    deletion = "\"pairwise\"",
+    gaps = "\"separate\"",
+    indel_events = "false",
+    ambiguity = "\"ignore\"",
+    frequencies = "\"pairwise\"",
+    frame = "None",
+    genetic_code = "1",
+    codon_positions = "None"
+)]
+#[text_signature = "(deletion='pairwise', gaps='separate', indel_events=False, ambiguity='ignore', frequencies='pairwise', frame=None, genetic_code=1, codon_positions=None)"]
+#[allow(clippy::too_many_arguments)]
+fn make_distance_options(
+    deletion: &str,
+    gaps: &str,
+    indel_events: bool,
+    ambiguity: &str,
+    frequencies: &str,
+    frame: Option<usize>,
+    genetic_code: u8,
+    codon_positions: Option<Vec<usize>>,
+) -> PyResult<DistanceOptions> {
+    let deletion = match deletion {
+        "pairwise" => Deletion::Pairwise,
+        "complete" => Deletion::Complete,
+        _ => {
+            return Err(exceptions::PyValueError::new_err(format!(
+                "unknown deletion: {:?}",
+                deletion
+            )))
+        }
+    };
+    let gaps = match gaps {
+        "separate" => GapTreatment::Separate,
+        "fifth_state" => GapTreatment::FifthState,
+        _ => {
+            return Err(exceptions::PyValueError::new_err(format!(
+                "unknown gap treatment: {:?}",
+                gaps
+            )))
+        }
+    };
+    let ambiguity = match ambiguity {
+        "ignore" => AmbiguityPolicy::Ignore,
+        "missing" => AmbiguityPolicy::Missing,
+        "overlap" => AmbiguityPolicy::Overlap,
+        "average" => AmbiguityPolicy::Average,
+        _ => {
+            return Err(exceptions::PyValueError::new_err(format!(
+                "unknown ambiguity policy: {:?}",
+                ambiguity
+            )))
+        }
+    };
+    let frequencies = match frequencies {
+        "pairwise" => BaseFrequencies::Pairwise,
+        "whole_set" => BaseFrequencies::WholeSet,
+        _ => {
+            return Err(exceptions::PyValueError::new_err(format!(
+                "unknown base frequencies: {:?}",
+                frequencies
+            )))
+        }
+    };
+    let code = ncbi_genetic_code(genetic_code)?;
+    let codon_positions = match codon_positions {
+        None => None,
+        Some(_) if frame.is_none() => {
+            return Err(exceptions::PyValueError::new_err(
+                "codon_positions need a frame",
+            ))
+        }
+        Some(positions) if positions.is_empty() => {
+            return Err(exceptions::PyValueError::new_err(
+                "codon_positions should select at least one position",
+            ))
+        }
+        Some(positions) => {
+            let mut selected = [false; 3];
+            for position in positions {
+                match position {
+                    1..=3 => selected[position - 1] = true,
+                    _ => {
+                        return Err(exceptions::PyValueError::new_err(format!(
+                            "codon positions should be 1, 2 or 3, got {}",
+                            position
+                        )))
+                    }
+                }
+            }
+            Some(selected)
+        }
+    };
+    Ok(DistanceOptions {
+        deletion,
+        gaps,
+        indel_events,
+        ambiguity,
+        frequencies,
+        reading_frame: frame.map(|offset| ReadingFrame { offset, code }),
+        codon_positions,
+    })
+}
+
+/// Returns the genetic code of the NCBI translation table `id`.
+fn ncbi_genetic_code(id: u8) -> PyResult<GeneticCode> {
+    GeneticCode::ncbi(id).ok_or_else(|| {
+        exceptions::PyValueError::new_err(format!("unsupported genetic code: {}", id))
+    })
+}
+
+/// Translates `sequence` from the position `frame` with the NCBI translation table
+/// numbered `genetic_code`.
+///
+/// Stop codons are "*", gaps of whole codons are "-" and other codons with gaps are "X",
+/// like ambiguous codons standing for different amino acids.
+/// An incomplete last codon is left out.
+#[pyfunction(genetic_code = "1", frame = "0")]
+#[text_signature = "(sequence, /, genetic_code=1, frame=0)"]
+fn translate(sequence: &str, genetic_code: u8, frame: usize) -> PyResult<String> {
+    let frame = ReadingFrame {
+        offset: frame,
+        code: ncbi_genetic_code(genetic_code)?,
+    };
+    Ok(crate::distance::translate(sequence, &frame))
+}
+
+/// Returns the positions in `sequence` of the stop codons when translated like by `translate`.
+#[pyfunction(genetic_code = "1", frame = "0")]
+#[text_signature = "(sequence, /, genetic_code=1, frame=0)"]
+fn stop_codons(sequence: &str, genetic_code: u8, frame: usize) -> PyResult<Vec<usize>> {
+    let frame = ReadingFrame {
+        offset: frame,
+        code: ncbi_genetic_code(genetic_code)?,
+    };
+    Ok(crate::distance::stop_codons(sequence, &frame))
+}
+
+/// Returns distances between `target` and `query`.
+///
+/// Performs alignment.
+///
+/// `distances` is a list of the names of the distances to compute:
+/// "p", "p_gaps", "jukes_cantor", "kimura2p", "tamura3p", "tamura_nei", "f81", "hky85", "gtr",
+/// and the gamma corrected "jukes_cantor_gamma", "kimura2p_gamma" and "tamura_nei_gamma",
+/// which need the shape parameter `gamma_shape`,
+/// and "logdet" and "paralinear", which allow unequal base frequencies in the sequences.
+/// By default "p", "jukes_cantor", "kimura2p" and "p_gaps".
+///
+/// The maximum likelihood distances "ml_jukes_cantor", "ml_kimura2p", "ml_hky85" and "ml_gtr"
+/// estimate the parameters of their models with the distance, with gamma distributed rates
+/// if `gamma_shape` is given. They stay finite for saturated sequences,
+/// up to `max_distance`, by default 10 substitutions per site.
+///
+/// The codon distances "ds", "dn" and "dn_ds", the synonymous and nonsynonymous distances
+/// of Nei and Gojobori and their ratio, need aligned sequences and a frame in `options`,
+/// and raise ValueError otherwise.
+///
+/// `options` made by `make_distance_options` choose how the sites are counted.
+#[pyfunction(
+    distances = "None",
+    gamma_shape = "None",
+    max_distance = "None",
+    options = "None"
+)]
+#[text_signature = "(aligner, target, query, /, distances=None, gamma_shape=None, max_distance=None, options=None)"]
+fn seq_distances(
+    aligner: &Aligner,
+    target: &str,
+    query: &str,
+    distances: Option<Vec<String>>,
+    gamma_shape: Option<f64>,
+    max_distance: Option<f64>,
+    options: Option<DistanceOptions>,
+) -> PyResult<Vec<f64>> {
+    let options = options.unwrap_or_default();
+    let distances = selected_distances(distances, gamma_shape, max_distance, &options, false)?;
+    Ok(crate::distance::seq_distances(
+        aligner, target, query, &distances, &options,
+    )?)
 }
 
-/// Returns 4 distances between `target` and `query`.
+/// Returns distances between the protein sequences `target` and `query`.
+///
+/// Performs alignment, without detecting the orientation.
+///
+/// `distances` is a list of the names of the distances to compute:
+/// "p", "p_gaps", "poisson" and "kimura", and the maximum likelihood "jtt" and "wag"
+/// under the empirical models of the same name, with gamma distributed rates
+/// if `gamma_shape` is given, up to `max_distance`, by default 10 substitutions per site.
+/// By default "p", "poisson", "kimura" and "p_gaps".
+///
+/// `options` made by `make_distance_options` choose how the sites are counted, like
+/// for nucleotides; the ambiguity codes B, Z, J and X, "?" and "*" are always missing data.
+#[pyfunction(
+    distances = "None",
+    gamma_shape = "None",
+    max_distance = "None",
+    options = "None"
+)]
+#[text_signature = "(aligner, target, query, /, distances=None, gamma_shape=None, max_distance=None, options=None)"]
+fn seq_distances_protein(
+    aligner: &Aligner,
+    target: &str,
+    query: &str,
+    distances: Option<Vec<String>>,
+    gamma_shape: Option<f64>,
+    max_distance: Option<f64>,
+    options: Option<DistanceOptions>,
+) -> PyResult<Vec<f64>> {
+    let options = options.unwrap_or_default();
+    let distances = selected_protein_distances(distances, gamma_shape, max_distance, &options)?;
+    Ok(crate::distance::seq_distances_protein(
+        aligner, target, query, &distances, &options,
+    )?)
+}
+
+/// Returns distances between the protein sequences `target` and `query`,
+/// selected like in `seq_distances_protein`.
 ///
 /// Expects aligned sequences.
-#[pyfunction]
-#[text_signature = "(target, query, /)"]
-fn seq_distances_aligned(target: &str, query: &str) -> [f64; 4] {
-    crate::distance::seq_distances_aligned(target, query)
+#[pyfunction(
+    distances = "None",
+    gamma_shape = "None",
+    max_distance = "None",
+    options = "None"
+)]
+#[text_signature = "(target, query, /, distances=None, gamma_shape=None, max_distance=None, options=None)"]
+fn seq_distances_protein_aligned(
+    target: &str,
+    query: &str,
+    distances: Option<Vec<String>>,
+    gamma_shape: Option<f64>,
+    max_distance: Option<f64>,
+    options: Option<DistanceOptions>,
+) -> PyResult<Vec<f64>> {
+    let options = options.unwrap_or_default();
+    let distances = selected_protein_distances(distances, gamma_shape, max_distance, &options)?;
+    Ok(crate::distance::seq_distances_protein_aligned(
+        target, query, &distances, &options,
+    ))
 }
 
-/// Returns 2D array of distances between `targets` and `queries`.
+/// Returns distances between `target` and `query` like `seq_distances`,
+/// and whether the reverse complement of `query` was used for them.
+///
+/// Performs alignment.
+#[pyfunction(
+    distances = "None",
+    gamma_shape = "None",
+    max_distance = "None",
+    options = "None"
+)]
+#[text_signature = "(aligner, target, query, /, distances=None, gamma_shape=None, max_distance=None, options=None)"]
+fn seq_distances_oriented(
+    aligner: &Aligner,
+    target: &str,
+    query: &str,
+    distances: Option<Vec<String>>,
+    gamma_shape: Option<f64>,
+    max_distance: Option<f64>,
+    options: Option<DistanceOptions>,
+) -> PyResult<(Vec<f64>, bool)> {
+    let options = options.unwrap_or_default();
+    let distances = selected_distances(distances, gamma_shape, max_distance, &options, false)?;
+    let (distances, orientation) =
+        crate::distance::seq_distances_oriented(aligner, target, query, &distances, &options)?;
+    Ok((distances, orientation == Orientation::Reverse))
+}
+
+/// Returns distances between `target` and `query`, selected like in `seq_distances`.
+///
+/// Expects aligned sequences.
+#[pyfunction(
+    distances = "None",
+    gamma_shape = "None",
+    max_distance = "None",
+    options = "None"
+)]
+#[text_signature = "(target, query, /, distances=None, gamma_shape=None, max_distance=None, options=None)"]
+fn seq_distances_aligned(
+    target: &str,
+    query: &str,
+    distances: Option<Vec<String>>,
+    gamma_shape: Option<f64>,
+    max_distance: Option<f64>,
+    options: Option<DistanceOptions>,
+) -> PyResult<Vec<f64>> {
+    let options = options.unwrap_or_default();
+    let distances = selected_distances(distances, gamma_shape, max_distance, &options, true)?;
+    Ok(crate::distance::seq_distances_aligned(
+        target, query, &distances, &options,
+    ))
+}
+
+/// Returns 2D array of distances between `targets` and `queries`,
+/// with a column for each distance selected like in `seq_distances`.
 ///
 /// `targets` and `queries` should be pandas string columns.
 /// Outer iteration over `targets`, inner iteration of `queries`.
 ///
 /// Performs alignment.
-#[pyfunction]
-#[text_signature = "(aligner, targets, queries, /)"]
+#[pyfunction(
+    distances = "None",
+    gamma_shape = "None",
+    max_distance = "None",
+    options = "None"
+)]
+#[text_signature = "(aligner, targets, queries, /, distances=None, gamma_shape=None, max_distance=None, options=None)"]
+#[allow(clippy::too_many_arguments)]
 fn make_distance_array<'py>(
     py: Python<'py>,
     aligner: &Aligner,
     targets: &PyAny,
     queries: &PyAny,
+    distances: Option<Vec<String>>,
+    gamma_shape: Option<f64>,
+    max_distance: Option<f64>,
+    options: Option<DistanceOptions>,
 ) -> PyResult<&'py numpy::PyArray2<f64>> {
+    let options = options.unwrap_or_default();
+    let distances = selected_distances(distances, gamma_shape, max_distance, &options, false)?;
     let is_same = std::ptr::eq(targets, queries);
     let targets = &Column::new(py, targets)?.strings;
-    if is_same {
-        PyArray2::from_vec2(
-            py,
-            &distance::make_distance_array(aligner, &targets, &targets),
-        )
+    let array = if is_same {
+        distance::make_distance_array(aligner, targets, targets, &distances, &options)?
     } else {
         let queries = Column::new(py, queries)?.strings;
-        PyArray2::from_vec2(
-            py,
-            &distance::make_distance_array(aligner, &targets, &queries),
-        )
-    }
-    .map_err(|_| exceptions::PyRuntimeError::new_err("can't convert Vec to numpy array"))
+        distance::make_distance_array(aligner, targets, &queries, &distances, &options)?
+    };
+    PyArray2::from_vec2(py, &array)
+        .map_err(|_| exceptions::PyRuntimeError::new_err("can't convert Vec to numpy array"))
 }
 
-/// Returns 2D array of distances between `targets` and `queries`.
+/// Returns 2D array of distances between `targets` and `queries`,
+/// with a column for each distance selected like in `seq_distances`.
 ///
 /// `targets` and `queries` should be pandas string columns.
 /// Outer iteration over `targets`, inner iteration of `queries`.
-#[pyfunction]
-#[text_signature = "(targets, queries, /)"]
+#[pyfunction(
+    distances = "None",
+    gamma_shape = "None",
+    max_distance = "None",
+    options = "None"
+)]
+#[text_signature = "(targets, queries, /, distances=None, gamma_shape=None, max_distance=None, options=None)"]
 fn make_distance_array_aligned<'py>(
     py: Python<'py>,
     targets: &PyAny,
     queries: &PyAny,
+    distances: Option<Vec<String>>,
+    gamma_shape: Option<f64>,
+    max_distance: Option<f64>,
+    options: Option<DistanceOptions>,
 ) -> PyResult<&'py numpy::PyArray2<f64>> {
+    let options = options.unwrap_or_default();
+    let distances = selected_distances(distances, gamma_shape, max_distance, &options, true)?;
     let is_same = std::ptr::eq(targets, queries);
     let targets = &Column::new(py, targets)?.strings;
     if is_same {
         PyArray2::from_vec2(
             py,
-            &distance::make_distance_array_aligned(&targets, &targets),
+            &distance::make_distance_array_aligned(targets, targets, &distances, &options),
         )
     } else {
         let queries = Column::new(py, queries)?.strings;
         PyArray2::from_vec2(
             py,
-            &distance::make_distance_array_aligned(&targets, &queries),
+            &distance::make_distance_array_aligned(targets, &queries, &distances, &options),
         )
     }
     .map_err(|_| exceptions::PyRuntimeError::new_err("can't convert Vec to numpy array"))
 }
 
+/// Returns 2D array of distances between the protein sequences `targets` and `queries`,
+/// with a column for each distance selected like in `seq_distances_protein`.
+///
+/// `targets` and `queries` should be pandas string columns.
+/// Outer iteration over `targets`, inner iteration of `queries`.
+///
+/// Performs alignment.
+#[pyfunction(
+    distances = "None",
+    gamma_shape = "None",
+    max_distance = "None",
+    options = "None"
+)]
+#[text_signature = "(aligner, targets, queries, /, distances=None, gamma_shape=None, max_distance=None, options=None)"]
+#[allow(clippy::too_many_arguments)]
+fn make_distance_array_protein<'py>(
+    py: Python<'py>,
+    aligner: &Aligner,
+    targets: &PyAny,
+    queries: &PyAny,
+    distances: Option<Vec<String>>,
+    gamma_shape: Option<f64>,
+    max_distance: Option<f64>,
+    options: Option<DistanceOptions>,
+) -> PyResult<&'py numpy::PyArray2<f64>> {
+    let options = options.unwrap_or_default();
+    let distances = selected_protein_distances(distances, gamma_shape, max_distance, &options)?;
+    let is_same = std::ptr::eq(targets, queries);
+    let targets = &Column::new(py, targets)?.strings;
+    let array = if is_same {
+        distance::make_distance_array_protein(aligner, targets, targets, &distances, &options)?
+    } else {
+        let queries = Column::new(py, queries)?.strings;
+        distance::make_distance_array_protein(aligner, targets, &queries, &distances, &options)?
+    };
+    PyArray2::from_vec2(py, &array)
+        .map_err(|_| exceptions::PyRuntimeError::new_err("can't convert Vec to numpy array"))
+}
 
-#[pyfunction]
-#[text_signature = "(target, query, /)"]
-fn seq_distances_p(target: &str, query: &str) -> f64 {
-    crate::distance::seq_distances_p(target, query)
+/// Returns 2D array of distances between the aligned protein sequences `targets` and `queries`,
+/// with a column for each distance selected like in `seq_distances_protein`.
+///
+/// `targets` and `queries` should be pandas string columns.
+/// Outer iteration over `targets`, inner iteration of `queries`.
+#[pyfunction(
+    distances = "None",
+    gamma_shape = "None",
+    max_distance = "None",
+    options = "None"
+)]
+#[text_signature = "(targets, queries, /, distances=None, gamma_shape=None, max_distance=None, options=None)"]
+fn make_distance_array_protein_aligned<'py>(
+    py: Python<'py>,
+    targets: &PyAny,
+    queries: &PyAny,
+    distances: Option<Vec<String>>,
+    gamma_shape: Option<f64>,
+    max_distance: Option<f64>,
+    options: Option<DistanceOptions>,
+) -> PyResult<&'py numpy::PyArray2<f64>> {
+    let options = options.unwrap_or_default();
+    let distances = selected_protein_distances(distances, gamma_shape, max_distance, &options)?;
+    let is_same = std::ptr::eq(targets, queries);
+    let targets = &Column::new(py, targets)?.strings;
+    let array = if is_same {
+        distance::make_distance_array_protein_aligned(targets, targets, &distances, &options)
+    } else {
+        let queries = Column::new(py, queries)?.strings;
+        distance::make_distance_array_protein_aligned(targets, &queries, &distances, &options)
+    };
+    PyArray2::from_vec2(py, &array)
+        .map_err(|_| exceptions::PyRuntimeError::new_err("can't convert Vec to numpy array"))
 }
-#[pyfunction]
-#[text_signature = "(target, query, /)"]
-fn seq_distances_p_gaps(target: &str, query: &str) -> f64 {
-    crate::distance::seq_distances_p_gaps(target, query)
+
+/// Returns distances between `target` and `query` like `seq_distances`, and their standard errors.
+///
+/// Performs alignment.
+#[pyfunction(
+    distances = "None",
+    gamma_shape = "None",
+    max_distance = "None",
+    options = "None"
+)]
+#[text_signature = "(aligner, target, query, /, distances=None, gamma_shape=None, max_distance=None, options=None)"]
+fn seq_distances_with_errors(
+    aligner: &Aligner,
+    target: &str,
+    query: &str,
+    distances: Option<Vec<String>>,
+    gamma_shape: Option<f64>,
+    max_distance: Option<f64>,
+    options: Option<DistanceOptions>,
+) -> PyResult<(Vec<f64>, Vec<f64>)> {
+    let options = options.unwrap_or_default();
+    let distances = selected_distances(distances, gamma_shape, max_distance, &options, false)?;
+    Ok(crate::distance::seq_distances_with_errors(
+        aligner, target, query, &distances, &options,
+    )?)
 }
-#[pyfunction]
-#[text_signature = "(target, query, /)"]
-fn seq_distances_jukes_cantor(target: &str, query: &str) -> f64 {
-    crate::distance::seq_distances_jukes_cantor(target, query)
+
+/// Returns distances between `target` and `query` like `seq_distances_aligned`,
+/// and their standard errors.
+///
+/// Expects aligned sequences.
+#[pyfunction(
+    distances = "None",
+    gamma_shape = "None",
+    max_distance = "None",
+    options = "None"
+)]
+#[text_signature = "(target, query, /, distances=None, gamma_shape=None, max_distance=None, options=None)"]
+fn seq_distances_aligned_with_errors(
+    target: &str,
+    query: &str,
+    distances: Option<Vec<String>>,
+    gamma_shape: Option<f64>,
+    max_distance: Option<f64>,
+    options: Option<DistanceOptions>,
+) -> PyResult<(Vec<f64>, Vec<f64>)> {
+    let options = options.unwrap_or_default();
+    let distances = selected_distances(distances, gamma_shape, max_distance, &options, true)?;
+    Ok(crate::distance::seq_distances_aligned_with_errors(
+        target, query, &distances, &options,
+    ))
 }
-#[pyfunction]
-#[text_signature = "(target, query, /)"]
-fn seq_distances_kimura2p(target: &str, query: &str) -> f64 {
-    crate::distance::seq_distances_kimura2p(target, query)
+
+/// Returns 2D arrays of distances between `targets` and `queries` like `make_distance_array`,
+/// and of their standard errors.
+///
+/// Performs alignment.
+#[pyfunction(
+    distances = "None",
+    gamma_shape = "None",
+    max_distance = "None",
+    options = "None"
+)]
+#[text_signature = "(aligner, targets, queries, /, distances=None, gamma_shape=None, max_distance=None, options=None)"]
+#[allow(clippy::too_many_arguments)]
+fn make_distance_array_with_errors<'py>(
+    py: Python<'py>,
+    aligner: &Aligner,
+    targets: &PyAny,
+    queries: &PyAny,
+    distances: Option<Vec<String>>,
+    gamma_shape: Option<f64>,
+    max_distance: Option<f64>,
+    options: Option<DistanceOptions>,
+) -> PyResult<(&'py numpy::PyArray2<f64>, &'py numpy::PyArray2<f64>)> {
+    let options = options.unwrap_or_default();
+    let distances = selected_distances(distances, gamma_shape, max_distance, &options, false)?;
+    let is_same = std::ptr::eq(targets, queries);
+    let targets = &Column::new(py, targets)?.strings;
+    let (array, errors) = if is_same {
+        distance::make_distance_array_with_errors(aligner, targets, targets, &distances, &options)?
+    } else {
+        let queries = Column::new(py, queries)?.strings;
+        distance::make_distance_array_with_errors(aligner, targets, &queries, &distances, &options)?
+    };
+    to_numpy_pair(py, &array, &errors)
+}
+
+/// Returns 2D arrays of distances between `targets` and `queries` like `make_distance_array_aligned`,
+/// and of their standard errors.
+#[pyfunction(
+    distances = "None",
+    gamma_shape = "None",
+    max_distance = "None",
+    options = "None"
+)]
+#[text_signature = "(targets, queries, /, distances=None, gamma_shape=None, max_distance=None, options=None)"]
+fn make_distance_array_aligned_with_errors<'py>(
+    py: Python<'py>,
+    targets: &PyAny,
+    queries: &PyAny,
+    distances: Option<Vec<String>>,
+    gamma_shape: Option<f64>,
+    max_distance: Option<f64>,
+    options: Option<DistanceOptions>,
+) -> PyResult<(&'py numpy::PyArray2<f64>, &'py numpy::PyArray2<f64>)> {
+    let options = options.unwrap_or_default();
+    let distances = selected_distances(distances, gamma_shape, max_distance, &options, true)?;
+    let is_same = std::ptr::eq(targets, queries);
+    let targets = &Column::new(py, targets)?.strings;
+    let (array, errors) = if is_same {
+        distance::make_distance_array_aligned_with_errors(targets, targets, &distances, &options)
+    } else {
+        let queries = Column::new(py, queries)?.strings;
+        distance::make_distance_array_aligned_with_errors(targets, &queries, &distances, &options)
+    };
+    to_numpy_pair(py, &array, &errors)
+}
+
+/// Converts the arrays of distances and of their standard errors to numpy arrays.
+fn to_numpy_pair<'py>(
+    py: Python<'py>,
+    array: &[Vec<f64>],
+    errors: &[Vec<f64>],
+) -> PyResult<(&'py numpy::PyArray2<f64>, &'py numpy::PyArray2<f64>)> {
+    let convert = |array| {
+        PyArray2::from_vec2(py, array)
+            .map_err(|_| exceptions::PyRuntimeError::new_err("can't convert Vec to numpy array"))
+    };
+    Ok((convert(array)?, convert(errors)?))
+}
+
+/// Returns 3D array of `replicates` bootstrap replicates of the array of distances
+/// between aligned `targets` and `queries` like `make_distance_array_aligned`.
+///
+/// The alignment columns are resampled with replacement, using the random `seed`.
+/// All the sequences should have the same number of columns.
+#[pyfunction(
+    seed = "0",
+    distances = "None",
+    gamma_shape = "None",
+    max_distance = "None",
+    options = "None"
+)]
+#[text_signature = "(targets, queries, replicates, /, seed=0, distances=None, gamma_shape=None, max_distance=None, options=None)"]
+#[allow(clippy::too_many_arguments)]
+fn bootstrap_distance_array_aligned<'py>(
+    py: Python<'py>,
+    targets: &PyAny,
+    queries: &PyAny,
+    replicates: usize,
+    seed: u64,
+    distances: Option<Vec<String>>,
+    gamma_shape: Option<f64>,
+    max_distance: Option<f64>,
+    options: Option<DistanceOptions>,
+) -> PyResult<&'py PyArray3<f64>> {
+    let options = options.unwrap_or_default();
+    let distances = selected_distances(distances, gamma_shape, max_distance, &options, true)?;
+    let replicates =
+        bootstrap_replicates(py, targets, queries, replicates, seed, &distances, &options)?;
+    PyArray3::from_vec3(py, &replicates)
+        .map_err(|_| exceptions::PyRuntimeError::new_err("can't convert Vec to numpy array"))
+}
+
+/// Returns 2D arrays of the means of the bootstrap replicates of the distances
+/// between aligned `targets` and `queries`, and of the lower and upper bounds
+/// of their `confidence` percentile intervals.
+///
+/// Makes the replicates like `bootstrap_distance_array_aligned`.
+#[pyfunction(
+    seed = "0",
+    confidence = "0.95",
+    distances = "None",
+    gamma_shape = "None",
+    max_distance = "None",
+    options = "None"
+)]
+#[text_signature = "(targets, queries, replicates, /, seed=0, confidence=0.95, distances=None, gamma_shape=None, max_distance=None, options=None)"]
+#[allow(clippy::too_many_arguments)]
+fn bootstrap_distance_summary_aligned<'py>(
+    py: Python<'py>,
+    targets: &PyAny,
+    queries: &PyAny,
+    replicates: usize,
+    seed: u64,
+    confidence: f64,
+    distances: Option<Vec<String>>,
+    gamma_shape: Option<f64>,
+    max_distance: Option<f64>,
+    options: Option<DistanceOptions>,
+) -> PyResult<(&'py PyArray2<f64>, &'py PyArray2<f64>, &'py PyArray2<f64>)> {
+    if !(0.0..=1.0).contains(&confidence) {
+        return Err(exceptions::PyValueError::new_err(format!(
+            "confidence should be between 0 and 1, got {}",
+            confidence
+        )));
+    }
+    let options = options.unwrap_or_default();
+    let distances = selected_distances(distances, gamma_shape, max_distance, &options, true)?;
+    let replicates =
+        bootstrap_replicates(py, targets, queries, replicates, seed, &distances, &options)?;
+    let summary = bootstrap::summarize(&replicates, confidence);
+    let convert = |array| {
+        PyArray2::from_vec2(py, array)
+            .map_err(|_| exceptions::PyRuntimeError::new_err("can't convert Vec to numpy array"))
+    };
+    Ok((
+        convert(&summary.mean)?,
+        convert(&summary.lower)?,
+        convert(&summary.upper)?,
+    ))
+}
+
+/// Makes the bootstrap replicates of the distances between the columns `targets` and `queries`.
+fn bootstrap_replicates(
+    py: Python,
+    targets: &PyAny,
+    queries: &PyAny,
+    replicates: usize,
+    seed: u64,
+    distances: &[Distance],
+    options: &DistanceOptions,
+) -> PyResult<Vec<Vec<Vec<f64>>>> {
+    let is_same = std::ptr::eq(targets, queries);
+    let targets = &Column::new(py, targets)?.strings;
+    Ok(if is_same {
+        bootstrap::bootstrap_distance_arrays_aligned(
+            targets, targets, distances, options, replicates, seed,
+        )?
+    } else {
+        let queries = Column::new(py, queries)?.strings;
+        bootstrap::bootstrap_distance_arrays_aligned(
+            targets, &queries, distances, options, replicates, seed,
+        )?
+    })
+}
+
+/// Returns true if the `options` count fractions of sites,
+/// by the "overlap" or "average" ambiguity policies.
+fn counts_fractions(options: &DistanceOptions) -> bool {
+    matches!(
+        options.ambiguity,
+        AmbiguityPolicy::Overlap | AmbiguityPolicy::Average
+    )
+}
+
+/// Returns the whole numbers of sites `counts`.
+fn whole_counts(counts: &[f64]) -> Vec<usize> {
+    counts.iter().map(|&count| count as usize).collect()
+}
+
+/// Returns the numbers of sites of the common content of `target` and `query`,
+/// named by `SITE_COUNTS`.
+///
+/// The numbers are integers, unless the "overlap" or "average" ambiguity policies of `options`
+/// count fractions of ambiguous sites, which makes them floats.
+///
+/// Expects aligned sequences.
+#[pyfunction(options = "None")]
+#[text_signature = "(target, query, /, options=None)"]
+fn seq_site_counts_aligned(
+    py: Python,
+    target: &str,
+    query: &str,
+    options: Option<DistanceOptions>,
+) -> PyObject {
+    let options = options.unwrap_or_default();
+    let counts = crate::distance::seq_site_counts_aligned(target, query, &options);
+    if counts_fractions(&options) {
+        counts.to_vec().into_py(py)
+    } else {
+        whole_counts(&counts).into_py(py)
+    }
+}
+
+/// Returns 2D array of the numbers of sites of `targets` and `queries`,
+/// with the columns named by `SITE_COUNTS`.
+///
+/// The array has an integer dtype, unless the "overlap" or "average" ambiguity policies
+/// of `options` count fractions of ambiguous sites, which makes it a float one.
+///
+/// `targets` and `queries` should be pandas string columns.
+/// Outer iteration over `targets`, inner iteration of `queries`.
+#[pyfunction(options = "None")]
+#[text_signature = "(targets, queries, /, options=None)"]
+fn make_site_count_array_aligned(
+    py: Python,
+    targets: &PyAny,
+    queries: &PyAny,
+    options: Option<DistanceOptions>,
+) -> PyResult<PyObject> {
+    let options = options.unwrap_or_default();
+    let is_same = std::ptr::eq(targets, queries);
+    let targets = &Column::new(py, targets)?.strings;
+    let counts = if is_same {
+        distance::make_site_count_array_aligned(targets, targets, &options)
+    } else {
+        let queries = Column::new(py, queries)?.strings;
+        distance::make_site_count_array_aligned(targets, &queries, &options)
+    };
+    if counts_fractions(&options) {
+        PyArray2::from_vec2(py, &counts).map(|array| array.to_object(py))
+    } else {
+        let counts: Vec<Vec<usize>> = counts.iter().map(|row| whole_counts(row)).collect();
+        PyArray2::from_vec2(py, &counts).map(|array| array.to_object(py))
+    }
+    .map_err(|_| exceptions::PyRuntimeError::new_err("can't convert Vec to numpy array"))
+}
+
+#[pyfunction(options = "None")]
+#[text_signature = "(target, query, /, options=None)"]
+fn seq_distances_p(target: &str, query: &str, options: Option<DistanceOptions>) -> f64 {
+    crate::distance::seq_distances_p(target, query, &options.unwrap_or_default())
+}
+#[pyfunction(options = "None")]
+#[text_signature = "(target, query, /, options=None)"]
+fn seq_distances_p_gaps(target: &str, query: &str, options: Option<DistanceOptions>) -> f64 {
+    crate::distance::seq_distances_p_gaps(target, query, &options.unwrap_or_default())
+}
+#[pyfunction(options = "None")]
+#[text_signature = "(target, query, /, options=None)"]
+fn seq_distances_jukes_cantor(
+    target: &str,
+    query: &str,
+    options: Option<DistanceOptions>,
+) -> PyResult<f64> {
+    let options = options.unwrap_or_default();
+    check_model_gaps("jukes_cantor", &options)?;
+    Ok(crate::distance::seq_distances_jukes_cantor(
+        target, query, &options,
+    ))
+}
+#[pyfunction(options = "None")]
+#[text_signature = "(target, query, /, options=None)"]
+fn seq_distances_kimura2p(
+    target: &str,
+    query: &str,
+    options: Option<DistanceOptions>,
+) -> PyResult<f64> {
+    let options = options.unwrap_or_default();
+    check_model_gaps("kimura2p", &options)?;
+    Ok(crate::distance::seq_distances_kimura2p(
+        target, query, &options,
+    ))
+}
+#[pyfunction(options = "None")]
+#[text_signature = "(target, query, /, options=None)"]
+fn seq_distances_tamura3p(
+    target: &str,
+    query: &str,
+    options: Option<DistanceOptions>,
+) -> PyResult<f64> {
+    let options = options.unwrap_or_default();
+    check_model_gaps("tamura3p", &options)?;
+    Ok(crate::distance::seq_distances_tamura3p(
+        target, query, &options,
+    ))
+}
+#[pyfunction(options = "None")]
+#[text_signature = "(target, query, /, options=None)"]
+fn seq_distances_tamura_nei(
+    target: &str,
+    query: &str,
+    options: Option<DistanceOptions>,
+) -> PyResult<f64> {
+    let options = options.unwrap_or_default();
+    check_model_gaps("tamura_nei", &options)?;
+    Ok(crate::distance::seq_distances_tamura_nei(
+        target, query, &options,
+    ))
 }
 
 /// A Python module implemented in Rust.
 #[pymodule]
 fn calculate_distances(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PyAlignment>()?;
+    m.add_class::<SubstitutionMatrix>()?;
+    m.add_class::<DistanceOptions>()?;
     m.add_function(wrap_pyfunction!(align_to_str, m)?)?;
     m.add_function(wrap_pyfunction!(align_seq, m)?)?;
     m.add_function(wrap_pyfunction!(make_aligner, m)?)?;
+    m.add_function(wrap_pyfunction!(substitution_matrix, m)?)?;
+    m.add_function(wrap_pyfunction!(transition_transversion_matrix, m)?)?;
+    m.add_function(wrap_pyfunction!(make_substitution_matrix, m)?)?;
+    m.add_function(wrap_pyfunction!(make_distance_options, m)?)?;
+    m.add_function(wrap_pyfunction!(translate, m)?)?;
+    m.add_function(wrap_pyfunction!(stop_codons, m)?)?;
     m.add_function(wrap_pyfunction!(seq_distances, m)?)?;
+    m.add_function(wrap_pyfunction!(seq_distances_oriented, m)?)?;
     m.add_function(wrap_pyfunction!(seq_distances_aligned, m)?)?;
     m.add_function(wrap_pyfunction!(seq_distances_p, m)?)?;
     m.add_function(wrap_pyfunction!(seq_distances_p_gaps, m)?)?;
     m.add_function(wrap_pyfunction!(seq_distances_jukes_cantor, m)?)?;
     m.add_function(wrap_pyfunction!(seq_distances_kimura2p, m)?)?;
+    m.add_function(wrap_pyfunction!(seq_distances_tamura3p, m)?)?;
+    m.add_function(wrap_pyfunction!(seq_distances_tamura_nei, m)?)?;
+    m.add_function(wrap_pyfunction!(seq_distances_protein, m)?)?;
+    m.add_function(wrap_pyfunction!(seq_distances_protein_aligned, m)?)?;
     m.add_function(wrap_pyfunction!(show_alignment, m)?)?;
+    m.add_function(wrap_pyfunction!(make_alignment, m)?)?;
+    m.add_function(wrap_pyfunction!(count_alignments, m)?)?;
+    m.add_function(wrap_pyfunction!(co_optimal_alignments, m)?)?;
     m.add_function(wrap_pyfunction!(make_distance_array, m)?)?;
     m.add_function(wrap_pyfunction!(make_distance_array_aligned, m)?)?;
+    m.add_function(wrap_pyfunction!(make_distance_array_protein, m)?)?;
+    m.add_function(wrap_pyfunction!(make_distance_array_protein_aligned, m)?)?;
+    m.add_function(wrap_pyfunction!(seq_distances_with_errors, m)?)?;
+    m.add_function(wrap_pyfunction!(seq_distances_aligned_with_errors, m)?)?;
+    m.add_function(wrap_pyfunction!(make_distance_array_with_errors, m)?)?;
+    m.add_function(wrap_pyfunction!(
+        make_distance_array_aligned_with_errors,
+        m
+    )?)?;
+    m.add_function(wrap_pyfunction!(bootstrap_distance_array_aligned, m)?)?;
+    m.add_function(wrap_pyfunction!(bootstrap_distance_summary_aligned, m)?)?;
+    m.add_function(wrap_pyfunction!(seq_site_counts_aligned, m)?)?;
+    m.add_function(wrap_pyfunction!(make_site_count_array_aligned, m)?)?;
+    m.add("SITE_COUNTS", SITE_COUNTS.to_vec())?;
 
     Ok(())
 }
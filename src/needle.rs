@@ -1,108 +1,992 @@
 //! Aligmnent using Needleman-Wunsch algorithm.
+mod matrix;
 mod score;
+mod simd;
 mod table;
 
 use pyo3::prelude::pyclass;
 
-use score::{Dir, Score};
+pub use matrix::{MatrixError, SubstitutionMatrix};
+use score::{best, Dir, Dirs, ScoreRow, Trace, MAX_SCORE, NEG_INF};
+use std::fmt;
+use std::iter;
+use std::mem;
+use std::ops::RangeInclusive;
 use std::string::FromUtf8Error;
 use table::Table;
 
+/// Error returned when an alignment score could exceed [MAX_SCORE].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ScoreOverflow;
+
+impl fmt::Display for ScoreOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "alignment score could exceed {} in absolute value",
+            MAX_SCORE
+        )
+    }
+}
+
+impl std::error::Error for ScoreOverflow {}
+
+/// Which parts of the sequences are aligned.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AlignmentMode {
+    /// Needleman-Wunsch: the whole sequences, end gaps use the end gap penalties.
+    Global,
+    /// Smith-Waterman: the best scoring pair of subsequences.
+    Local,
+    /// The whole query inside the target: end gaps in the query are free.
+    Glocal,
+    /// Overlapping sequences: all end gaps are free.
+    Overlap,
+}
+
+impl AlignmentMode {
+    /// Returns whether the free end gaps leave the ends of the target
+    /// and the ends of the query unaligned, rather than aligned to gaps.
+    fn unaligned_ends(self) -> (bool, bool) {
+        match self {
+            AlignmentMode::Global => (false, false),
+            AlignmentMode::Glocal => (true, false),
+            AlignmentMode::Local | AlignmentMode::Overlap => (true, true),
+        }
+    }
+}
+
+/// Which of the co-optimal alignments [Aligner::align] returns.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TiePolicy {
+    /// Gaps as far left as possible: the traceback prefers a match or mismatch,
+    /// then a gap in the target, then a gap in the query.
+    LeftmostGaps,
+    /// Gaps as far right as possible: the traceback prefers a gap in the target,
+    /// then a gap in the query, then a match or mismatch.
+    RightmostGaps,
+    /// A mismatch rather than a pair of gaps, otherwise gaps as far right as possible.
+    PreferSubstitution,
+}
+
+impl TiePolicy {
+    /// Returns the order of preference of the matrices of a cell,
+    /// `mismatch` tells whether its symbols differ.
+    fn order(self, mismatch: bool) -> [Dir; 3] {
+        match self {
+            TiePolicy::LeftmostGaps => [Dir::Diagonal, Dir::Up, Dir::Left],
+            TiePolicy::PreferSubstitution if mismatch => [Dir::Diagonal, Dir::Up, Dir::Left],
+            TiePolicy::RightmostGaps | TiePolicy::PreferSubstitution => {
+                [Dir::Up, Dir::Left, Dir::Diagonal]
+            }
+        }
+    }
+
+    /// Chooses one of the co-optimal matrices `dirs` of a cell, [None] if there are none.
+    fn choose(self, dirs: Dirs, mismatch: bool) -> Option<Dir> {
+        self.order(mismatch)
+            .iter()
+            .copied()
+            .find(|&dir| dirs.contains(dir))
+    }
+}
+
+/// Orientation of the query in an alignment.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Orientation {
+    /// The query as given.
+    Forward,
+    /// The reverse complement of the query.
+    Reverse,
+}
+
+/// Returns the complement of a nucleotide, including the IUPAC ambiguity codes.
+///
+/// Keeps the case, other symbols are their own complement.
+fn complement(c: u8) -> u8 {
+    let complement = match c.to_ascii_uppercase() {
+        b'A' => b'T',
+        b'T' | b'U' => b'A',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'R' => b'Y',
+        b'Y' => b'R',
+        b'K' => b'M',
+        b'M' => b'K',
+        b'B' => b'V',
+        b'V' => b'B',
+        b'D' => b'H',
+        b'H' => b'D',
+        _ => return c,
+    };
+    if c.is_ascii_lowercase() {
+        complement.to_ascii_lowercase()
+    } else {
+        complement
+    }
+}
+
+/// Writes the reverse complement of `seq` into `reverse`.
+pub fn reverse_complement_into(seq: &[u8], reverse: &mut Vec<u8>) {
+    reverse.clear();
+    reverse.extend(seq.iter().rev().map(|&c| complement(c)));
+}
+
 /// Contains parameters for the Needleman-Wunsch algorithm.
 #[pyclass]
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Aligner {
-    pub(super) match_score: i16,
-    pub(super) mismatch_score: i16,
-    pub(super) gap_penalty: i16,
-    pub(super) gap_extend_penalty: i16,
-    pub(super) end_gap_penalty: i16,
-    pub(super) end_gap_extend_penalty: i16,
+    pub(super) match_score: i32,
+    pub(super) mismatch_score: i32,
+    pub(super) gap_penalty: i32,
+    pub(super) gap_extend_penalty: i32,
+    pub(super) end_gap_penalty: i32,
+    pub(super) end_gap_extend_penalty: i32,
+    /// Scores pairs of symbols instead of `match_score` and `mismatch_score`.
+    ///
+    /// Pairs missing from the matrix are still scored with `match_score` and `mismatch_score`.
+    pub(super) substitution_matrix: Option<SubstitutionMatrix>,
+    pub(super) mode: AlignmentMode,
+    /// Number of cells of the table above which the alignment takes linear memory.
+    pub(super) linear_memory_threshold: usize,
+    /// Number of diagonals around the main diagonal to compute, all of them if [None].
+    pub(super) band_width: Option<usize>,
+    /// Whether to widen the band and align again if the path touches its edge.
+    pub(super) widen_band: bool,
+    /// Which of the co-optimal alignments to return.
+    pub(super) tie_policy: TiePolicy,
+    /// Whether [Aligner::align_oriented] also aligns the reverse complement of the query.
+    pub(super) detect_orientation: bool,
 }
 
 impl Aligner {
-    /// Construct the Needleman-Wunsch table for `target` and `query`.
+    /// Largest absolute value of the scores and penalties.
+    fn max_abs_score(&self) -> i32 {
+        [
+            self.match_score,
+            self.mismatch_score,
+            self.gap_penalty,
+            self.gap_extend_penalty,
+            self.end_gap_penalty,
+            self.end_gap_extend_penalty,
+        ]
+        .iter()
+        .map(|score| score.saturating_abs())
+        .chain(
+            self.substitution_matrix
+                .as_ref()
+                .map(SubstitutionMatrix::max_abs_score),
+        )
+        .max()
+        .unwrap_or(0)
+    }
+
+    /// Returns the score of aligning `target_c` to `query_c`.
+    fn substitution_score(&self, target_c: u8, query_c: u8) -> i32 {
+        self.substitution_matrix
+            .as_ref()
+            .and_then(|matrix| matrix.score(target_c, query_c))
+            .unwrap_or(if target_c == query_c {
+                self.match_score
+            } else {
+                self.mismatch_score
+            })
+    }
+
+    /// Checks that each score and penalty doesn't exceed [MAX_SCORE] in absolute value.
+    /// # Errors
+    /// Returns [ScoreOverflow] otherwise.
+    pub fn check_scores(&self) -> Result<(), ScoreOverflow> {
+        if self.max_abs_score() <= MAX_SCORE {
+            Ok(())
+        } else {
+            Err(ScoreOverflow)
+        }
+    }
+
+    /// Checks that aligning sequences of lengths `target_len` and `query_len`
+    /// can't produce a score exceeding [MAX_SCORE] in absolute value.
+    ///
+    /// Each step of the path changes the score by at most [Aligner::max_abs_score]
+    /// and there are at most `target_len + query_len` steps.
+    /// # Errors
+    /// Returns [ScoreOverflow] otherwise.
+    pub fn check_lengths(&self, target_len: usize, query_len: usize) -> Result<(), ScoreOverflow> {
+        (target_len + query_len)
+            .checked_mul(self.max_abs_score() as usize)
+            .filter(|&bound| bound <= MAX_SCORE as usize)
+            .map(|_| ())
+            .ok_or(ScoreOverflow)
+    }
+
+    /// Returns the penalties for opening and extending a gap in the query
+    /// in the row `row` of the table for a query of length `query_len`.
+    ///
+    /// Gaps in the first and the last row are end gaps.
+    fn left_gap_penalties(&self, row: usize, query_len: usize) -> (i32, i32) {
+        match self.mode {
+            _ if row != 0 && row != query_len => (self.gap_penalty, self.gap_extend_penalty),
+            AlignmentMode::Global => (self.end_gap_penalty, self.end_gap_extend_penalty),
+            AlignmentMode::Glocal | AlignmentMode::Overlap => (0, 0),
+            AlignmentMode::Local => (self.gap_penalty, self.gap_extend_penalty),
+        }
+    }
+
+    /// Returns the penalties for opening and extending a gap in the target
+    /// in the column `column` of the table for a target of length `target_len`.
+    ///
+    /// Gaps in the first and the last column are end gaps.
+    fn up_gap_penalties(&self, column: usize, target_len: usize) -> (i32, i32) {
+        match self.mode {
+            _ if column != 0 && column != target_len => (self.gap_penalty, self.gap_extend_penalty),
+            AlignmentMode::Global => (self.end_gap_penalty, self.end_gap_extend_penalty),
+            AlignmentMode::Overlap => (0, 0),
+            AlignmentMode::Glocal | AlignmentMode::Local => {
+                (self.gap_penalty, self.gap_extend_penalty)
+            }
+        }
+    }
+
+    /// Align `target` and `query` with the Gotoh algorithm.
+    ///
+    /// Keeps three matrices: for paths ending with a match or mismatch,
+    /// with a gap in the target and with a gap in the query,
+    /// so that each gap is opened exactly once.
+    ///
+    /// In the local mode the path may start and end in any cell,
+    /// the rest of the sequences is aligned as end gaps.
+    ///
+    /// With a `band_width` only the cells within that many diagonals of the main diagonal
+    /// and of the diagonal of the bottom-right corner are computed.
+    /// If `widen_band` is set and the path touches an edge of the band,
+    /// the band is widened twice and the sequences aligned again,
+    /// until the path is inside the band.
+    ///
+    /// Only the traceback of the table is kept, and only if it has at most
+    /// `linear_memory_threshold` cells. Above that the scores are computed again
+    /// for halves of the rows, until the traceback of the rows fits,
+    /// so that the memory grows linearly with the length of the target.
+    /// Both produce the same alignment.
+    ///
+    /// Among the co-optimal alignments, the one preferred by the `tie_policy` is returned.
+    /// # Errors
+    /// Returns [ScoreOverflow] if the score could exceed [MAX_SCORE] in absolute value.
     pub fn align<'target, 'query>(
         &self,
         target: &'target [u8],
         query: &'query [u8],
-    ) -> Alignment<'target, 'query> {
-        // Allocate and initialize table
-        let mut table = Table::<Score>::new(target.len() + 1, query.len() + 1);
-        table.fill_default();
-        // Fill the left column and the top row
-        table[[0, 0]] = Score::new(Dir::Diagonal, 0);
-        for i in 0..target.len() {
-            table[[i + 1, 0]] = Score::new(
-                Dir::Left,
-                self.end_gap_penalty + i as i16 * self.end_gap_extend_penalty,
-            );
+    ) -> Result<Alignment<'target, 'query>, ScoreOverflow> {
+        self.align_with(&mut AlignerWorkspace::default(), target, query)
+    }
+
+    /// Align `target` and `query` like [Aligner::align], reusing the buffers of the `workspace`.
+    /// # Errors
+    /// Returns [ScoreOverflow] if the score could exceed [MAX_SCORE] in absolute value.
+    pub fn align_with<'target, 'query>(
+        &self,
+        workspace: &mut AlignerWorkspace,
+        target: &'target [u8],
+        query: &'query [u8],
+    ) -> Result<Alignment<'target, 'query>, ScoreOverflow> {
+        self.with_band(workspace, target, query, |_, _, _, alignment| alignment)
+    }
+
+    /// Align `target` and `query` like [Aligner::align_with], and if `detect_orientation` is set,
+    /// also the reverse complement of `query`, which is written into `reverse`.
+    ///
+    /// Returns the alignment with the better score, and the orientation of the query in it.
+    /// The query is kept as given if both orientations score the same.
+    /// # Errors
+    /// Returns [ScoreOverflow] if the score could exceed [MAX_SCORE] in absolute value.
+    pub fn align_oriented<'target, 'query>(
+        &self,
+        workspace: &mut AlignerWorkspace,
+        target: &'target [u8],
+        query: &'query [u8],
+        reverse: &'query mut Vec<u8>,
+    ) -> Result<(Alignment<'target, 'query>, Orientation), ScoreOverflow> {
+        let forward = self.align_with(workspace, target, query)?;
+        if !self.detect_orientation {
+            return Ok((forward, Orientation::Forward));
         }
-        for j in 0..query.len() {
-            table[[0, j + 1]] = Score::new(
-                Dir::Up,
-                self.end_gap_penalty + j as i16 * self.end_gap_extend_penalty,
-            );
+        reverse_complement_into(query, reverse);
+        let reversed = self.align_with(workspace, target, reverse)?;
+        if reversed.score > forward.score {
+            Ok((reversed, Orientation::Reverse))
+        } else {
+            Ok((forward, Orientation::Forward))
         }
+    }
 
-        // Fill the rest of the table
-        for j in 0..query.len() {
-            // row iteration for efficiency
-            for i in 0..target.len() {
-                // Calculate score from the left
-                let left_cell = table[[i, j + 1]];
-                let left_score = left_cell.score
-                    + match (left_cell.dir, j == query.len() - 1) {
-                        // `j == query.len() - 1` means that we are currently in the bottom row,
-                        // therefore the gap is an end gap.
-                        // if the left cell has the left direction, the gap is being extended
-                        // otherwise it's being opened.
-                        (Dir::Left, false) => self.gap_extend_penalty,
-                        (Dir::Left, true) => self.end_gap_extend_penalty,
-                        (_, false) => self.gap_penalty,
-                        (_, true) => self.end_gap_penalty,
-                    };
-                // Calculate score from up
-                let up_cell = table[[i + 1, j]];
-                // `i == target.len() - 1` means that we are currently in the right column,
-                // therefore the gap is an end gap.
-                // if the left cell has the up direction, the gap is being extended
-                // otherwise it's being opened.
-                let up_score = up_cell.score
-                    + match (up_cell.dir, i == target.len() - 1) {
-                        (Dir::Up, false) => self.gap_extend_penalty,
-                        (Dir::Up, true) => self.end_gap_extend_penalty,
-                        (_, false) => self.gap_penalty,
-                        (_, true) => self.end_gap_penalty,
-                    };
-                // Calculate the diagonal score
-                let diag_score = table[[i, j]].score
-                    // It's either match or mismatch
-                    + if target[i] == query[j] {
-                        self.match_score
-                    } else {
-                        self.mismatch_score
-                    };
-                // Find the maximal score and record the direction from which it came
-                let new_score = if left_score <= diag_score && up_score <= diag_score {
-                    Score::new(Dir::Diagonal, diag_score)
-                } else if left_score <= up_score {
-                    Score::new(Dir::Up, up_score)
-                } else {
-                    Score::new(Dir::Left, left_score)
+    /// Counts the co-optimal alignments of `target` and `query`, saturating at [u64::MAX].
+    ///
+    /// Only the alignments inside the band [Aligner::align] ends up with are counted.
+    /// In the local mode, alignments ending in any cell with the best score are counted.
+    /// # Errors
+    /// Returns [ScoreOverflow] if the score could exceed [MAX_SCORE] in absolute value.
+    pub fn count_alignments(&self, target: &[u8], query: &[u8]) -> Result<u64, ScoreOverflow> {
+        let mut workspace = AlignerWorkspace::default();
+        self.with_band(&mut workspace, target, query, |pair, _, rows, _| {
+            let mut counts = PathCounts::default();
+            let (last_row, _) =
+                self.fill_rows(pair, rows, None, 0..=query.len(), None, Some(&mut counts));
+            if self.mode == AlignmentMode::Local {
+                counts.local_total.max(1)
+            } else {
+                let (_, dirs) = last_row.best(target.len());
+                paths(dirs, || counts.above[target.len()])
+            }
+        })
+    }
+
+    /// Returns up to `limit` co-optimal alignments of `target` and `query`,
+    /// the first of them being the one returned by [Aligner::align].
+    ///
+    /// Alignments are searched like by [Aligner::count_alignments],
+    /// but the traceback of the whole table is kept, regardless of `linear_memory_threshold`.
+    /// # Errors
+    /// Returns [ScoreOverflow] if the score could exceed [MAX_SCORE] in absolute value.
+    pub fn co_optimal_alignments<'target, 'query>(
+        &self,
+        target: &'target [u8],
+        query: &'query [u8],
+        limit: usize,
+    ) -> Result<Vec<Alignment<'target, 'query>>, ScoreOverflow> {
+        let mut workspace = AlignerWorkspace::default();
+        self.with_band(
+            &mut workspace,
+            target,
+            query,
+            |pair, traces, rows, alignment| {
+                let (target_len, query_len) = (target.len(), query.len());
+                traces.reshape(target_len + 1, query_len + 1);
+                let mut counts = PathCounts::default();
+                let (last_row, _) = self.fill_rows(
+                    pair,
+                    rows,
+                    None,
+                    0..=query_len,
+                    Some(traces),
+                    Some(&mut counts),
+                );
+                let ends = match self.mode {
+                    AlignmentMode::Local if !counts.local_ends.is_empty() => counts
+                        .local_ends
+                        .iter()
+                        .map(|&end| (end, Dirs::single(Dir::Diagonal)))
+                        .collect(),
+                    // An empty local alignment
+                    AlignmentMode::Local => vec![([0, 0], Dirs::default())],
+                    _ => vec![([target_len, query_len], last_row.best(target_len).1)],
                 };
-                table[[i + 1, j + 1]] = new_score;
+                self.co_optimal_paths(pair, traces, &ends, limit)
+                    .into_iter()
+                    .map(|path| Alignment {
+                        target,
+                        query,
+                        path,
+                        score: alignment.score,
+                        mode: self.mode,
+                    })
+                    .collect()
+            },
+        )
+    }
+
+    /// Returns up to `limit` optimal paths through the `traces` of the whole table,
+    /// ending in the co-optimal matrices of the cells `ends`.
+    ///
+    /// The paths are searched depth-first, following the preferred matrix first.
+    fn co_optimal_paths(
+        &self,
+        pair: &Pair,
+        traces: &Table<Trace>,
+        ends: &[([usize; 2], Dirs)],
+        limit: usize,
+    ) -> Vec<Vec<Dir>> {
+        let (target_len, query_len) = (pair.target.len(), pair.query.len());
+        let mut paths = Vec::new();
+        let mut path = Vec::new();
+        // Cells and matrices to follow the paths from, with the length of the path before them
+        let mut stack = Vec::new();
+        for &([i, j], dirs) in ends {
+            path.clear();
+            path.extend(iter::repeat_n(Dir::Up, query_len - j));
+            path.extend(iter::repeat_n(Dir::Left, target_len - i));
+            self.push_choices(pair, &mut stack, path.len(), [i, j], dirs);
+            while let Some((len, [i, j], dir)) = stack.pop() {
+                if paths.len() >= limit {
+                    return paths;
+                }
+                path.truncate(len);
+                match dir {
+                    Some(dir) if [i, j] != [0, 0] => {
+                        path.push(dir);
+                        let dirs = traces[[i, j]].get(dir);
+                        let cell = dir.step_back([i, j]);
+                        self.push_choices(pair, &mut stack, path.len(), cell, dirs);
+                    }
+                    _ => {
+                        let mut path = path.clone();
+                        path.extend(iter::repeat_n(Dir::Up, j));
+                        path.extend(iter::repeat_n(Dir::Left, i));
+                        paths.push(path);
+                    }
+                }
+            }
+        }
+        paths
+    }
+
+    /// Pushes the co-optimal matrices `dirs` of the `cell` onto the `stack`,
+    /// so that the one preferred by the tie policy is popped first.
+    ///
+    /// Pushes [None] if the path starts in the cell.
+    fn push_choices(
+        &self,
+        pair: &Pair,
+        stack: &mut Vec<(usize, [usize; 2], Option<Dir>)>,
+        len: usize,
+        cell: [usize; 2],
+        dirs: Dirs,
+    ) {
+        if dirs.is_empty() {
+            stack.push((len, cell, None));
+        }
+        let order = self.tie_policy.order(pair.mismatch(cell));
+        for &dir in order.iter().rev() {
+            if dirs.contains(dir) {
+                stack.push((len, cell, Some(dir)));
+            }
+        }
+    }
+
+    /// Aligns `target` and `query` with the buffers of the `workspace`, widening the band
+    /// as needed, and calls `f` with the pair in the final band and its alignment.
+    /// # Errors
+    /// Returns [ScoreOverflow] if the score could exceed [MAX_SCORE] in absolute value.
+    fn with_band<'target, 'query, R>(
+        &self,
+        workspace: &mut AlignerWorkspace,
+        target: &'target [u8],
+        query: &'query [u8],
+        f: impl FnOnce(
+            &Pair<'target, 'query, '_>,
+            &mut Table<Trace>,
+            &mut RowBuffers,
+            Alignment<'target, 'query>,
+        ) -> R,
+    ) -> Result<R, ScoreOverflow> {
+        self.check_lengths(target.len(), query.len())?;
+        let AlignerWorkspace {
+            traces,
+            rows,
+            profile,
+        } = workspace;
+        profile.fill(self, target, query);
+        let mut pair = Pair {
+            target,
+            query,
+            band: Band::full(target.len(), query.len()),
+            profile,
+        };
+        let mut band_width = self.band_width;
+        loop {
+            if let Some(width) = band_width {
+                pair.band = Band::new(width, target.len(), query.len());
+            }
+            let (alignment, touches_band) = self.align_in_band(&pair, traces, rows);
+            match band_width {
+                Some(width) if touches_band && self.widen_band => {
+                    band_width = Some((width * 2).max(1))
+                }
+                _ => return Ok(f(&pair, traces, rows, alignment)),
             }
         }
+    }
 
-        Alignment {
+    /// Aligns the `pair` inside its band.
+    ///
+    /// Returns the alignment and whether its path touches an edge of the band.
+    fn align_in_band<'target, 'query>(
+        &self,
+        pair: &Pair<'target, 'query, '_>,
+        traces: &mut Table<Trace>,
+        rows: &mut RowBuffers,
+    ) -> (Alignment<'target, 'query>, bool) {
+        let (target, query) = (pair.target, pair.query);
+        let width = target.len() + 1;
+        let height = query.len() + 1;
+        // Number of rows whose traceback fits under the threshold
+        let block_rows = (self.linear_memory_threshold / width).max(1);
+        traces.reshape(width, height.min(block_rows));
+        let whole_table = height <= block_rows;
+
+        let (last_row, local_end) = self.fill_rows(
+            pair,
+            rows,
+            None,
+            0..=query.len(),
+            if whole_table { Some(traces) } else { None },
+            None,
+        );
+        let (end, score) = if self.mode == AlignmentMode::Local {
+            let (end, score) = local_end;
+            ((end, Dir::Diagonal), score)
+        } else {
+            let end = [target.len(), query.len()];
+            let (score, dirs) = last_row.best(target.len());
+            let dir = self.tie_policy.choose(dirs, pair.mismatch(end));
+            ((end, dir.expect("a cell has a best matrix")), score)
+        };
+
+        let mut path = Vec::with_capacity(target.len().max(query.len()));
+        // The trailing parts outside of the path
+        let [i, j] = end.0;
+        path.extend(iter::repeat_n(Dir::Up, query.len() - j));
+        path.extend(iter::repeat_n(Dir::Left, target.len() - i));
+        let trailing = path.len();
+        let start = if whole_table {
+            trace_block(pair, self.tie_policy, traces, 0, end, &mut path)
+        } else {
+            self.trace_rows(pair, rows, None, end, traces, &mut path)
+        };
+        let [i, j] = match start {
+            Traceback::Start(cell) => cell,
+            Traceback::Above(..) => unreachable!("the first row has nothing above"),
+        };
+        let touches_band = pair.touches_band(end.0, &path[trailing..]);
+        // The leading parts outside of the path
+        path.extend(iter::repeat_n(Dir::Up, j));
+        path.extend(iter::repeat_n(Dir::Left, i));
+        let alignment = Alignment {
             target,
             query,
-            table,
+            path,
+            score,
+            mode: self.mode,
+        };
+        (alignment, touches_band)
+    }
+
+    /// Computes the row `j` of the Gotoh table from the row `above` it
+    /// and stores the matrices of the previous cells on the optimal paths into `trace`.
+    ///
+    /// The first row has no row above it. Only the cells inside the band are computed,
+    /// the cells next to them are marked unreachable.
+    /// `codes` holds the sets of previous matrices of the diagonal and up matrices of the row.
+    fn fill_row(
+        &self,
+        pair: &Pair,
+        j: usize,
+        above: Option<&ScoreRow>,
+        row: &mut ScoreRow,
+        codes: &mut [i32],
+        trace: &mut [Trace],
+    ) {
+        let target_len = pair.target.len();
+        let local = self.mode == AlignmentMode::Local;
+        let columns = pair.band.columns(j, target_len);
+        if *columns.start() > 0 {
+            row.set_unreachable(columns.start() - 1);
+        }
+        if *columns.end() < target_len {
+            row.set_unreachable(columns.end() + 1);
+        }
+        let (first, last) = (*columns.start(), *columns.end());
+        match above {
+            Some(above) => {
+                let substitution = pair.profile.row(pair.query[j - 1]);
+                if first == 0 {
+                    // Extend a gap in the target along the left column, or open a new one
+                    let (up, dirs) = if local {
+                        (NEG_INF, Dirs::default())
+                    } else {
+                        let (open, extend) = self.up_gap_penalties(0, target_len);
+                        best(
+                            above.diagonal[0].saturating_add(open),
+                            above.up[0].saturating_add(extend),
+                            above.left[0].saturating_add(open),
+                        )
+                    };
+                    row.diagonal[0] = NEG_INF;
+                    row.up[0] = up;
+                    codes[0] = (dirs.bits() << 3) as i32;
+                }
+                // Gaps in the target in the right column are end gaps
+                let inner_end = (last + 1).min(target_len);
+                simd::diagonal_and_up(
+                    above,
+                    substitution,
+                    (self.gap_penalty, self.gap_extend_penalty),
+                    local,
+                    first.max(1)..inner_end.max(1),
+                    row,
+                    codes,
+                );
+                if last == target_len && target_len > 0 {
+                    simd::diagonal_and_up(
+                        above,
+                        substitution,
+                        self.up_gap_penalties(target_len, target_len),
+                        local,
+                        target_len..target_len + 1,
+                        row,
+                        codes,
+                    );
+                }
+            }
+            None => {
+                for i in columns.clone() {
+                    // A global alignment starts in the top-left corner
+                    row.diagonal[i] = if i == 0 && !local { 0 } else { NEG_INF };
+                    row.up[i] = NEG_INF;
+                    codes[i] = 0;
+                }
+            }
+        }
+        // Cells without a gap in the query
+        let mut no_left = |i: usize| {
+            row.left[i] = NEG_INF;
+            let code = codes[i] as u8;
+            trace[i] = Trace::new(
+                Dirs::from_bits(code),
+                Dirs::from_bits(code >> 3),
+                Dirs::default(),
+            );
+        };
+        if local && above.is_none() {
+            columns.for_each(no_left);
+            return;
+        }
+        if first == 0 {
+            no_left(0);
+        }
+        // Extend a gap in the query, or open a new one
+        // Gaps in the query in the top and the bottom row are end gaps
+        simd::left(
+            self.left_gap_penalties(j, pair.query.len()),
+            first.max(1)..last + 1,
+            row,
+            codes,
+            trace,
+        );
+    }
+
+    /// Computes the `rows` of the Gotoh table, starting from the row `above` them.
+    ///
+    /// Stores the traceback of the rows into `traces` and counts the optimal paths
+    /// into `counts`, if given.
+    /// Returns the last row and the best cell to end a local alignment in, with its score.
+    fn fill_rows<'rows>(
+        &self,
+        pair: &Pair,
+        rows: &'rows mut RowBuffers,
+        above: Option<&ScoreRow>,
+        row_range: RangeInclusive<usize>,
+        mut traces: Option<&mut Table<Trace>>,
+        mut counts: Option<&mut PathCounts>,
+    ) -> (&'rows ScoreRow, ([usize; 2], i32)) {
+        rows.resize(pair.target.len() + 1);
+        if let Some(above) = above {
+            rows.above.clone_from(above);
+        }
+        let mut has_above = above.is_some();
+        let mut local_end = ([0, 0], 0);
+        for (k, j) in row_range.enumerate() {
+            let RowBuffers {
+                above,
+                row,
+                codes,
+                scratch,
+            } = &mut *rows;
+            let trace = match traces.as_deref_mut() {
+                Some(traces) => traces.row_mut(k),
+                None => scratch,
+            };
+            let above_row = if has_above { Some(&*above) } else { None };
+            self.fill_row(pair, j, above_row, row, codes, trace);
+            if let Some(counts) = counts.as_deref_mut() {
+                counts.add_row(pair, j, row, trace, self.mode == AlignmentMode::Local);
+            }
+            if self.mode == AlignmentMode::Local {
+                for i in pair.band.columns(j, pair.target.len()) {
+                    if row.diagonal[i] > local_end.1 {
+                        local_end = ([i, j], row.diagonal[i]);
+                    }
+                }
+            }
+            mem::swap(above, row);
+            has_above = true;
+        }
+        assert!(has_above, "no rows to fill");
+        (&rows.above, local_end)
+    }
+
+    /// Follows the optimal path back from the matrix `dir` of the cell `[i, j]`
+    /// to the row `above`, or to its start if there is none, pushing its steps into `path`.
+    ///
+    /// The rows up to `j` are computed again and split in halves, the lower half first,
+    /// until the traceback of the rows fits into `traces`.
+    fn trace_rows(
+        &self,
+        pair: &Pair,
+        rows: &mut RowBuffers,
+        above: Option<(usize, &ScoreRow)>,
+        end: ([usize; 2], Dir),
+        traces: &mut Table<Trace>,
+        path: &mut Vec<Dir>,
+    ) -> Traceback {
+        let first_row = above.map_or(0, |(j, _)| j + 1);
+        let last_row = end.0[1];
+        let above_row = above.map(|(_, row)| row);
+        if last_row + 1 - first_row <= traces.height {
+            self.fill_rows(
+                pair,
+                rows,
+                above_row,
+                first_row..=last_row,
+                Some(traces),
+                None,
+            );
+            return trace_block(pair, self.tie_policy, traces, first_row, end, path);
+        }
+        let middle = (first_row + last_row) / 2;
+        let (middle_row, _) = self.fill_rows(pair, rows, above_row, first_row..=middle, None, None);
+        let middle_row = middle_row.clone();
+        match self.trace_rows(pair, rows, Some((middle, &middle_row)), end, traces, path) {
+            Traceback::Above(cell, dir) => {
+                drop(middle_row);
+                self.trace_rows(pair, rows, above, (cell, dir), traces, path)
+            }
+            start => start,
+        }
+    }
+}
+
+/// Diagonals `i - j` of the Gotoh table computed by an alignment.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Band {
+    lowest: isize,
+    highest: isize,
+}
+
+impl Band {
+    /// All the diagonals of the table for sequences of lengths `target_len` and `query_len`.
+    fn full(target_len: usize, query_len: usize) -> Self {
+        Band {
+            lowest: -(query_len as isize),
+            highest: target_len as isize,
+        }
+    }
+
+    /// The diagonals within `width` of the main diagonal and of the diagonal
+    /// of the bottom-right corner, so that both corners are inside the band.
+    fn new(width: usize, target_len: usize, query_len: usize) -> Self {
+        let full = Band::full(target_len, query_len);
+        let width = width.min(target_len + query_len) as isize;
+        let corner = target_len as isize - query_len as isize;
+        Band {
+            lowest: (corner.min(0) - width).max(full.lowest),
+            highest: (corner.max(0) + width).min(full.highest),
+        }
+    }
+
+    /// Returns the columns of the row `j` inside the band.
+    fn columns(&self, j: usize, target_len: usize) -> RangeInclusive<usize> {
+        let j = j as isize;
+        (j + self.lowest).max(0) as usize..=(j + self.highest).min(target_len as isize) as usize
+    }
+}
+
+/// Sequences being aligned, with the part of the table to compute.
+struct Pair<'target, 'query, 'profile> {
+    target: &'target [u8],
+    query: &'query [u8],
+    band: Band,
+    profile: &'profile Profile,
+}
+
+/// Buffers reused by the alignments of many pairs of sequences.
+///
+/// Aligning with [Aligner::align_with] allocates the table and its rows only
+/// when a pair needs more space than the previous ones.
+#[derive(Default)]
+pub struct AlignerWorkspace {
+    traces: Table<Trace>,
+    rows: RowBuffers,
+    profile: Profile,
+}
+
+/// Rows of the table being computed.
+#[derive(Default)]
+struct RowBuffers {
+    /// The last computed row.
+    above: ScoreRow,
+    /// The row being computed.
+    row: ScoreRow,
+    /// Previous matrices of the diagonal and the up matrices of `row`.
+    codes: Vec<i32>,
+    /// Traceback of `row`, when it isn't kept.
+    scratch: Vec<Trace>,
+}
+
+impl RowBuffers {
+    /// Makes the rows at least `width` cells long.
+    fn resize(&mut self, width: usize) {
+        self.above.resize(width);
+        self.row.resize(width);
+        self.codes.resize(width, 0);
+        self.scratch.resize(width, Trace::default());
+    }
+}
+
+/// Substitution scores of the symbols of the query against each symbol of the target.
+#[derive(Default)]
+struct Profile {
+    /// Rows indexed by the symbol of the query, with the score of `target[i - 1]` at `i`.
+    rows: Vec<Vec<i32>>,
+}
+
+impl Profile {
+    /// Scores the symbols of `query` against `target` with the `aligner`.
+    fn fill(&mut self, aligner: &Aligner, target: &[u8], query: &[u8]) {
+        self.rows.resize_with(256, Vec::new);
+        self.rows.iter_mut().for_each(Vec::clear);
+        for &query_c in query {
+            let row = &mut self.rows[query_c as usize];
+            if row.is_empty() {
+                row.push(0);
+                row.extend(
+                    target
+                        .iter()
+                        .map(|&target_c| aligner.substitution_score(target_c, query_c)),
+                );
+            }
+        }
+    }
+
+    /// Returns the scores of `query_c` against the target.
+    fn row(&self, query_c: u8) -> &[i32] {
+        &self.rows[query_c as usize]
+    }
+}
+
+impl Pair<'_, '_, '_> {
+    /// Checks whether the path going back from the `cell` by `steps`
+    /// touches an edge of the band which isn't an edge of the table.
+    fn touches_band(&self, mut cell: [usize; 2], steps: &[Dir]) -> bool {
+        let full = Band::full(self.target.len(), self.query.len());
+        let on_edge = |[i, j]: [usize; 2]| {
+            let diagonal = i as isize - j as isize;
+            (diagonal == self.band.lowest && self.band.lowest > full.lowest)
+                || (diagonal == self.band.highest && self.band.highest < full.highest)
+        };
+        let mut touches = on_edge(cell);
+        for &step in steps {
+            cell = step.step_back(cell);
+            touches |= on_edge(cell);
+        }
+        touches
+    }
+
+    /// Checks whether the symbols of the cell `[i, j]` differ.
+    fn mismatch(&self, [i, j]: [usize; 2]) -> bool {
+        i > 0 && j > 0 && self.target[i - 1] != self.query[j - 1]
+    }
+}
+
+/// Where a traceback through some rows of the Gotoh table stopped.
+enum Traceback {
+    /// The path continues from the matrix of a cell in the row above.
+    Above([usize; 2], Dir),
+    /// The path starts in the cell.
+    Start([usize; 2]),
+}
+
+/// Follows the optimal path back through the `traces` of the rows starting at `first_row`,
+/// from the matrix `dir` of the cell `[i, j]`, pushing its steps into `path`.
+///
+/// Ties are broken by the `policy`.
+fn trace_block(
+    pair: &Pair,
+    policy: TiePolicy,
+    traces: &Table<Trace>,
+    first_row: usize,
+    ([mut i, mut j], dir): ([usize; 2], Dir),
+    path: &mut Vec<Dir>,
+) -> Traceback {
+    let mut dir = Some(dir);
+    while let Some(current) = dir {
+        if i == 0 && j == 0 {
+            break;
+        }
+        if j < first_row {
+            return Traceback::Above([i, j], current);
+        }
+        path.push(current);
+        let previous = traces[[i, j - first_row]].get(current);
+        [i, j] = current.step_back([i, j]);
+        dir = policy.choose(previous, pair.mismatch([i, j]));
+    }
+    Traceback::Start([i, j])
+}
+
+/// Numbers of optimal paths ending in the matrices of the cells of two rows.
+#[derive(Default)]
+struct PathCounts {
+    /// Counts of the last computed row, for the diagonal, up and left matrices.
+    above: Vec<[u64; 3]>,
+    /// Counts of the row being computed.
+    row: Vec<[u64; 3]>,
+    /// Best score of a local alignment so far.
+    local_score: i32,
+    /// Cells ending the local alignments with the best score.
+    local_ends: Vec<[usize; 2]>,
+    /// Number of the local alignments with the best score.
+    local_total: u64,
+}
+
+impl PathCounts {
+    /// Counts the paths ending in the row `j` with the traceback `trace` and the scores `row`.
+    fn add_row(&mut self, pair: &Pair, j: usize, row: &ScoreRow, trace: &[Trace], local: bool) {
+        let target_len = pair.target.len();
+        self.above.resize(target_len + 1, [0; 3]);
+        self.row.resize(target_len + 1, [0; 3]);
+        for i in pair.band.columns(j, target_len) {
+            let trace = trace[i];
+            let diagonal = paths(trace.get(Dir::Diagonal), || self.above[i - 1]);
+            let up = paths(trace.get(Dir::Up), || self.above[i]);
+            let left = paths(trace.get(Dir::Left), || self.row[i - 1]);
+            self.row[i] = [diagonal, up, left];
+            let score = row.diagonal[i];
+            if local && score > 0 && score >= self.local_score {
+                if score > self.local_score {
+                    self.local_score = score;
+                    self.local_ends.clear();
+                    self.local_total = 0;
+                }
+                self.local_ends.push([i, j]);
+                self.local_total = self.local_total.saturating_add(diagonal);
+            }
         }
+        mem::swap(&mut self.above, &mut self.row);
     }
 }
 
+/// Returns the number of paths through the matrices `dirs` of the previous cell,
+/// whose counts are given by `previous`, or 1 if the path starts in the cell.
+fn paths(dirs: Dirs, previous: impl FnOnce() -> [u64; 3]) -> u64 {
+    if dirs.is_empty() {
+        return 1;
+    }
+    [Dir::Diagonal, Dir::Up, Dir::Left]
+        .iter()
+        .zip(previous().iter())
+        .filter(|(&dir, _)| dirs.contains(dir))
+        .fold(0, |total, (_, &count)| total.saturating_add(count))
+}
+
 impl Default for Aligner {
     fn default() -> Self {
         Aligner {
@@ -112,6 +996,13 @@ impl Default for Aligner {
             gap_extend_penalty: -10,
             end_gap_penalty: -2,
             end_gap_extend_penalty: -1,
+            substitution_matrix: None,
+            mode: AlignmentMode::Global,
+            linear_memory_threshold: 1 << 24,
+            band_width: None,
+            widen_band: true,
+            tie_policy: TiePolicy::LeftmostGaps,
+            detect_orientation: false,
         }
     }
 }
@@ -120,18 +1011,28 @@ impl Default for Aligner {
 pub struct Alignment<'target, 'query> {
     target: &'target [u8],
     query: &'query [u8],
-    table: Table<Score>,
+    /// Steps of the optimal path, starting from the bottom-right corner.
+    path: Vec<Dir>,
+    /// Score of the optimal path.
+    score: i32,
+    /// Mode of the alignment, telling which end gaps leave the sequences unaligned.
+    mode: AlignmentMode,
 }
 
 impl<'target, 'query> Alignment<'target, 'query> {
+    /// Score of the optimal alignment.
+    pub fn score(&self) -> i32 {
+        self.score
+    }
+
     /// Iterate backwards over the path in the Needleman-Wunsch table.
     pub fn iter<'alignment>(&'alignment self) -> AlignmentIter<'target, 'query, 'alignment> {
         AlignmentIter {
-            current_row: self.table.height - 1,
-            current_column: self.table.width - 1,
+            current_row: self.query.len(),
+            current_column: self.target.len(),
             target: self.target,
             query: self.query,
-            table: &self.table,
+            path: self.path.iter(),
         }
     }
 
@@ -141,10 +1042,80 @@ impl<'target, 'query> Alignment<'target, 'query> {
     ) -> AlignmentCommonIter<'target, 'query, 'alignment> {
         AlignmentCommonIter {
             back_gap: true,
+            front_gap: self.front_gap_len(),
             inner: self.iter(),
         }
     }
 
+    /// Number of steps of the path before the first aligned pair.
+    fn front_gap_len(&self) -> usize {
+        self.path
+            .iter()
+            .rev()
+            .position(|&dir| dir == Dir::Diagonal)
+            .unwrap_or(self.path.len())
+    }
+
+    /// Returns the cells `[column, row]` where the common part of the path starts and ends.
+    ///
+    /// The common part is the one traversed by [Alignment::common_path_iter].
+    /// Returns [None] if no symbols of `target` and `query` are aligned to each other.
+    pub fn common_region(&self) -> Option<([usize; 2], [usize; 2])> {
+        let mut iter = self.iter();
+        // Skip the back gap
+        while iter.current_dir()? != Dir::Diagonal {
+            iter.next();
+        }
+        let end = [iter.current_column, iter.current_row];
+        // Skip to the front gap
+        let front_gap = self.front_gap_len();
+        while iter.path.len() > front_gap {
+            iter.next();
+        }
+        Some(([iter.current_column, iter.current_row], end))
+    }
+
+    /// Returns the number of gap openings and gap extensions, including the end gaps.
+    pub fn gap_counts(&self) -> (usize, usize) {
+        let mut opens = 0;
+        let mut extensions = 0;
+        let mut previous = Dir::Diagonal;
+        for &dir in self.path.iter() {
+            match dir {
+                Dir::Diagonal => {}
+                _ if dir == previous => extensions += 1,
+                _ => opens += 1,
+            }
+            previous = dir;
+        }
+        (opens, extensions)
+    }
+
+    /// Fraction of identical pairs in the common part of the alignment.
+    ///
+    /// Returns NaN if the common part is empty.
+    pub fn identity(&self) -> f64 {
+        let (identical, length) =
+            self.common_path_iter()
+                .fold((0, 0), |(identical, length), (target_c, query_c)| {
+                    (identical + (target_c == query_c) as usize, length + 1)
+                });
+        identical as f64 / length as f64
+    }
+
+    /// Fraction of the shorter sequence covered by the common part of the alignment.
+    pub fn coverage(&self) -> f64 {
+        let ([target_start, query_start], [target_end, query_end]) = match self.common_region() {
+            Some(region) => region,
+            None => return 0.0,
+        };
+        if self.target.len() <= self.query.len() {
+            (target_end - target_start) as f64 / self.target.len() as f64
+        } else {
+            (query_end - query_start) as f64 / self.query.len() as f64
+        }
+    }
+
     /// Returns two strings representing aligned target and query respectively.
     /// # Errors
     /// Returns [Err] if invalid UTF-8 has been constructed
@@ -207,6 +1178,133 @@ impl<'target, 'query> Alignment<'target, 'query> {
             + "\n"
             + &String::from_utf8(query_align)?)
     }
+
+    /// Returns the runs of steps of the path of the same [EditKind], from the start of the sequences.
+    ///
+    /// Only the aligned region is covered: the end gaps of a global alignment
+    /// are insertions and deletions like the internal gaps, but the ends left unaligned
+    /// by the free end gaps of the other modes are not edits.
+    pub fn edits(&self) -> Vec<Edit> {
+        self.clipped_edits().1
+    }
+
+    /// Returns the numbers of the unaligned symbols at the start and the end of the query,
+    /// and the [Alignment::edits] between them.
+    fn clipped_edits(&self) -> (usize, Vec<Edit>, usize) {
+        let (target_unaligned, query_unaligned) = self.mode.unaligned_ends();
+        let front = self.front_gap_len();
+        // The whole path is in front if no symbols are aligned
+        let back = self
+            .path
+            .iter()
+            .position(|&dir| dir == Dir::Diagonal)
+            .unwrap_or(0);
+        let (mut front_clip, mut back_clip) = (0, 0);
+        let mut edits: Vec<Edit> = Vec::new();
+        // Whether the last step was left out, so that the next one starts a new run
+        let mut skipped = false;
+        let (mut i, mut j) = (0, 0);
+        for (k, &dir) in self.path.iter().rev().enumerate() {
+            let in_front = k < front;
+            let unaligned = (in_front || k >= self.path.len() - back)
+                && match dir {
+                    Dir::Diagonal => false,
+                    Dir::Up => query_unaligned,
+                    Dir::Left => target_unaligned,
+                };
+            let kind = match dir {
+                Dir::Diagonal if self.target[i] == self.query[j] => EditKind::Match,
+                Dir::Diagonal => EditKind::Mismatch,
+                Dir::Up => EditKind::Insertion,
+                Dir::Left => EditKind::Deletion,
+            };
+            match edits.last_mut() {
+                _ if unaligned && dir == Dir::Up && in_front => front_clip += 1,
+                _ if unaligned && dir == Dir::Up => back_clip += 1,
+                _ if unaligned => {}
+                Some(edit) if edit.kind == kind && !skipped => edit.len += 1,
+                _ => edits.push(Edit {
+                    kind,
+                    target_start: i,
+                    query_start: j,
+                    len: 1,
+                }),
+            }
+            skipped = unaligned;
+            match dir {
+                Dir::Diagonal => {
+                    i += 1;
+                    j += 1;
+                }
+                Dir::Up => j += 1,
+                Dir::Left => i += 1,
+            }
+        }
+        (front_clip, edits, back_clip)
+    }
+
+    /// Returns the CIGAR string of the alignment, with the target as the reference.
+    ///
+    /// Uses the operations `=`, `X`, `I` and `D` of [EditKind] for the [Alignment::edits],
+    /// and `S` for the unaligned ends of the query, soft clipped.
+    /// The unaligned ends of the target are left out, the alignment starts
+    /// at the start of the common region in the target.
+    pub fn cigar(&self) -> String {
+        let (front_clip, edits, back_clip) = self.clipped_edits();
+        let clip = |len: usize| {
+            if len > 0 {
+                format!("{}S", len)
+            } else {
+                String::new()
+            }
+        };
+        clip(front_clip)
+            + &edits
+                .iter()
+                .map(|edit| format!("{}{}", edit.len, edit.kind.cigar_op()))
+                .collect::<String>()
+            + &clip(back_clip)
+    }
+}
+
+/// Kind of a step of the path of an alignment, named like the operations of CIGAR strings.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EditKind {
+    /// Identical symbols aligned to each other.
+    Match,
+    /// Different symbols aligned to each other.
+    Mismatch,
+    /// A symbol of the query aligned to a gap.
+    Insertion,
+    /// A symbol of the target aligned to a gap.
+    Deletion,
+}
+
+impl EditKind {
+    /// Returns the CIGAR operation of the kind.
+    pub fn cigar_op(self) -> char {
+        match self {
+            EditKind::Match => '=',
+            EditKind::Mismatch => 'X',
+            EditKind::Insertion => 'I',
+            EditKind::Deletion => 'D',
+        }
+    }
+}
+
+/// Run of steps of the path of an alignment of the same kind.
+///
+/// Positions are 0-based, a gap is at the position of the next symbol of its sequence.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Edit {
+    /// Kind of the steps of the run.
+    pub kind: EditKind,
+    /// Position of the run in the target.
+    pub target_start: usize,
+    /// Position of the run in the query.
+    pub query_start: usize,
+    /// Number of steps of the run.
+    pub len: usize,
 }
 
 /// Iterator over the alignment path
@@ -215,13 +1313,14 @@ pub struct AlignmentIter<'target, 'query, 'alignment> {
     current_column: usize,
     target: &'target [u8],
     query: &'query [u8],
-    table: &'alignment Table<Score>,
+    path: core::slice::Iter<'alignment, Dir>,
 }
 
 impl<'target, 'query, 'alignment> AlignmentIter<'target, 'query, 'alignment> {
-    // Returns the current cell of the alignment table
-    fn current_cell(&self) -> &Score {
-        &self.table[[self.current_column, self.current_row]]
+    // Returns the direction of the current step of the path,
+    // or [None] if the path is finished
+    fn current_dir(&self) -> Option<Dir> {
+        self.path.as_slice().first().copied()
     }
 
     // Returns the current char of target sequence
@@ -243,17 +1342,18 @@ impl<'target, 'query, 'alignment> AlignmentIter<'target, 'query, 'alignment> {
     // Moves to the previous cell of the alignment sequence
     // according to the current direction
     fn shift_back(&mut self) {
-        match self.current_cell().dir {
-            Dir::Diagonal => {
+        match self.path.next() {
+            Some(Dir::Diagonal) => {
                 self.current_row -= 1;
                 self.current_column -= 1;
             }
-            Dir::Up => {
+            Some(Dir::Up) => {
                 self.current_row -= 1;
             }
-            Dir::Left => {
+            Some(Dir::Left) => {
                 self.current_column -= 1;
             }
+            None => {}
         }
     }
 }
@@ -262,12 +1362,9 @@ impl<'target, 'query, 'alignment> Iterator for AlignmentIter<'target, 'query, 'a
     type Item = (u8, u8);
     // Yields the previous pair of aligned characters
     fn next(&mut self) -> Option<(u8, u8)> {
-        // If current cell is the top-left cell,
-        // the iteration is finished
-        if self.current_row == 0 && self.current_column == 0 {
-            return None;
-        }
-        let (target_c, query_c) = match self.current_cell().dir {
+        // If the path is exhausted, we are in the top-left cell
+        // and the iteration is finished
+        let (target_c, query_c) = match self.current_dir()? {
             Dir::Diagonal => (self.current_target(), self.current_query()),
             Dir::Up => (b'-', self.current_query()),
             Dir::Left => (self.current_target(), b'-'),
@@ -277,20 +1374,22 @@ impl<'target, 'query, 'alignment> Iterator for AlignmentIter<'target, 'query, 'a
     }
 }
 
-/// Iterates over the alignment path, skipping
+/// Iterates over the alignment path, skipping the end gaps
 pub struct AlignmentCommonIter<'target, 'query, 'alignment> {
     back_gap: bool,
+    /// Number of steps before the first aligned pair.
+    front_gap: usize,
     inner: AlignmentIter<'target, 'query, 'alignment>,
 }
 
 impl<'target, 'query, 'alignment> AlignmentCommonIter<'target, 'query, 'alignment> {
     /// Returns the next pair of symbols after the back gap
-    fn next_after_back_gap(&mut self) -> Option<<AlignmentIter as Iterator>::Item> {
+    fn next_after_back_gap(&mut self) -> Option<<Self as Iterator>::Item> {
         if !self.back_gap {
             return self.inner.next();
         }
         loop {
-            if let Dir::Diagonal = self.inner.current_cell().dir {
+            if let Dir::Diagonal = self.inner.current_dir()? {
                 self.back_gap = false;
                 return self.inner.next();
             }
@@ -302,7 +1401,7 @@ impl<'target, 'query, 'alignment> AlignmentCommonIter<'target, 'query, 'alignmen
 impl<'target, 'query, 'alignment> Iterator for AlignmentCommonIter<'target, 'query, 'alignment> {
     type Item = <AlignmentIter<'target, 'query, 'alignment> as Iterator>::Item;
     fn next(&mut self) -> Option<Self::Item> {
-        if self.inner.current_row == 0 || self.inner.current_column == 0 {
+        if self.inner.path.len() <= self.front_gap {
             None
         } else {
             self.next_after_back_gap()
@@ -322,6 +1421,13 @@ mod test_super {
             gap_extend_penalty: -5,
             end_gap_penalty: -2,
             end_gap_extend_penalty: -1,
+            substitution_matrix: None,
+            mode: AlignmentMode::Global,
+            linear_memory_threshold: 1 << 24,
+            band_width: None,
+            widen_band: true,
+            tie_policy: TiePolicy::LeftmostGaps,
+            detect_orientation: false,
         }
     }
 
@@ -342,6 +1448,7 @@ mod test_super {
         for (target, query, target_align_test, query_align_test) in aligned {
             let (target_align, query_align) = aligner
                 .align(target.as_bytes(), query.as_bytes())
+                .unwrap()
                 .as_strings()?;
             assert_eq!(target_align, target_align_test);
             assert_eq!(query_align, query_align_test);
@@ -349,11 +1456,557 @@ mod test_super {
         Ok(())
     }
 
+    // Scores every alignment of `target[i..]` and `query[j..]` by brute force
+    // and returns the best score, given the direction of the last step.
+    fn brute_force_score(
+        aligner: &Aligner,
+        target: &[u8],
+        query: &[u8],
+        [i, j]: [usize; 2],
+        last: Option<Dir>,
+    ) -> i32 {
+        let (n, m) = (target.len(), query.len());
+        let mut best = None;
+        let mut consider = |score: i32| best = Some(best.map_or(score, |b: i32| b.max(score)));
+        if i < n && j < m {
+            let substitution = aligner.substitution_score(target[i], query[j]);
+            consider(
+                substitution
+                    + brute_force_score(
+                        aligner,
+                        target,
+                        query,
+                        [i + 1, j + 1],
+                        Some(Dir::Diagonal),
+                    ),
+            );
+        }
+        if i < n {
+            let (open, extend) = aligner.left_gap_penalties(j, m);
+            let penalty = if last == Some(Dir::Left) {
+                extend
+            } else {
+                open
+            };
+            consider(
+                penalty + brute_force_score(aligner, target, query, [i + 1, j], Some(Dir::Left)),
+            );
+        }
+        if j < m {
+            let (open, extend) = aligner.up_gap_penalties(i, n);
+            let penalty = if last == Some(Dir::Up) { extend } else { open };
+            consider(
+                penalty + brute_force_score(aligner, target, query, [i, j + 1], Some(Dir::Up)),
+            );
+        }
+        best.unwrap_or(0)
+    }
+
+    // Scores every pair of subsequences by brute force
+    // and returns the best score of a local alignment.
+    fn brute_force_local_score(aligner: &Aligner, target: &[u8], query: &[u8]) -> i32 {
+        let mut best = 0;
+        for target_start in 0..target.len() {
+            for target_end in target_start + 1..=target.len() {
+                for query_start in 0..query.len() {
+                    for query_end in query_start + 1..=query.len() {
+                        best = best.max(brute_force_score(
+                            aligner,
+                            &target[target_start..target_end],
+                            &query[query_start..query_end],
+                            [0, 0],
+                            None,
+                        ));
+                    }
+                }
+            }
+        }
+        best
+    }
+
+    // Computes the score of `alignment` from its path.
+    //
+    // In the local mode, only the common part of the path is scored.
+    fn path_score(aligner: &Aligner, alignment: &Alignment) -> i32 {
+        let (mut i, mut j) = (0, 0);
+        let mut last = None;
+        let mut score = 0;
+        let scored = if aligner.mode == AlignmentMode::Local {
+            let back_gap = alignment
+                .path
+                .iter()
+                .position(|&dir| dir == Dir::Diagonal)
+                .unwrap_or(0);
+            alignment.front_gap_len()..alignment.path.len() - back_gap
+        } else {
+            0..alignment.path.len()
+        };
+        for (step, &dir) in alignment.path.iter().rev().enumerate() {
+            score += match dir {
+                _ if !scored.contains(&step) => 0,
+                Dir::Diagonal => {
+                    aligner.substitution_score(alignment.target[i], alignment.query[j])
+                }
+                Dir::Left => {
+                    let (open, extend) = aligner.left_gap_penalties(j, alignment.query.len());
+                    if last == Some(Dir::Left) {
+                        extend
+                    } else {
+                        open
+                    }
+                }
+                Dir::Up => {
+                    let (open, extend) = aligner.up_gap_penalties(i, alignment.target.len());
+                    if last == Some(Dir::Up) {
+                        extend
+                    } else {
+                        open
+                    }
+                }
+            };
+            match dir {
+                Dir::Diagonal => {
+                    i += 1;
+                    j += 1;
+                }
+                Dir::Left => i += 1,
+                Dir::Up => j += 1,
+            }
+            last = Some(dir);
+        }
+        score
+    }
+
+    #[test]
+    fn test_affine_gap_scores() -> Result<(), FromUtf8Error> {
+        // Optimal scores computed by exhaustive search.
+        // A two-column gap costs -5 - 1, not -5 - 5.
+        let aligner = Aligner {
+            match_score: 2,
+            mismatch_score: -3,
+            gap_penalty: -5,
+            gap_extend_penalty: -1,
+            end_gap_penalty: -5,
+            end_gap_extend_penalty: -1,
+            substitution_matrix: None,
+            mode: AlignmentMode::Global,
+            linear_memory_threshold: 1 << 24,
+            band_width: None,
+            widen_band: true,
+            tie_policy: TiePolicy::LeftmostGaps,
+            detect_orientation: false,
+        };
+        let cases = vec![
+            ("AGCCGTGC", "AGCTGC", 6, "AGCCGTGC", "AGC--TGC"),
+            ("AGCTTGGC", "AGCTC", 3, "AGCTTGGC", "AGCT---C"),
+        ];
+        for (target, query, score, target_align_test, query_align_test) in cases {
+            let alignment = aligner.align(target.as_bytes(), query.as_bytes()).unwrap();
+            assert_eq!(alignment.score(), score);
+            let (target_align, query_align) = alignment.as_strings()?;
+            assert_eq!(target_align, target_align_test);
+            assert_eq!(query_align, query_align_test);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_long_sequence_score() {
+        // The score is below `i16::MIN`
+        let aligner = Aligner {
+            end_gap_extend_penalty: -10,
+            ..Aligner::default()
+        };
+        let target = b"ACGT".repeat(1000);
+        let query = b"ACGT".repeat(5);
+        let alignment = aligner.align(&target, &query).unwrap();
+        // Two end gaps are cheaper than one
+        assert_eq!(alignment.score(), 20 - 2 * 2 - 3978 * 10);
+        assert_eq!(path_score(&aligner, &alignment), alignment.score());
+    }
+
+    #[test]
+    fn test_score_overflow() {
+        let aligner = Aligner {
+            gap_penalty: -50_000_000,
+            ..Aligner::default()
+        };
+        assert_eq!(aligner.check_scores(), Ok(()));
+        assert!(aligner.align(b"ACGT", b"ACGT").is_ok());
+        assert_eq!(
+            aligner.align(&[b'A'; 10], b"ACGT").err(),
+            Some(ScoreOverflow)
+        );
+        let aligner = Aligner {
+            match_score: i32::MAX,
+            ..Aligner::default()
+        };
+        assert_eq!(aligner.check_scores(), Err(ScoreOverflow));
+    }
+
+    #[test]
+    fn test_substitution_matrix() -> Result<(), FromUtf8Error> {
+        let aligner = Aligner {
+            substitution_matrix: SubstitutionMatrix::named("NUC.4.4"),
+            ..test_aligner()
+        };
+        // `R` is a partial match for `A` and `G`
+        let alignment = aligner.align(b"ACGTRAC", b"ACGTGAC").unwrap();
+        assert_eq!(alignment.score(), 6 * 5 + 1);
+        // Symbols outside of the matrix are scored with `match_score` and `mismatch_score`
+        let alignment = aligner.align(b"AC*", b"AC*").unwrap();
+        assert_eq!(alignment.score(), 2 * 5 + 1);
+
+        let aligner = Aligner {
+            substitution_matrix: SubstitutionMatrix::named("BLOSUM62"),
+            ..test_aligner()
+        };
+        let alignment = aligner.align(b"MKVLAAGIV", b"MKILAGIV").unwrap();
+        let (target_align, query_align) = alignment.as_strings()?;
+        assert_eq!(target_align, "MKVLAAGIV");
+        assert_eq!(query_align, "MKIL-AGIV");
+        assert_eq!(path_score(&aligner, &alignment), alignment.score());
+        Ok(())
+    }
+
+    #[test]
+    fn test_alignment_modes() -> Result<(), FromUtf8Error> {
+        let cases = vec![
+            (
+                AlignmentMode::Local,
+                "ttttacgtacgtgggg",
+                "ccacgtacgtcc",
+                8,
+                "tttt--acgtacgtgggg--",
+                "----ccacgtacgt----cc",
+            ),
+            (
+                AlignmentMode::Glocal,
+                "ttttacgtacgtgggg",
+                "acgaacgt",
+                6,
+                "ttttacgtacgtgggg",
+                "----acgaacgt----",
+            ),
+            (
+                AlignmentMode::Overlap,
+                "ttttacgtac",
+                "acgtacgggg",
+                6,
+                "ttttacgtac----",
+                "----acgtacgggg",
+            ),
+        ];
+        for (mode, target, query, score, target_align_test, query_align_test) in cases {
+            let aligner = Aligner {
+                mode,
+                ..test_aligner()
+            };
+            let alignment = aligner.align(target.as_bytes(), query.as_bytes()).unwrap();
+            assert_eq!(alignment.score(), score);
+            let (target_align, query_align) = alignment.as_strings()?;
+            assert_eq!(target_align, target_align_test);
+            assert_eq!(query_align, query_align_test);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_local_common_path() {
+        let aligner = Aligner {
+            mode: AlignmentMode::Local,
+            ..test_aligner()
+        };
+        let alignment = aligner.align(b"ttttacgtgggg", b"ccacgtcc").unwrap();
+        assert_eq!(alignment.common_region(), Some(([4, 2], [8, 6])));
+        assert_eq!(
+            alignment.common_path_iter().collect::<Vec<_>>(),
+            vec![(b't', b't'), (b'g', b'g'), (b'c', b'c'), (b'a', b'a')]
+        );
+        // Nothing to align
+        let alignment = aligner.align(b"aaaa", b"cc").unwrap();
+        assert_eq!(alignment.score(), 0);
+        assert_eq!(alignment.common_region(), None);
+        assert_eq!(
+            alignment.as_strings().unwrap(),
+            ("aaaa--".to_owned(), "----cc".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_optimal_score() {
+        // Deterministic pseudo-random sequences
+        let mut state: u32 = 12345;
+        let mut random_seq = |len: usize| -> Vec<u8> {
+            (0..len)
+                .map(|_| {
+                    state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    b"ACGT"[(state >> 16) as usize % 4]
+                })
+                .collect()
+        };
+        let aligners = [
+            test_aligner(),
+            Aligner::default(),
+            Aligner {
+                substitution_matrix: Some(SubstitutionMatrix::transition_transversion(3, -1, -2)),
+                ..test_aligner()
+            },
+            Aligner {
+                match_score: 2,
+                mismatch_score: -3,
+                gap_penalty: -5,
+                gap_extend_penalty: -1,
+                end_gap_penalty: 0,
+                end_gap_extend_penalty: 0,
+                substitution_matrix: None,
+                mode: AlignmentMode::Global,
+                linear_memory_threshold: 1 << 24,
+                band_width: None,
+                widen_band: true,
+                tie_policy: TiePolicy::LeftmostGaps,
+                detect_orientation: false,
+            },
+        ];
+        for aligner in aligners.iter() {
+            for (target_len, query_len) in [(0, 3), (4, 0), (5, 5), (6, 3), (3, 6), (7, 5)].iter() {
+                for _ in 0..10 {
+                    let target = random_seq(*target_len);
+                    let query = random_seq(*query_len);
+                    for &mode in [
+                        AlignmentMode::Global,
+                        AlignmentMode::Glocal,
+                        AlignmentMode::Overlap,
+                    ]
+                    .iter()
+                    {
+                        let aligner = Aligner {
+                            mode,
+                            ..aligner.clone()
+                        };
+                        let alignment = aligner.align(&target, &query).unwrap();
+                        assert_eq!(
+                            alignment.score(),
+                            brute_force_score(&aligner, &target, &query, [0, 0], None)
+                        );
+                        assert_eq!(path_score(&aligner, &alignment), alignment.score());
+                    }
+                    let aligner = Aligner {
+                        mode: AlignmentMode::Local,
+                        ..aligner.clone()
+                    };
+                    let alignment = aligner.align(&target, &query).unwrap();
+                    assert_eq!(
+                        alignment.score(),
+                        brute_force_local_score(&aligner, &target, &query)
+                    );
+                    assert_eq!(path_score(&aligner, &alignment), alignment.score());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_banded_alignment() {
+        // A deletion early and an insertion late move the path off the main diagonal
+        let target = b"AACCGGTTACGTACGTTTGGCCAA";
+        let query = b"AACCGGTACGTACGTTTGGCCAAA";
+        let full = test_aligner().align(target, query).unwrap();
+        let narrow = Aligner {
+            band_width: Some(0),
+            widen_band: false,
+            ..test_aligner()
+        };
+        let alignment = narrow.align(target, query).unwrap();
+        assert!(alignment.score() < full.score());
+        assert!(alignment.path.iter().all(|&dir| dir == Dir::Diagonal));
+        let widened = Aligner {
+            widen_band: true,
+            ..narrow
+        }
+        .align(target, query)
+        .unwrap();
+        assert_eq!(widened.score(), full.score());
+        assert_eq!(widened.path, full.path);
+    }
+
+    #[test]
+    fn test_workspace() {
+        let pairs: [(&[u8], &[u8]); 5] = [
+            (b"ACGTACGTTTGGCCAA", b"ACGTTTGGCAA"),
+            (b"AC", b"ACGT"),
+            (b"", b"TTT"),
+            (b"GATTACA", b"GATACCA"),
+            (b"ACGTACGTTTGGCCAATTACG", b"CCAATTACG"),
+        ];
+        let mut workspace = AlignerWorkspace::default();
+        for &mode in [AlignmentMode::Global, AlignmentMode::Local].iter() {
+            for &linear_memory_threshold in [10, 1 << 24].iter() {
+                let aligner = Aligner {
+                    mode,
+                    linear_memory_threshold,
+                    ..test_aligner()
+                };
+                for (target, query) in pairs.iter() {
+                    let expected = aligner.align(target, query).unwrap();
+                    let alignment = aligner.align_with(&mut workspace, target, query).unwrap();
+                    assert_eq!(alignment.score(), expected.score());
+                    assert_eq!(alignment.path, expected.path);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_band_columns() {
+        let band = Band::new(2, 10, 7);
+        assert_eq!((band.lowest, band.highest), (-2, 5));
+        assert_eq!(band.columns(0, 10), 0..=5);
+        assert_eq!(band.columns(4, 10), 2..=9);
+        assert_eq!(band.columns(7, 10), 5..=10);
+        assert_eq!(Band::new(100, 10, 7), Band::full(10, 7));
+    }
+
+    #[test]
+    fn test_linear_memory() {
+        let mut state: u32 = 54321;
+        let mut random_seq = |len: usize| -> Vec<u8> {
+            (0..len)
+                .map(|_| {
+                    state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    b"ACGT"[(state >> 16) as usize % 4]
+                })
+                .collect()
+        };
+        let modes = [
+            AlignmentMode::Global,
+            AlignmentMode::Local,
+            AlignmentMode::Glocal,
+            AlignmentMode::Overlap,
+        ];
+        for (target_len, query_len) in [(0, 7), (9, 0), (1, 1), (30, 30), (40, 17), (13, 50)].iter()
+        {
+            for _ in 0..5 {
+                let target = random_seq(*target_len);
+                let query = random_seq(*query_len);
+                for (&mode, &band_width) in modes.iter().zip([None, Some(3)].iter().cycle()) {
+                    let aligner = Aligner {
+                        mode,
+                        band_width,
+                        widen_band: false,
+                        ..test_aligner()
+                    };
+                    let expected = aligner.align(&target, &query).unwrap();
+                    for &linear_memory_threshold in [1, 5, 40, 200].iter() {
+                        let alignment = Aligner {
+                            linear_memory_threshold,
+                            ..aligner.clone()
+                        }
+                        .align(&target, &query)
+                        .unwrap();
+                        assert_eq!(alignment.score(), expected.score());
+                        assert_eq!(alignment.path, expected.path);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_tie_policy() -> Result<(), FromUtf8Error> {
+        let aligner = Aligner {
+            mismatch_score: -2,
+            gap_penalty: -1,
+            gap_extend_penalty: -1,
+            end_gap_penalty: -1,
+            end_gap_extend_penalty: -1,
+            ..test_aligner()
+        };
+        // A gap in a homopolymer, and a mismatch tied with a pair of gaps
+        let cases: [(&[u8], &[u8], [&str; 3]); 2] = [
+            (b"CAAAG", b"CAAG", ["C-AAG", "CAA-G", "CAA-G"]),
+            (b"ACT", b"AGT", ["AGT", "A-GT", "AGT"]),
+        ];
+        let policies = [
+            TiePolicy::LeftmostGaps,
+            TiePolicy::RightmostGaps,
+            TiePolicy::PreferSubstitution,
+        ];
+        for (target, query, expected) in cases.iter() {
+            for (&tie_policy, expected) in policies.iter().zip(expected.iter()) {
+                let aligner = Aligner {
+                    tie_policy,
+                    ..aligner.clone()
+                };
+                let (_, aligned_query) = aligner.align(target, query).unwrap().as_strings()?;
+                assert_eq!(&aligned_query, expected, "{:?}", tie_policy);
+                assert_eq!(aligner.count_alignments(target, query).unwrap(), 3);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_co_optimal_alignments() {
+        let mut state: u32 = 2024;
+        let mut random_seq = |len: usize| -> Vec<u8> {
+            (0..len)
+                .map(|_| {
+                    state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    b"ACGT"[(state >> 16) as usize % 4]
+                })
+                .collect()
+        };
+        let modes = [
+            AlignmentMode::Global,
+            AlignmentMode::Local,
+            AlignmentMode::Glocal,
+            AlignmentMode::Overlap,
+        ];
+        for (target_len, query_len) in [(0, 3), (4, 0), (6, 6), (9, 5), (5, 11)].iter() {
+            for _ in 0..10 {
+                let target = random_seq(*target_len);
+                let query = random_seq(*query_len);
+                for (&mode, &band_width) in modes.iter().zip([None, Some(2)].iter().cycle()) {
+                    let aligner = Aligner {
+                        mode,
+                        band_width,
+                        mismatch_score: -2,
+                        gap_penalty: -2,
+                        gap_extend_penalty: -1,
+                        tie_policy: TiePolicy::PreferSubstitution,
+                        ..test_aligner()
+                    };
+                    let expected = aligner.align(&target, &query).unwrap();
+                    let count = aligner.count_alignments(&target, &query).unwrap();
+                    let alignments = aligner
+                        .co_optimal_alignments(&target, &query, usize::MAX)
+                        .unwrap();
+                    assert_eq!(alignments.len() as u64, count);
+                    assert_eq!(alignments[0].path, expected.path);
+                    for (k, alignment) in alignments.iter().enumerate() {
+                        assert_eq!(path_score(&aligner, alignment), expected.score());
+                        assert!(alignments[..k]
+                            .iter()
+                            .all(|other| other.path != alignment.path));
+                    }
+                    let limited = aligner.co_optimal_alignments(&target, &query, 2).unwrap();
+                    assert_eq!(limited.len() as u64, count.min(2));
+                    let linear = Aligner {
+                        linear_memory_threshold: 1,
+                        ..aligner
+                    };
+                    assert_eq!(linear.count_alignments(&target, &query).unwrap(), count);
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_next_after_back_gap_no_gap() {
         let target = [1, 2, 3, 4, 5, 6, 7];
         let query = [1, 2, 3, 11, 5, 6, 8];
-        let alignment = test_aligner().align(&target, &query);
+        let alignment = test_aligner().align(&target, &query).unwrap();
         assert_eq!(
             alignment.common_path_iter().next_after_back_gap(),
             Some((7, 8))
@@ -364,7 +2017,7 @@ mod test_super {
     fn test_next_after_back_gap() {
         let target = [1, 2, 3, 4, 5, 6, 7];
         let query = [2, 3, 4, 11];
-        let alignment = test_aligner().align(&target, &query);
+        let alignment = test_aligner().align(&target, &query).unwrap();
         assert_eq!(
             alignment.common_path_iter().next_after_back_gap(),
             Some((5, 11))
@@ -375,7 +2028,7 @@ mod test_super {
     fn test_common_path_iter() {
         let target = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17];
         let query = [4, 5, 6, 7, 8, 10, 11, 12, 13, 14];
-        let alignment = test_aligner().align(&target, &query);
+        let alignment = test_aligner().align(&target, &query).unwrap();
         assert_eq!(
             alignment.common_path_iter().collect::<Vec<_>>(),
             vec![
@@ -393,4 +2046,92 @@ mod test_super {
             ]
         )
     }
+
+    #[test]
+    fn test_alignment_properties() {
+        let target = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17];
+        let query = [4, 5, 6, 7, 8, 10, 11, 12, 13, 14];
+        let alignment = test_aligner().align(&target, &query).unwrap();
+        assert_eq!(alignment.common_region(), Some(([3, 0], [14, 10])));
+        // Two end gaps of length 3 and one internal gap of length 1
+        assert_eq!(alignment.gap_counts(), (3, 4));
+        assert_eq!(alignment.identity(), 10.0 / 11.0);
+        assert_eq!(alignment.coverage(), 1.0);
+    }
+
+    #[test]
+    fn test_edits() {
+        let alignment = test_aligner().align(b"ttcctcgt", b"cattctcgt").unwrap();
+        assert_eq!(alignment.cigar(), "1I1X1=1X5=");
+        let aligner = Aligner {
+            mode: AlignmentMode::Local,
+            ..test_aligner()
+        };
+        let alignment = aligner.align(b"GGACGTA", b"TACGTCC").unwrap();
+        // The unaligned ends of the query are soft clipped, those of the target left out
+        assert_eq!(alignment.cigar(), "1S4=2S");
+        assert_eq!(
+            alignment.edits(),
+            [Edit {
+                kind: EditKind::Match,
+                target_start: 2,
+                query_start: 1,
+                len: 4,
+            }]
+        );
+        let glocal = Aligner {
+            mode: AlignmentMode::Glocal,
+            ..test_aligner()
+        };
+        let alignment = glocal.align(b"ttttacgtacgtgggg", b"acgaacgt").unwrap();
+        assert_eq!(alignment.cigar(), "3=1X4=");
+        assert_eq!(alignment.edits()[0].target_start, 4);
+        assert_eq!(test_aligner().align(b"", b"").unwrap().cigar(), "");
+    }
+
+    #[test]
+    fn test_orientation() {
+        let mut reverse = Vec::new();
+        reverse_complement_into(b"ACGTURYKMBVDHNSW-acgtn", &mut reverse);
+        assert_eq!(reverse, b"nacgt-WSNDHBVKMRYAACGT");
+
+        let target = b"GGATCCTTAGCAACGTTAGCCAT";
+        let query = b"ATGGCTAACGTTGCTAAGG";
+        let mut workspace = AlignerWorkspace::default();
+        let mut reverse = Vec::new();
+        let (alignment, orientation) = test_aligner()
+            .align_oriented(&mut workspace, target, query, &mut reverse)
+            .unwrap();
+        assert_eq!(orientation, Orientation::Forward);
+        assert_eq!(alignment.query, query);
+        let aligner = Aligner {
+            detect_orientation: true,
+            ..test_aligner()
+        };
+        let mut reverse = Vec::new();
+        let (alignment, orientation) = aligner
+            .align_oriented(&mut workspace, target, query, &mut reverse)
+            .unwrap();
+        assert_eq!(orientation, Orientation::Reverse);
+        assert_eq!(alignment.query, b"CCTTAGCAACGTTAGCCAT");
+        assert_eq!(alignment.identity(), 1.0);
+        let mut reverse = Vec::new();
+        let (_, orientation) = aligner
+            .align_oriented(&mut workspace, target, target, &mut reverse)
+            .unwrap();
+        assert_eq!(orientation, Orientation::Forward);
+    }
+
+    #[test]
+    fn test_no_common_region() {
+        let aligner = Aligner {
+            mismatch_score: -20,
+            ..test_aligner()
+        };
+        let alignment = aligner.align(b"a", b"c").unwrap();
+        assert_eq!(alignment.common_region(), None);
+        assert_eq!(alignment.common_path_iter().next(), None);
+        assert!(alignment.identity().is_nan());
+        assert_eq!(alignment.coverage(), 0.0);
+    }
 }
@@ -0,0 +1,376 @@
+//! Substitution matrices.
+use core::fmt;
+
+use pyo3::prelude::pyclass;
+
+/// Error returned when a substitution matrix is malformed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MatrixError(String);
+
+impl fmt::Display for MatrixError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid substitution matrix: {}", self.0)
+    }
+}
+
+impl std::error::Error for MatrixError {}
+
+/// Scores for aligning pairs of symbols.
+///
+/// Lookup is case-insensitive, unless the alphabet contains both cases of a letter.
+#[pyclass]
+#[derive(Clone, PartialEq, Eq)]
+pub struct SubstitutionMatrix {
+    /// Symbols in the order of the rows and the columns.
+    alphabet: Vec<u8>,
+    /// Position of each byte in `alphabet`, or `u8::MAX` if it's absent.
+    index: [u8; 256],
+    /// Scores stored row by row.
+    scores: Vec<i32>,
+}
+
+impl fmt::Debug for SubstitutionMatrix {
+    /// Prints the matrix in the NCBI format.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &symbol in self.alphabet.iter() {
+            write!(f, " {:>3}", symbol as char)?;
+        }
+        for (row, &symbol) in self.scores.chunks(self.alphabet.len()).zip(&self.alphabet) {
+            write!(f, "\n{}", symbol as char)?;
+            for score in row {
+                write!(f, " {:>3}", score)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SubstitutionMatrix {
+    /// Constructs a matrix from its `alphabet` and the rows of scores.
+    /// # Errors
+    /// Returns [MatrixError] if a symbol is repeated or the rows don't form a square matrix.
+    pub fn new(alphabet: &[u8], rows: &[Vec<i32>]) -> Result<Self, MatrixError> {
+        if alphabet.len() >= u8::MAX as usize {
+            return Err(MatrixError(format!(
+                "alphabet is too large: {} symbols",
+                alphabet.len()
+            )));
+        }
+        if rows.len() != alphabet.len() || rows.iter().any(|row| row.len() != alphabet.len()) {
+            return Err(MatrixError(format!(
+                "expected {0}x{0} scores for the alphabet {1:?}",
+                alphabet.len(),
+                String::from_utf8_lossy(alphabet)
+            )));
+        }
+        let mut index = [u8::MAX; 256];
+        for (i, &symbol) in alphabet.iter().enumerate() {
+            if index[symbol as usize] != u8::MAX {
+                return Err(MatrixError(format!(
+                    "symbol {:?} is repeated",
+                    symbol as char
+                )));
+            }
+            index[symbol as usize] = i as u8;
+        }
+        // Letters of the other case fall back to the given ones
+        for (i, &symbol) in alphabet.iter().enumerate() {
+            for other in [symbol.to_ascii_lowercase(), symbol.to_ascii_uppercase()].iter() {
+                if index[*other as usize] == u8::MAX {
+                    index[*other as usize] = i as u8;
+                }
+            }
+        }
+        Ok(SubstitutionMatrix {
+            alphabet: alphabet.to_vec(),
+            index,
+            scores: rows.concat(),
+        })
+    }
+
+    /// Constructs a matrix from the scores of pairs of symbols.
+    ///
+    /// The score of `(b, a)` is taken to be the same as of `(a, b)`, unless given explicitly.
+    /// # Errors
+    /// Returns [MatrixError] if the score of a pair of symbols is missing.
+    pub fn from_pairs(pairs: &[(u8, u8, i32)]) -> Result<Self, MatrixError> {
+        let mut alphabet: Vec<u8> = Vec::new();
+        for &(a, b, _) in pairs {
+            for symbol in [a, b].iter() {
+                if !alphabet.contains(symbol) {
+                    alphabet.push(*symbol);
+                }
+            }
+        }
+        let position = |symbol: u8| alphabet.iter().position(|&s| s == symbol).unwrap();
+        let mut rows = vec![vec![None; alphabet.len()]; alphabet.len()];
+        // Explicit scores override the symmetric ones
+        for &(a, b, score) in pairs {
+            rows[position(b)][position(a)].get_or_insert(score);
+        }
+        for &(a, b, score) in pairs {
+            rows[position(a)][position(b)] = Some(score);
+        }
+        let rows = rows
+            .into_iter()
+            .zip(alphabet.iter())
+            .map(|(row, &a)| {
+                row.into_iter()
+                    .zip(alphabet.iter())
+                    .map(|(score, &b)| {
+                        score.ok_or_else(|| {
+                            MatrixError(format!(
+                                "missing score for {:?}",
+                                String::from_utf8_lossy(&[a, b])
+                            ))
+                        })
+                    })
+                    .collect()
+            })
+            .collect::<Result<Vec<Vec<i32>>, MatrixError>>()?;
+        Self::new(&alphabet, &rows)
+    }
+
+    /// Parses a matrix in the NCBI format.
+    ///
+    /// The first non-comment line contains the alphabet,
+    /// each of the following lines contains a symbol and its row of scores.
+    /// Lines starting with `#` are comments.
+    /// # Errors
+    /// Returns [MatrixError] if the text is malformed.
+    pub fn parse(text: &str) -> Result<Self, MatrixError> {
+        let mut lines = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        let alphabet: Vec<u8> = lines
+            .next()
+            .ok_or_else(|| MatrixError("no alphabet".to_owned()))?
+            .split_whitespace()
+            .map(|symbol| symbol.as_bytes()[0])
+            .collect();
+        let rows = lines
+            .zip(alphabet.iter())
+            .map(|(line, &symbol)| {
+                let mut fields = line.split_whitespace();
+                if fields.next().map(str::as_bytes) != Some(&[symbol]) {
+                    return Err(MatrixError(format!(
+                        "expected row for {:?}",
+                        symbol as char
+                    )));
+                }
+                fields
+                    .map(|field| {
+                        field
+                            .parse()
+                            .map_err(|_| MatrixError(format!("invalid score {:?}", field)))
+                    })
+                    .collect()
+            })
+            .collect::<Result<Vec<Vec<i32>>, MatrixError>>()?;
+        Self::new(&alphabet, &rows)
+    }
+
+    /// Returns one of the built-in matrices by `name`.
+    ///
+    /// Known names are `NUC.4.4` (also `EDNAFULL`), `BLOSUM62` and `PAM250`.
+    pub fn named(name: &str) -> Option<Self> {
+        let text = match name.to_ascii_uppercase().as_str() {
+            "NUC.4.4" | "EDNAFULL" => NUC_4_4,
+            "BLOSUM62" => BLOSUM62,
+            "PAM250" => PAM250,
+            _ => return None,
+        };
+        Some(Self::parse(text).expect("built-in matrices are valid"))
+    }
+
+    /// DNA matrix that distinguishes transitions (A↔G, C↔T) from transversions.
+    ///
+    /// `U` is treated as `T`.
+    pub fn transition_transversion(
+        match_score: i32,
+        transition_score: i32,
+        transversion_score: i32,
+    ) -> Self {
+        const ALPHABET: &[u8] = b"ACGTU";
+        let is_purine = |symbol: u8| matches!(symbol, b'A' | b'G');
+        let rows: Vec<Vec<i32>> = ALPHABET
+            .iter()
+            .map(|&a| {
+                ALPHABET
+                    .iter()
+                    .map(|&b| {
+                        let same = a == b || matches!((a, b), (b'T', b'U') | (b'U', b'T'));
+                        if same {
+                            match_score
+                        } else if is_purine(a) == is_purine(b) {
+                            transition_score
+                        } else {
+                            transversion_score
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        Self::new(ALPHABET, &rows).expect("the matrix is square")
+    }
+
+    /// Returns the score of aligning `a` to `b`, if both symbols are in the alphabet.
+    pub fn score(&self, a: u8, b: u8) -> Option<i32> {
+        let (i, j) = (self.index[a as usize], self.index[b as usize]);
+        if i == u8::MAX || j == u8::MAX {
+            None
+        } else {
+            Some(self.scores[i as usize * self.alphabet.len() + j as usize])
+        }
+    }
+
+    /// Largest absolute value of the scores.
+    pub fn max_abs_score(&self) -> i32 {
+        self.scores
+            .iter()
+            .map(|score| score.saturating_abs())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// NCBI nucleotide matrix, also known as EDNAFULL.
+const NUC_4_4: &str = "
+   A  T  G  C  S  W  R  Y  K  M  B  V  H  D  N
+A  5 -4 -4 -4 -4  1  1 -4 -4  1 -4 -1 -1 -1 -2
+T -4  5 -4 -4 -4  1 -4  1  1 -4 -1 -4 -1 -1 -2
+G -4 -4  5 -4  1 -4  1 -4  1 -4 -1 -1 -4 -1 -2
+C -4 -4 -4  5  1 -4 -4  1 -4  1 -1 -1 -1 -4 -2
+S -4 -4  1  1 -1 -4 -2 -2 -2 -2 -1 -1 -3 -3 -1
+W  1  1 -4 -4 -4 -1 -2 -2 -2 -2 -3 -3 -1 -1 -1
+R  1 -4  1 -4 -2 -2 -1 -4 -2 -2 -3 -1 -3 -1 -1
+Y -4  1 -4  1 -2 -2 -4 -1 -2 -2 -1 -3 -1 -3 -1
+K -4  1  1 -4 -2 -2 -2 -2 -1 -4 -1 -3 -3 -1 -1
+M  1 -4 -4  1 -2 -2 -2 -2 -4 -1 -3 -1 -1 -3 -1
+B -4 -1 -1 -1 -1 -3 -3 -1 -1 -3 -1 -2 -2 -2 -1
+V -1 -4 -1 -1 -1 -3 -1 -3 -3 -1 -2 -1 -2 -2 -1
+H -1 -1 -4 -1 -3 -1 -3 -1 -3 -1 -2 -2 -1 -2 -1
+D -1 -1 -1 -4 -3 -1 -1 -3 -1 -3 -2 -2 -2 -1 -1
+N -2 -2 -2 -2 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1
+";
+
+const BLOSUM62: &str = "
+   A  R  N  D  C  Q  E  G  H  I  L  K  M  F  P  S  T  W  Y  V  B  Z  X  *
+A  4 -1 -2 -2  0 -1 -1  0 -2 -1 -1 -1 -1 -2 -1  1  0 -3 -2  0 -2 -1  0 -4
+R -1  5  0 -2 -3  1  0 -2  0 -3 -2  2 -1 -3 -2 -1 -1 -3 -2 -3 -1  0 -1 -4
+N -2  0  6  1 -3  0  0  0  1 -3 -3  0 -2 -3 -2  1  0 -4 -2 -3  3  0 -1 -4
+D -2 -2  1  6 -3  0  2 -1 -1 -3 -4 -1 -3 -3 -1  0 -1 -4 -3 -3  4  1 -1 -4
+C  0 -3 -3 -3  9 -3 -4 -3 -3 -1 -1 -3 -1 -2 -3 -1 -1 -2 -2 -1 -3 -3 -2 -4
+Q -1  1  0  0 -3  5  2 -2  0 -3 -2  1  0 -3 -1  0 -1 -2 -1 -2  0  3 -1 -4
+E -1  0  0  2 -4  2  5 -2  0 -3 -3  1 -2 -3 -1  0 -1 -3 -2 -2  1  4 -1 -4
+G  0 -2  0 -1 -3 -2 -2  6 -2 -4 -4 -2 -3 -3 -2  0 -2 -2 -3 -3 -1 -2 -1 -4
+H -2  0  1 -1 -3  0  0 -2  8 -3 -3 -1 -2 -1 -2 -1 -2 -2  2 -3  0  0 -1 -4
+I -1 -3 -3 -3 -1 -3 -3 -4 -3  4  2 -3  1  0 -3 -2 -1 -3 -1  3 -3 -3 -1 -4
+L -1 -2 -3 -4 -1 -2 -3 -4 -3  2  4 -2  2  0 -3 -2 -1 -2 -1  1 -4 -3 -1 -4
+K -1  2  0 -1 -3  1  1 -2 -1 -3 -2  5 -1 -3 -1  0 -1 -3 -2 -2  0  1 -1 -4
+M -1 -1 -2 -3 -1  0 -2 -3 -2  1  2 -1  5  0 -2 -1 -1 -1 -1  1 -3 -1 -1 -4
+F -2 -3 -3 -3 -2 -3 -3 -3 -1  0  0 -3  0  6 -4 -2 -2  1  3 -1 -3 -3 -1 -4
+P -1 -2 -2 -1 -3 -1 -1 -2 -2 -3 -3 -1 -2 -4  7 -1 -1 -4 -3 -2 -2 -1 -2 -4
+S  1 -1  1  0 -1  0  0  0 -1 -2 -2  0 -1 -2 -1  4  1 -3 -2 -2  0  0  0 -4
+T  0 -1  0 -1 -1 -1 -1 -2 -2 -1 -1 -1 -1 -2 -1  1  5 -2 -2  0 -1 -1  0 -4
+W -3 -3 -4 -4 -2 -2 -3 -2 -2 -3 -2 -3 -1  1 -4 -3 -2 11  2 -3 -4 -3 -2 -4
+Y -2 -2 -2 -3 -2 -1 -2 -3  2 -1 -1 -2 -1  3 -3 -2 -2  2  7 -1 -3 -2 -1 -4
+V  0 -3 -3 -3 -1 -2 -2 -3 -3  3  1 -2  1 -1 -2 -2  0 -3 -1  4 -3 -2 -1 -4
+B -2 -1  3  4 -3  0  1 -1  0 -3 -4  0 -3 -3 -2  0 -1 -4 -3 -3  4  1 -1 -4
+Z -1  0  0  1 -3  3  4 -2  0 -3 -3  1 -1 -3 -1  0 -1 -3 -2 -2  1  4 -1 -4
+X  0 -1 -1 -1 -2 -1 -1 -1 -1 -1 -1 -1 -1 -1 -2  0  0 -2 -1 -1 -1 -1 -1 -4
+* -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4  1
+";
+
+const PAM250: &str = "
+   A  R  N  D  C  Q  E  G  H  I  L  K  M  F  P  S  T  W  Y  V  B  Z  X  *
+A  2 -2  0  0 -2  0  0  1 -1 -1 -2 -1 -1 -3  1  1  1 -6 -3  0  0  0  0 -8
+R -2  6  0 -1 -4  1 -1 -3  2 -2 -3  3  0 -4  0  0 -1  2 -4 -2 -1  0 -1 -8
+N  0  0  2  2 -4  1  1  0  2 -2 -3  1 -2 -3  0  1  0 -4 -2 -2  2  1  0 -8
+D  0 -1  2  4 -5  2  3  1  1 -2 -4  0 -3 -6 -1  0  0 -7 -4 -2  3  3 -1 -8
+C -2 -4 -4 -5 12 -5 -5 -3 -3 -2 -6 -5 -5 -4 -3  0 -2 -8  0 -2 -4 -5 -3 -8
+Q  0  1  1  2 -5  4  2 -1  3 -2 -2  1 -1 -5  0 -1 -1 -5 -4 -2  1  3 -1 -8
+E  0 -1  1  3 -5  2  4  0  1 -2 -3  0 -2 -5 -1  0  0 -7 -4 -2  3  3 -1 -8
+G  1 -3  0  1 -3 -1  0  5 -2 -3 -4 -2 -3 -5  0  1  0 -7 -5 -1  0  0 -1 -8
+H -1  2  2  1 -3  3  1 -2  6 -2 -2  0 -2 -2  0 -1 -1 -3  0 -2  1  2 -1 -8
+I -1 -2 -2 -2 -2 -2 -2 -3 -2  5  2 -2  2  1 -2 -1  0 -5 -1  4 -2 -2 -1 -8
+L -2 -3 -3 -4 -6 -2 -3 -4 -2  2  6 -3  4  2 -3 -3 -2 -2 -1  2 -3 -3 -1 -8
+K -1  3  1  0 -5  1  0 -2  0 -2 -3  5  0 -5 -1  0  0 -3 -4 -2  1  0 -1 -8
+M -1  0 -2 -3 -5 -1 -2 -3 -2  2  4  0  6  0 -2 -2 -1 -4 -2  2 -2 -2 -1 -8
+F -3 -4 -3 -6 -4 -5 -5 -5 -2  1  2 -5  0  9 -5 -3 -3  0  7 -1 -4 -5 -2 -8
+P  1  0  0 -1 -3  0 -1  0  0 -2 -3 -1 -2 -5  6  1  0 -6 -5 -1 -1  0 -1 -8
+S  1  0  1  0  0 -1  0  1 -1 -1 -3  0 -2 -3  1  2  1 -2 -3 -1  0  0  0 -8
+T  1 -1  0  0 -2 -1  0  0 -1  0 -2  0 -1 -3  0  1  3 -5 -3  0  0 -1  0 -8
+W -6  2 -4 -7 -8 -5 -7 -7 -3 -5 -2 -3 -4  0 -6 -2 -5 17  0 -6 -5 -6 -4 -8
+Y -3 -4 -2 -4  0 -4 -4 -5  0 -1 -1 -4 -2  7 -5 -3 -3  0 10 -2 -3 -4 -2 -8
+V  0 -2 -2 -2 -2 -2 -2 -1 -2  4  2 -2  2 -1 -1 -1  0 -6 -2  4 -2 -2 -1 -8
+B  0 -1  2  3 -4  1  3  0  1 -2 -3  1 -2 -4 -1  0  0 -5 -3 -2  3  2 -1 -8
+Z  0  0  1  3 -5  3  3  0  2 -2 -3  0 -2 -5  0  0 -1 -6 -4 -2  2  3 -1 -8
+X  0 -1  0 -1 -3 -1 -1 -1 -1 -1 -1 -1 -1 -2 -1  0  0 -4 -2 -1 -1 -1 -1 -8
+* -8 -8 -8 -8 -8 -8 -8 -8 -8 -8 -8 -8 -8 -8 -8 -8 -8 -8 -8 -8 -8 -8 -8  1
+";
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    #[test]
+    fn test_named() {
+        for name in ["NUC.4.4", "EDNAFULL", "blosum62", "PAM250"].iter() {
+            let matrix = SubstitutionMatrix::named(name).expect("unknown matrix");
+            for &a in matrix.alphabet.iter() {
+                for &b in matrix.alphabet.iter() {
+                    assert_eq!(matrix.score(a, b), matrix.score(b, a));
+                }
+            }
+        }
+        assert_eq!(SubstitutionMatrix::named("BLOSUM1000"), None);
+        let nuc = SubstitutionMatrix::named("NUC.4.4").unwrap();
+        assert_eq!(nuc.score(b'A', b'A'), Some(5));
+        assert_eq!(nuc.score(b'r', b'A'), Some(1));
+        assert_eq!(nuc.score(b'R', b'c'), Some(-4));
+        assert_eq!(nuc.score(b'A', b'-'), None);
+        let blosum = SubstitutionMatrix::named("BLOSUM62").unwrap();
+        assert_eq!(blosum.score(b'W', b'W'), Some(11));
+        assert_eq!(blosum.score(b'i', b'v'), Some(3));
+    }
+
+    #[test]
+    fn test_transition_transversion() {
+        let matrix = SubstitutionMatrix::transition_transversion(2, -1, -3);
+        assert_eq!(matrix.score(b'a', b'A'), Some(2));
+        assert_eq!(matrix.score(b'T', b'U'), Some(2));
+        assert_eq!(matrix.score(b'A', b'G'), Some(-1));
+        assert_eq!(matrix.score(b'c', b'u'), Some(-1));
+        assert_eq!(matrix.score(b'A', b'T'), Some(-3));
+        assert_eq!(matrix.score(b'N', b'T'), None);
+    }
+
+    #[test]
+    fn test_from_pairs() {
+        let matrix = SubstitutionMatrix::from_pairs(&[
+            (b'x', b'x', 3),
+            (b'y', b'y', 2),
+            (b'x', b'y', -1),
+            (b'y', b'x', -2),
+            (b'x', b'z', 1),
+            (b'z', b'z', 0),
+            (b'y', b'z', 0),
+        ])
+        .unwrap();
+        assert_eq!(matrix.score(b'x', b'y'), Some(-1));
+        assert_eq!(matrix.score(b'y', b'x'), Some(-2));
+        assert_eq!(matrix.score(b'z', b'x'), Some(1));
+        assert_eq!(matrix.score(b'X', b'Z'), Some(1));
+        assert!(SubstitutionMatrix::from_pairs(&[(b'x', b'x', 1), (b'y', b'y', 1)]).is_err());
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(SubstitutionMatrix::new(b"AC", &[vec![1, 0], vec![0]]).is_err());
+        assert!(SubstitutionMatrix::new(b"AA", &[vec![1, 0], vec![0, 1]]).is_err());
+        assert!(SubstitutionMatrix::parse("A C\nA 1 0\nG 0 1").is_err());
+        assert!(SubstitutionMatrix::parse("A C\nA 1 0\nC 0 x").is_err());
+    }
+}
@@ -1,7 +1,21 @@
 //! Scores for the Needleman-Wunsch algorithm
 use core::fmt::{self, Debug};
 
+/// Largest absolute value of a score of an alignment.
+///
+/// [crate::needle::Aligner] refuses to align sequences if the score could exceed it.
+pub const MAX_SCORE: i32 = i32::MAX / 4;
+
+/// Score of an unreachable state.
+///
+/// Far enough from [i32::MIN] that adding a penalty to it doesn't overflow
+/// and far enough from [MAX_SCORE] that it can't be confused with a valid score.
+pub const NEG_INF: i32 = i32::MIN / 2;
+
 /// For marking direction in the Needleman-Wunsch algorithm.
+///
+/// Also names the three matrices of the Gotoh algorithm,
+/// by the direction of the last step of the path ending in them.
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub enum Dir {
     /// The optimal score comes from the left.
@@ -24,34 +38,180 @@ impl Debug for Dir {
     }
 }
 
-/// Contents of a cell of the Needleman-Wunsch table.
-#[derive(Copy, Clone, PartialEq, Eq)]
-pub struct Score {
-    /// Direction of the previous optimal score.
-    pub dir: Dir,
-    /// Current optimal score.
-    pub score: i16,
+impl Dir {
+    /// Returns the cell before `[i, j]` on a path making the step `self` into it.
+    pub fn step_back(self, [i, j]: [usize; 2]) -> [usize; 2] {
+        match self {
+            Dir::Diagonal => [i - 1, j - 1],
+            Dir::Up => [i, j - 1],
+            Dir::Left => [i - 1, j],
+        }
+    }
 }
 
-impl Debug for Score {
-    /// Formats the [Score] with the width 5.
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}{:>4}", self.dir, self.score)
+/// Set of the matrices of the Gotoh algorithm.
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
+pub struct Dirs(u8);
+
+impl Dirs {
+    /// Returns the bit of the matrix `dir` in the set.
+    const fn bit(dir: Dir) -> u8 {
+        match dir {
+            Dir::Diagonal => 0b001,
+            Dir::Up => 0b010,
+            Dir::Left => 0b100,
+        }
+    }
+
+    /// Constructs a set from its bits, the lowest for the diagonal, then up, then left.
+    pub fn from_bits(bits: u8) -> Self {
+        Dirs(bits & 0b111)
+    }
+
+    /// Constructs a set with only the matrix `dir`.
+    pub fn single(dir: Dir) -> Self {
+        Dirs(Self::bit(dir))
+    }
+
+    /// Returns the bits of the set.
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Checks whether `dir` is in the set.
+    pub fn contains(self, dir: Dir) -> bool {
+        self.0 & Self::bit(dir) != 0
+    }
+
+    /// Checks whether the set is empty.
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
     }
 }
 
-impl Default for Score {
-    fn default() -> Self {
-        Score {
-            dir: Dir::Diagonal,
-            score: 0,
+impl Debug for Dirs {
+    /// Formats the [Dirs] with the width 3.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for dir in [Dir::Diagonal, Dir::Up, Dir::Left] {
+            if self.contains(dir) {
+                write!(f, "{:?}", dir)?
+            } else {
+                f.write_str("·")?
+            }
         }
+        Ok(())
     }
 }
 
-impl Score {
-    /// Contructs a [Score]
-    pub fn new(dir: Dir, score: i16) -> Self {
-        Score { dir, score }
+/// Returns the best of the scores of the three matrices of a cell
+/// and the set of the matrices having it.
+pub fn best(diagonal: i32, up: i32, left: i32) -> (i32, Dirs) {
+    let score = diagonal.max(up).max(left);
+    let bits = [(diagonal, Dir::Diagonal), (up, Dir::Up), (left, Dir::Left)]
+        .iter()
+        .filter(|&&(value, _)| value == score)
+        .fold(0, |bits, &(_, dir)| bits | Dirs::bit(dir));
+    (score, Dirs(bits))
+}
+
+/// Traceback of a cell of the Gotoh table.
+///
+/// Holds the matrices of the previous cell on the optimal paths ending in each
+/// of the three matrices of the cell, packed in three bits each.
+/// No matrices if the optimal path starts with the step into the cell.
+/// Kept in a `u16` alone, so that the vectorised kernels can store the traces of several cells.
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
+#[repr(transparent)]
+pub struct Trace(u16);
+
+impl Trace {
+    /// Contructs a [Trace] from the previous matrices of the paths ending
+    /// with a match or mismatch, with a gap in the target and with a gap in the query.
+    pub fn new(diagonal: Dirs, up: Dirs, left: Dirs) -> Self {
+        Trace(diagonal.0 as u16 | (up.0 as u16) << 3 | (left.0 as u16) << 6)
+    }
+
+    /// Returns the matrices of the previous cell on the optimal paths ending in the matrix `dir`.
+    pub fn get(self, dir: Dir) -> Dirs {
+        let shift = match dir {
+            Dir::Diagonal => 0,
+            Dir::Up => 3,
+            Dir::Left => 6,
+        };
+        Dirs::from_bits((self.0 >> shift) as u8)
+    }
+}
+
+impl Debug for Trace {
+    /// Formats the [Trace] with the width 11.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?} {:?} {:?}",
+            self.get(Dir::Diagonal),
+            self.get(Dir::Up),
+            self.get(Dir::Left)
+        )
+    }
+}
+
+/// Optimal scores of the three matrices of the Gotoh algorithm along a row of the table.
+#[derive(Clone, Debug, Default)]
+pub struct ScoreRow {
+    /// Paths ending with `target[i - 1]` aligned to `query[j - 1]`.
+    pub diagonal: Vec<i32>,
+    /// Paths ending with `query[j - 1]` aligned to a gap.
+    pub up: Vec<i32>,
+    /// Paths ending with `target[i - 1]` aligned to a gap.
+    pub left: Vec<i32>,
+}
+
+impl ScoreRow {
+    /// Makes the row `width` cells long, new cells are unreachable.
+    pub fn resize(&mut self, width: usize) {
+        self.diagonal.resize(width, NEG_INF);
+        self.up.resize(width, NEG_INF);
+        self.left.resize(width, NEG_INF);
+    }
+
+    /// Marks all the matrices of the cell `i` unreachable.
+    pub fn set_unreachable(&mut self, i: usize) {
+        self.diagonal[i] = NEG_INF;
+        self.up[i] = NEG_INF;
+        self.left[i] = NEG_INF;
+    }
+
+    /// Returns the best score in the cell `i` and the matrices having it.
+    pub fn best(&self, i: usize) -> (i32, Dirs) {
+        best(self.diagonal[i], self.up[i], self.left[i])
+    }
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    #[test]
+    fn test_trace() {
+        for diagonal in 0..8 {
+            for up in 0..8 {
+                for left in 0..8 {
+                    let [diagonal, up, left] = [diagonal, up, left].map(Dirs::from_bits);
+                    let trace = Trace::new(diagonal, up, left);
+                    assert_eq!(trace.get(Dir::Diagonal), diagonal);
+                    assert_eq!(trace.get(Dir::Up), up);
+                    assert_eq!(trace.get(Dir::Left), left);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_best() {
+        assert_eq!(best(1, 1, 1), (1, Dirs::from_bits(0b111)));
+        assert_eq!(best(0, 1, 1), (1, Dirs::from_bits(0b110)));
+        assert_eq!(best(0, 2, 1), (2, Dirs::from_bits(0b010)));
+        assert!(best(2, 1, 0).1.contains(Dir::Diagonal));
+        assert!(!best(2, 1, 0).1.contains(Dir::Up));
     }
 }
@@ -0,0 +1,604 @@
+//! Vectorised computation of the rows of the Gotoh table.
+//!
+//! The matrices of a cell for a match or mismatch and for a gap in the target
+//! only depend on the row above, so they are computed for several cells at once,
+//! with AVX2 or SSE2 when the processor supports them and one by one otherwise.
+//!
+//! Gaps in the query depend on the cell to the left. Once the other two matrices
+//! of the row are known, the best gap ending in each cell of a vector is found
+//! by a prefix maximum over its lanes, in as many steps as the logarithm of the lanes,
+//! and the gap reaching the vector from the left is carried over from the previous one.
+use super::score::{best, Dirs, ScoreRow, Trace};
+use std::ops::Range;
+
+/// Computes the diagonal and the up matrices of the cells `columns` of a row
+/// from the row `above` it.
+///
+/// `substitution[i]` is the score of aligning `target[i - 1]` to the symbol of the query of the row,
+/// `open` and `extend` are the penalties for gaps in the target.
+/// Stores the sets of optimal previous matrices of the cells into `codes`,
+/// packed like by [super::score::Trace::new].
+///
+/// All the kernels produce the same scores and the same ties.
+/// # Panics
+/// Panics if `columns` starts at zero or doesn't fit into the rows.
+pub fn diagonal_and_up(
+    above: &ScoreRow,
+    substitution: &[i32],
+    (open, extend): (i32, i32),
+    local: bool,
+    columns: Range<usize>,
+    row: &mut ScoreRow,
+    codes: &mut [i32],
+) {
+    if columns.is_empty() {
+        return;
+    }
+    assert!(columns.start > 0, "the first column has no diagonal");
+    let width = columns.end;
+    assert!(
+        [
+            above.diagonal.len(),
+            above.up.len(),
+            above.left.len(),
+            substitution.len(),
+            row.diagonal.len(),
+            row.up.len(),
+            codes.len(),
+        ]
+        .iter()
+        .all(|&len| len >= width),
+        "{:?} don't fit into the row",
+        columns
+    );
+    #[cfg(target_arch = "x86_64")]
+    let columns = {
+        // Safety: the lengths are checked above and the features are detected
+        let computed = if is_x86_feature_detected!("avx2") {
+            unsafe {
+                x86::avx2::diagonal_and_up(
+                    above,
+                    substitution,
+                    (open, extend),
+                    local,
+                    &columns,
+                    row,
+                    codes,
+                )
+            }
+        } else {
+            unsafe {
+                x86::sse2::diagonal_and_up(
+                    above,
+                    substitution,
+                    (open, extend),
+                    local,
+                    &columns,
+                    row,
+                    codes,
+                )
+            }
+        };
+        computed..columns.end
+    };
+    scalar_diagonal_and_up(
+        above,
+        substitution,
+        (open, extend),
+        local,
+        columns,
+        row,
+        codes,
+    );
+}
+
+/// Computes the left matrix of the cells `columns` of a row whose diagonal and up matrices
+/// are computed, continuing the left matrix of the cell before them.
+///
+/// `open` and `extend` are the penalties for gaps in the query.
+/// Stores the traceback of the cells into `trace`, with the sets of previous matrices
+/// of the diagonal and the up matrices packed in `codes` by [diagonal_and_up].
+///
+/// All the kernels produce the same scores and the same ties.
+/// # Panics
+/// Panics if `columns` starts at zero or doesn't fit into the row.
+pub fn left(
+    (open, extend): (i32, i32),
+    columns: Range<usize>,
+    row: &mut ScoreRow,
+    codes: &[i32],
+    trace: &mut [Trace],
+) {
+    if columns.is_empty() {
+        return;
+    }
+    assert!(
+        columns.start > 0,
+        "the first column has no cell to the left"
+    );
+    let width = columns.end;
+    assert!(
+        [
+            row.diagonal.len(),
+            row.up.len(),
+            row.left.len(),
+            codes.len(),
+            trace.len(),
+        ]
+        .iter()
+        .all(|&len| len >= width),
+        "{:?} don't fit into the row",
+        columns
+    );
+    #[cfg(target_arch = "x86_64")]
+    let columns = {
+        // Safety: the lengths are checked above and the features are detected
+        let computed = if is_x86_feature_detected!("avx2") {
+            unsafe { x86::avx2::left((open, extend), &columns, row, codes, trace) }
+        } else {
+            unsafe { x86::sse2::left((open, extend), &columns, row, codes, trace) }
+        };
+        computed..columns.end
+    };
+    scalar_left((open, extend), columns, row, codes, trace);
+}
+
+/// Scalar kernel of [diagonal_and_up].
+fn scalar_diagonal_and_up(
+    above: &ScoreRow,
+    substitution: &[i32],
+    (open, extend): (i32, i32),
+    local: bool,
+    columns: Range<usize>,
+    row: &mut ScoreRow,
+    codes: &mut [i32],
+) {
+    for i in columns {
+        let (diagonal, dirs) = best(above.diagonal[i - 1], above.up[i - 1], above.left[i - 1]);
+        // A local alignment can start anywhere
+        let (diagonal, diagonal_dirs) = if local && diagonal < 0 {
+            (0, Dirs::default())
+        } else {
+            (diagonal, dirs)
+        };
+        row.diagonal[i] = diagonal + substitution[i];
+        let (up, up_dirs) = best(
+            above.diagonal[i].saturating_add(open),
+            above.up[i].saturating_add(extend),
+            above.left[i].saturating_add(open),
+        );
+        row.up[i] = up;
+        codes[i] = (diagonal_dirs.bits() | up_dirs.bits() << 3) as i32;
+    }
+}
+
+/// Scalar kernel of [left].
+fn scalar_left(
+    (open, extend): (i32, i32),
+    columns: Range<usize>,
+    row: &mut ScoreRow,
+    codes: &[i32],
+    trace: &mut [Trace],
+) {
+    for i in columns {
+        let (left, dirs) = best(
+            row.diagonal[i - 1].saturating_add(open),
+            row.up[i - 1].saturating_add(open),
+            row.left[i - 1].saturating_add(extend),
+        );
+        row.left[i] = left;
+        let code = codes[i] as u8;
+        trace[i] = Trace::new(Dirs::from_bits(code), Dirs::from_bits(code >> 3), dirs);
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    /// Defines a module with the kernels of [super::diagonal_and_up] and [super::left]
+    /// computing `$lanes` cells at once with the integer vector intrinsics
+    /// of the target feature `$feature`.
+    ///
+    /// The `$lane_moves` define the moves of values between the lanes, which differ
+    /// between the features: `shift`, `broadcast_last` and `store_trace`.
+    macro_rules! kernel {
+        (
+            $name:ident, $feature:tt, $vector:ty, $lanes:expr,
+            $set1:ident, $loadu:ident, $storeu:ident,
+            $add:ident, $cmpgt:ident, $cmpeq:ident, $and:ident, $andnot:ident, $or:ident,
+            $slli:ident,
+            { $($lane_moves:item)* }
+        ) => {
+            pub mod $name {
+                use crate::needle::score::{ScoreRow, Trace, NEG_INF};
+                use core::arch::x86_64::*;
+                use std::ops::Range;
+
+                #[inline]
+                #[target_feature(enable = $feature)]
+                unsafe fn load(slice: &[i32], i: usize) -> $vector {
+                    $loadu(slice.as_ptr().add(i) as *const $vector)
+                }
+
+                #[inline]
+                #[target_feature(enable = $feature)]
+                unsafe fn store(slice: &mut [i32], i: usize, value: $vector) {
+                    $storeu(slice.as_mut_ptr().add(i) as *mut $vector, value)
+                }
+
+                /// Returns `b` where `mask` is set and `a` elsewhere.
+                #[inline]
+                #[target_feature(enable = $feature)]
+                unsafe fn select(mask: $vector, a: $vector, b: $vector) -> $vector {
+                    $or($and(mask, b), $andnot(mask, a))
+                }
+
+                /// Returns the larger of `a` and `b` in each lane.
+                #[inline]
+                #[target_feature(enable = $feature)]
+                unsafe fn max(a: $vector, b: $vector) -> $vector {
+                    select($cmpgt(b, a), a, b)
+                }
+
+                /// Returns the best of the three matrices and the bits
+                /// of the set of the matrices having it.
+                #[inline]
+                #[target_feature(enable = $feature)]
+                unsafe fn best(
+                    diagonal: $vector,
+                    up: $vector,
+                    left: $vector,
+                ) -> ($vector, $vector) {
+                    let score = select($cmpgt(up, diagonal), diagonal, up);
+                    let score = select($cmpgt(left, score), score, left);
+                    let dirs = $or(
+                        $or(
+                            $and($cmpeq(diagonal, score), $set1(0b001)),
+                            $and($cmpeq(up, score), $set1(0b010)),
+                        ),
+                        $and($cmpeq(left, score), $set1(0b100)),
+                    );
+                    (score, dirs)
+                }
+
+                $($lane_moves)*
+
+                /// Computes the whole vectors of cells of `columns`.
+                ///
+                /// Returns the first column left for the scalar kernel.
+                /// # Safety
+                /// The rows must be at least `columns.end` long and the processor
+                /// must support the target feature.
+                #[target_feature(enable = $feature)]
+                pub unsafe fn diagonal_and_up(
+                    above: &ScoreRow,
+                    substitution: &[i32],
+                    (open, extend): (i32, i32),
+                    local: bool,
+                    columns: &Range<usize>,
+                    row: &mut ScoreRow,
+                    codes: &mut [i32],
+                ) -> usize {
+                    let zero = $set1(0);
+                    let open = $set1(open);
+                    let extend = $set1(extend);
+                    let mut i = columns.start;
+                    while i + $lanes <= columns.end {
+                        let (diagonal, diagonal_dirs) = best(
+                            load(&above.diagonal, i - 1),
+                            load(&above.up, i - 1),
+                            load(&above.left, i - 1),
+                        );
+                        // A local alignment can start anywhere
+                        let (diagonal, diagonal_dirs) = if local {
+                            let mask = $cmpgt(zero, diagonal);
+                            (select(mask, diagonal, zero), $andnot(mask, diagonal_dirs))
+                        } else {
+                            (diagonal, diagonal_dirs)
+                        };
+                        store(&mut row.diagonal, i, $add(diagonal, load(substitution, i)));
+                        let (up, up_dirs) = best(
+                            $add(load(&above.diagonal, i), open),
+                            $add(load(&above.up, i), extend),
+                            $add(load(&above.left, i), open),
+                        );
+                        store(&mut row.up, i, up);
+                        // Shift the set of the up matrix by three bits
+                        let up_dirs = $add(up_dirs, up_dirs);
+                        let up_dirs = $add(up_dirs, up_dirs);
+                        let up_dirs = $add(up_dirs, up_dirs);
+                        store(codes, i, $or(diagonal_dirs, up_dirs));
+                        i += $lanes;
+                    }
+                    i
+                }
+
+                /// Computes the left matrix and the traceback of the whole vectors
+                /// of cells of `columns`.
+                ///
+                /// Returns the first column left for the scalar kernel.
+                /// # Safety
+                /// The rows must be at least `columns.end` long, `columns` must not start
+                /// at zero and the processor must support the target feature.
+                #[target_feature(enable = $feature)]
+                pub unsafe fn left(
+                    (open, extend): (i32, i32),
+                    columns: &Range<usize>,
+                    row: &mut ScoreRow,
+                    codes: &[i32],
+                    trace: &mut [Trace],
+                ) -> usize {
+                    let unreachable = $set1(NEG_INF);
+                    let open = $set1(open);
+                    // Extending a gap over the lanes up to the whole vector.
+                    // Only used if the row has a whole vector, which bounds the penalties.
+                    let mut extensions = [0; $lanes];
+                    for (lanes, extension) in extensions.iter_mut().enumerate() {
+                        *extension = extend.saturating_mul(lanes as i32 + 1);
+                    }
+                    let extensions = load(&extensions, 0);
+                    let mut carry = $set1(row.left[columns.start - 1]);
+                    let mut i = columns.start;
+                    while i + $lanes <= columns.end {
+                        let diagonal = $add(load(&row.diagonal, i - 1), open);
+                        let up = $add(load(&row.up, i - 1), open);
+                        // The best gap opened after each cell and extended up to each lane
+                        let mut left = max(diagonal, up);
+                        let mut lanes: i32 = 1;
+                        while lanes < $lanes {
+                            let extension = $set1(extend.saturating_mul(lanes));
+                            left = max(left, $add(shift(left, lanes, unreachable), extension));
+                            lanes *= 2;
+                        }
+                        // The gap coming from the previous vector
+                        let left = max(left, $add(carry, extensions));
+                        store(&mut row.left, i, left);
+                        let previous = $add(shift(left, 1, carry), $set1(extend));
+                        let (_, dirs) = best(diagonal, up, previous);
+                        store_trace(trace, i, $or(load(codes, i), $slli::<6>(dirs)));
+                        carry = broadcast_last(left);
+                        i += $lanes;
+                    }
+                    i
+                }
+            }
+        };
+    }
+
+    kernel!(
+        avx2,
+        "avx2",
+        __m256i,
+        8,
+        _mm256_set1_epi32,
+        _mm256_loadu_si256,
+        _mm256_storeu_si256,
+        _mm256_add_epi32,
+        _mm256_cmpgt_epi32,
+        _mm256_cmpeq_epi32,
+        _mm256_and_si256,
+        _mm256_andnot_si256,
+        _mm256_or_si256,
+        _mm256_slli_epi32,
+        {
+            /// Returns `x` moved up by `lanes` lanes, with the lanes of `fill` below them.
+            #[inline]
+            #[target_feature(enable = "avx2")]
+            unsafe fn shift(x: __m256i, lanes: i32, fill: __m256i) -> __m256i {
+                let index = _mm256_setr_epi32(0, 1, 2, 3, 4, 5, 6, 7);
+                let moved = _mm256_permutevar8x32_epi32(
+                    x,
+                    _mm256_sub_epi32(index, _mm256_set1_epi32(lanes)),
+                );
+                select(
+                    _mm256_cmpgt_epi32(_mm256_set1_epi32(lanes), index),
+                    moved,
+                    fill,
+                )
+            }
+
+            /// Returns the last lane of `x` in all the lanes.
+            #[inline]
+            #[target_feature(enable = "avx2")]
+            unsafe fn broadcast_last(x: __m256i) -> __m256i {
+                _mm256_permutevar8x32_epi32(x, _mm256_set1_epi32(7))
+            }
+
+            /// Stores the traces packed in the lanes of `bits` into `trace` at `i`.
+            #[inline]
+            #[target_feature(enable = "avx2")]
+            unsafe fn store_trace(trace: &mut [Trace], i: usize, bits: __m256i) {
+                // Packing works within the halves, gather the two halves in the lower one
+                let packed = _mm256_permute4x64_epi64::<0b1000>(_mm256_packs_epi32(bits, bits));
+                _mm_storeu_si128(
+                    trace.as_mut_ptr().add(i) as *mut __m128i,
+                    _mm256_castsi256_si128(packed),
+                )
+            }
+        }
+    );
+
+    kernel!(
+        sse2,
+        "sse2",
+        __m128i,
+        4,
+        _mm_set1_epi32,
+        _mm_loadu_si128,
+        _mm_storeu_si128,
+        _mm_add_epi32,
+        _mm_cmpgt_epi32,
+        _mm_cmpeq_epi32,
+        _mm_and_si128,
+        _mm_andnot_si128,
+        _mm_or_si128,
+        _mm_slli_epi32,
+        {
+            /// Returns `x` moved up by `lanes` lanes, with the lanes of `fill` below them.
+            ///
+            /// `fill` should have the same value in all the lanes.
+            #[inline]
+            #[target_feature(enable = "sse2")]
+            unsafe fn shift(x: __m128i, lanes: i32, fill: __m128i) -> __m128i {
+                match lanes {
+                    1 => _mm_or_si128(_mm_slli_si128::<4>(x), _mm_srli_si128::<12>(fill)),
+                    2 => _mm_or_si128(_mm_slli_si128::<8>(x), _mm_srli_si128::<8>(fill)),
+                    _ => unreachable!("a vector has four lanes"),
+                }
+            }
+
+            /// Returns the last lane of `x` in all the lanes.
+            #[inline]
+            #[target_feature(enable = "sse2")]
+            unsafe fn broadcast_last(x: __m128i) -> __m128i {
+                _mm_shuffle_epi32::<0xFF>(x)
+            }
+
+            /// Stores the traces packed in the lanes of `bits` into `trace` at `i`.
+            #[inline]
+            #[target_feature(enable = "sse2")]
+            unsafe fn store_trace(trace: &mut [Trace], i: usize, bits: __m128i) {
+                _mm_storel_epi64(
+                    trace.as_mut_ptr().add(i) as *mut __m128i,
+                    _mm_packs_epi32(bits, bits),
+                )
+            }
+        }
+    );
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::super::score::NEG_INF;
+    use super::*;
+
+    /// Rows of pseudo-random scores with many ties and unreachable cells.
+    fn random_row(state: &mut u32, width: usize) -> Vec<i32> {
+        (0..width)
+            .map(|_| {
+                *state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                match (*state >> 16) % 8 {
+                    0 => NEG_INF,
+                    value => value as i32 - 4,
+                }
+            })
+            .collect()
+    }
+
+    fn unreachable_row(width: usize) -> ScoreRow {
+        let mut row = ScoreRow::default();
+        row.resize(width);
+        row
+    }
+
+    #[test]
+    fn test_kernels() {
+        let mut state = 7;
+        for &width in [1, 2, 5, 9, 17, 40].iter() {
+            for &local in [false, true].iter() {
+                let above = ScoreRow {
+                    diagonal: random_row(&mut state, width),
+                    up: random_row(&mut state, width),
+                    left: random_row(&mut state, width),
+                };
+                let substitution = random_row(&mut state, width);
+                let columns = 1..width;
+                let mut expected = unreachable_row(width);
+                let mut expected_codes = vec![0; width];
+                scalar_diagonal_and_up(
+                    &above,
+                    &substitution,
+                    (-3, -1),
+                    local,
+                    columns.clone(),
+                    &mut expected,
+                    &mut expected_codes,
+                );
+                let mut row = unreachable_row(width);
+                let mut codes = vec![0; width];
+                diagonal_and_up(
+                    &above,
+                    &substitution,
+                    (-3, -1),
+                    local,
+                    columns.clone(),
+                    &mut row,
+                    &mut codes,
+                );
+                assert_eq!(row.diagonal, expected.diagonal);
+                assert_eq!(row.up, expected.up);
+                assert_eq!(codes, expected_codes);
+                #[cfg(target_arch = "x86_64")]
+                {
+                    let mut row = unreachable_row(width);
+                    let mut codes = vec![0; width];
+                    let computed = unsafe {
+                        x86::sse2::diagonal_and_up(
+                            &above,
+                            &substitution,
+                            (-3, -1),
+                            local,
+                            &columns,
+                            &mut row,
+                            &mut codes,
+                        )
+                    };
+                    scalar_diagonal_and_up(
+                        &above,
+                        &substitution,
+                        (-3, -1),
+                        local,
+                        computed..width,
+                        &mut row,
+                        &mut codes,
+                    );
+                    assert_eq!(row.diagonal, expected.diagonal);
+                    assert_eq!(row.up, expected.up);
+                    assert_eq!(codes, expected_codes);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_left_kernels() {
+        let mut state = 11;
+        for &width in [1, 2, 5, 9, 17, 40].iter() {
+            for &penalties in [(-3, -1), (0, 0), (-1, -2)].iter() {
+                let mut start = ScoreRow {
+                    diagonal: random_row(&mut state, width),
+                    up: random_row(&mut state, width),
+                    left: random_row(&mut state, width),
+                };
+                // Only the cell before the columns has a left matrix
+                start.left[1..].iter_mut().for_each(|left| *left = NEG_INF);
+                let codes: Vec<_> = (0..width as i32).map(|i| i * 7 % 64).collect();
+                let columns = 1..width;
+                let mut expected = start.clone();
+                let mut expected_trace = vec![Trace::default(); width];
+                scalar_left(
+                    penalties,
+                    columns.clone(),
+                    &mut expected,
+                    &codes,
+                    &mut expected_trace,
+                );
+                let mut row = start.clone();
+                let mut trace = vec![Trace::default(); width];
+                left(penalties, columns.clone(), &mut row, &codes, &mut trace);
+                assert_eq!(row.left, expected.left);
+                assert_eq!(trace, expected_trace);
+                #[cfg(target_arch = "x86_64")]
+                {
+                    let mut row = start.clone();
+                    let mut trace = vec![Trace::default(); width];
+                    let computed = unsafe {
+                        x86::sse2::left(penalties, &columns, &mut row, &codes, &mut trace)
+                    };
+                    scalar_left(penalties, computed..width, &mut row, &codes, &mut trace);
+                    assert_eq!(row.left, expected.left);
+                    assert_eq!(trace, expected_trace);
+                }
+            }
+        }
+    }
+}
@@ -4,6 +4,7 @@ use core::ops::Index;
 use core::ops::IndexMut;
 
 /// Two dimensional table
+#[derive(Default)]
 pub struct Table<T> {
     /// Width of the table.
     pub width: usize,
@@ -25,7 +26,7 @@ impl<T> Index<[usize; 2]> for Table<T> {
         if x < self.width && y < self.height {
             &self.data[self.width * y + x]
         } else {
-            panic!(format!("{:?} is not a valid index", [x, y]))
+            panic!("{:?} is not a valid index", [x, y])
         }
     }
 }
@@ -41,28 +42,37 @@ impl<T> IndexMut<[usize; 2]> for Table<T> {
         if x < self.width && y < self.height {
             &mut self.data[self.width * y + x]
         } else {
-            panic!(format!("{:?} is not a valid index", [x, y]))
+            panic!("{:?} is not a valid index", [x, y])
         }
     }
 }
 
 impl<T> Table<T> {
-    /// Allocates an empty table with `width` and `height`.
+    /// Returns the row `y` as a mutable slice.
     ///
-    /// The data inside is not initialized.
-    pub fn new(width: usize, height: usize) -> Self {
-        Table {
-            width,
-            height,
-            data: Vec::with_capacity(width * height),
+    /// # Panics
+    /// Panics if `y >= self.height`.
+    ///
+    /// Panics if the `self` is not initialized
+    pub fn row_mut(&mut self, y: usize) -> &mut [T] {
+        if y < self.height {
+            &mut self.data[self.width * y..self.width * (y + 1)]
+        } else {
+            panic!("{} is not a valid row", y)
         }
     }
 }
 
 impl<T: Default> Table<T> {
-    /// Initializes `self` with the default values of `T`.
-    pub fn fill_default(&mut self) {
-        self.data.resize_with(self.width * self.height, T::default);
+    /// Changes the dimensions of `self` to `width` and `height`, keeping the allocated data.
+    ///
+    /// The values are left over from the previous dimensions, or default if there weren't enough.
+    pub fn reshape(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        if self.data.len() < width * height {
+            self.data.resize_with(width * height, T::default);
+        }
     }
 }
 
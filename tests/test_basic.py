@@ -1,3 +1,7 @@
+import math
+
+import pytest
+
 from itaxotools import calculate_distances as calc
 
 
@@ -10,3 +14,201 @@ def test_basic():
     assert calc.seq_distances_p_gaps(x, y) - 0.125 <= precision
     assert calc.seq_distances_jukes_cantor(x, y) - 0.070 <= precision
     assert calc.seq_distances_kimura2p(x, y) - 0.072 <= precision
+
+
+def test_alignment():
+    aligner = calc.make_aligner(1, -1, -2, -1, -10, -5)
+    alignment = calc.make_alignment(aligner, "ttcctcgt", "cattctcgt")
+
+    assert alignment.target == "-ttcctcgt"
+    assert alignment.query == "cattctcgt"
+    assert alignment.score == 2
+    assert alignment.gap_opens == 1
+    assert (alignment.target_start, alignment.target_end) == (0, 8)
+    assert (alignment.query_start, alignment.query_end) == (1, 9)
+    assert alignment.cigar == "1I1X1=1X5="
+    assert alignment.edits[:2] == [("I", 0, 0, 1), ("X", 0, 1, 1)]
+    local = calc.make_aligner(1, -1, -2, -1, -10, -5, mode="local")
+    alignment = calc.make_alignment(local, "GGACGTA", "TACGTCC")
+    assert alignment.cigar == "1S4=2S"
+    assert alignment.target_start == 2
+    assert alignment.edits == [("=", 2, 1, 4)]
+
+
+def test_substitution_matrix():
+    matrix = calc.make_substitution_matrix({"AA": 2, "AG": -1, "GG": 2})
+    aligner = calc.make_aligner(1, -1, -2, -1, -10, -5, substitution_matrix=matrix)
+
+    assert calc.make_alignment(aligner, "AGA", "AAA").score == 3
+    nuc = calc.substitution_matrix("NUC.4.4")
+    aligner = calc.make_aligner(1, -1, -2, -1, -10, -5, substitution_matrix=nuc)
+    assert calc.make_alignment(aligner, "ARC", "AGC").score == 11
+
+
+def test_local_alignment():
+    aligner = calc.make_aligner(1, -1, -2, -1, -10, -5, mode="local")
+    alignment = calc.make_alignment(aligner, "ttttacgtgggg", "ccacgtcc")
+
+    assert alignment.score == 4
+    assert (alignment.target_start, alignment.target_end) == (4, 8)
+    assert (alignment.query_start, alignment.query_end) == (2, 6)
+
+
+def test_co_optimal_alignments():
+    aligner = calc.make_aligner(1, -2, -1, -1, -1, -1, tie_policy="rightmost")
+    alignments = calc.co_optimal_alignments(aligner, "ACT", "AGT")
+
+    assert calc.count_alignments(aligner, "ACT", "AGT") == len(alignments) == 3
+    assert alignments[0].query == calc.make_alignment(aligner, "ACT", "AGT").query == "A-GT"
+
+
+def test_orientation():
+    target = "ggatccttagcaacgttagccat"
+    query = "atggctaacgttgctaagg"
+    aligner = calc.make_aligner(1, -1, -2, -1, -10, -5, detect_orientation=True)
+
+    distances, reversed = calc.seq_distances_oriented(aligner, target, query)
+    assert reversed and distances[0] == 0.0
+    alignment = calc.make_alignment(aligner, target, query)
+    assert alignment.reversed and alignment.query == "----ccttagcaacgttagccat"
+
+
+def test_distance_selection():
+    x = "agggtcgttaggtcagtcgt"
+    y = "----tcg-taagtcagtcgt"
+
+    assert calc.seq_distances_aligned(x, y) == calc.seq_distances_aligned(
+        x, y, distances=["p", "jukes_cantor", "kimura2p", "p_gaps"]
+    )
+    k2p, k2p_gamma = calc.seq_distances_aligned(
+        x, y, distances=["kimura2p", "kimura2p_gamma"], gamma_shape=0.5
+    )
+    assert k2p_gamma > k2p
+    (paralinear,) = calc.seq_distances_aligned(x, x, distances=["paralinear"])
+    assert abs(paralinear) < 1e-12
+
+
+def test_site_counts():
+    counts = calc.seq_site_counts_aligned("--ACGTTA-CN", "AAACATCAAGA")
+    assert dict(zip(calc.SITE_COUNTS, counts)) == {
+        "total_length": 8,
+        "common_length": 7,
+        "gap_length": 1,
+        "transitions": 2,
+        "transversions": 1,
+    }
+    assert all(isinstance(count, int) for count in counts)
+    options = calc.make_distance_options(ambiguity="average")
+    counts = calc.seq_site_counts_aligned("ACGR", "ACGA", options=options)
+    assert counts == [4.0, 4.0, 0.0, 0.5, 0.0]
+
+
+def test_standard_errors():
+    x = "AAAAGGGCCTAAGAAGCATT"
+    y = "AAGAGGGCTTAAGCAGCATA"
+    distances, errors = calc.seq_distances_aligned_with_errors(x, y)
+    assert distances == calc.seq_distances_aligned(x, y)
+    assert abs(errors[0] - math.sqrt(0.2 * 0.8 / 20)) < 1e-12
+    assert all(error > 0 for error in errors)
+
+
+def test_ambiguity():
+    x = "ACGTACGTAGCTACGTAGCR"
+    y = "ACGTACGTAGCTACGTAGCA"
+    assert calc.seq_distances_aligned(x, y, distances=["p"]) == [0.0]
+    options = calc.make_distance_options(ambiguity="average")
+    assert calc.seq_distances_aligned(x, y, distances=["p"], options=options) == [0.025]
+    # Symbols that are not IUPAC codes are missing data, like "?"
+    assert calc.seq_distances_aligned("AXGTA", "AXGTC", distances=["p"]) == [0.25]
+    assert calc.seq_distances_aligned("A??TA", "A??TC", distances=["p"]) == [1 / 3]
+
+
+def test_distance_options():
+    x = "ACG--T"
+    y = "ACGCCT"
+    options = calc.make_distance_options(gaps="fifth_state", indel_events=True)
+    assert calc.seq_distances_aligned(x, y, distances=["p"], options=options) == [0.2]
+    assert calc.seq_distances_p(x, y, options=options) == 0.2
+    for distance in ["kimura2p", "tamura_nei", "hky85", "logdet", "gtr", "ml_jukes_cantor"]:
+        with pytest.raises(ValueError):
+            calc.seq_distances_aligned(x, y, distances=[distance], options=options)
+    with pytest.raises(ValueError):
+        calc.seq_distances_kimura2p(x, y, options=options)
+    # Gaps are differences for the p-distances, but not transversions
+    fifth_state = calc.make_distance_options(gaps="fifth_state")
+    x = "ACGT--ACGT"
+    y = "ACGTACACGT"
+    counts = calc.seq_site_counts_aligned(x, y, options=fifth_state)
+    assert dict(zip(calc.SITE_COUNTS, counts)) == {
+        "total_length": 10,
+        "common_length": 10,
+        "gap_length": 2,
+        "transitions": 0,
+        "transversions": 0,
+    }
+    assert calc.seq_distances_aligned(x, y, distances=["p"], options=fifth_state) == [0.2]
+    assert calc.seq_distances_aligned(x, y, distances=["kimura2p"]) == [0.0]
+
+
+def test_nucleotide_models():
+    x = "AAAAGGGCCTAAGAAGCATT"
+    y = "AAGAGGGCTTAAGCAGCATA"
+    models = ["f81", "hky85", "gtr"]
+    assert calc.seq_distances_aligned(x, x, distances=models) == [0.0, 0.0, 0.0]
+    options = calc.make_distance_options(frequencies="whole_set")
+    distances = calc.seq_distances_aligned(x, y, distances=models, options=options)
+    assert all(0.2 < distance < 1.0 for distance in distances)
+
+
+def test_maximum_likelihood():
+    x = "ACGTACGT"
+    y = "TGCATGCA"
+    assert calc.seq_distances_aligned(x, y, distances=["jukes_cantor"]) == [math.inf]
+    (distance,) = calc.seq_distances_aligned(
+        x, y, distances=["ml_jukes_cantor"], max_distance=5.0
+    )
+    assert abs(distance - 5.0) < 1e-6
+
+
+def test_protein_distances():
+    x = "MKV-LAX"
+    y = "MKVWIAA"
+    assert calc.seq_distances_protein_aligned(x, y, distances=["p", "p_gaps"]) == [0.2, 2 / 6]
+    (poisson, jtt) = calc.seq_distances_protein_aligned(x, y, distances=["poisson", "jtt"])
+    assert abs(poisson + math.log(0.8)) < 1e-12
+    assert 0.0 < jtt < 10.0
+
+
+def test_codon_distances():
+    assert calc.translate("ATGGCCTGAAGA") == "MA*R"
+    assert calc.translate("ATGGCCTGAAGA", genetic_code=5) == "MAWS"
+    assert calc.stop_codons("CATGTAAGG", frame=1) == [4]
+    x = "CTTCTTAAA"
+    y = "CTCATTAAG"
+    options = calc.make_distance_options(frame=0)
+    aligner = calc.make_aligner(1, -1, -2, -1, -10, -5)
+    with pytest.raises(ValueError):
+        calc.seq_distances_aligned(x, y, distances=["ds"])
+    with pytest.raises(ValueError):
+        calc.seq_distances(aligner, x, y, distances=["dn"], options=options)
+    ds, dn, ratio = calc.seq_distances_aligned(
+        x, y, distances=["ds", "dn", "dn_ds"], options=options
+    )
+    assert ds > dn > 0.0
+    assert abs(ratio - dn / ds) < 1e-12
+
+
+def test_codon_positions():
+    x = "ACGACGACG"
+    y = "ACTACGTCG"
+    third = calc.make_distance_options(frame=0, codon_positions=[3])
+    first_second = calc.make_distance_options(frame=0, codon_positions=[1, 2])
+    assert calc.seq_distances_p(x, y, options=third) == 1 / 3
+    assert calc.seq_distances_p(x, y, options=first_second) == 1 / 6
+    aligner = calc.make_aligner(1, -1, -2, -1, -10, -5)
+    with pytest.raises(ValueError):
+        calc.make_distance_options(frame=0, codon_positions=[])
+    with pytest.raises(ValueError):
+        calc.seq_distances(aligner, x, y, distances=["p"], options=third)
+    with pytest.raises(ValueError):
+        calc.seq_distances_protein_aligned(x, y, options=third)